@@ -0,0 +1,514 @@
+use hyper::{http::StatusCode, Body, Method, Request, Response};
+use serde::Serialize;
+use snafu::OptionExt;
+
+use crate::{
+	delivery,
+	error::*,
+	features::{self, Feature},
+	storage::{Range, Storage},
+	webhook::{
+		checks_and_status, handle_error, handle_event, AppState, MergeRequest,
+	},
+	Result,
+};
+
+/// Deliveries listed on the status page.
+const STATUS_PAGE_DELIVERIES: usize = 20;
+
+pub(crate) fn json_response(
+	status: StatusCode,
+	body: serde_json::Value,
+) -> Result<Response<Body>> {
+	Response::builder()
+		.status(status)
+		.header("content-type", "application/json")
+		.body(Body::from(body.to_string()))
+		.ok()
+		.context(Message {
+			msg: format!("Error building response"),
+		})
+}
+
+pub(crate) fn not_found() -> Result<Response<Body>> {
+	Response::builder()
+		.status(StatusCode::NOT_FOUND)
+		.body(Body::from("Not found."))
+		.ok()
+		.context(Message {
+			msg: format!("Error building response"),
+		})
+}
+
+/// Returns the admin token a request presents. It is taken as a bearer token, or as the password
+/// of basic authentication so that the status page can be opened in a browser. Browsers send basic
+/// credentials with cross-site requests too, so they only count for safe methods; anything that
+/// changes state needs the bearer token, which a page on another site cannot set.
+fn presented_token(req: &Request<Body>) -> Option<String> {
+	let authorization = req
+		.headers()
+		.get(hyper::header::AUTHORIZATION)
+		.and_then(|v| v.to_str().ok())
+		.unwrap_or("");
+	if let Some(bearer) = authorization.strip_prefix("Bearer ") {
+		Some(bearer.to_owned())
+	} else if let Some(basic) = authorization.strip_prefix("Basic ") {
+		if !(req.method() == Method::GET || req.method() == Method::HEAD) {
+			return None;
+		}
+		base64::decode(basic)
+			.ok()
+			.and_then(|credentials| String::from_utf8(credentials).ok())
+			.and_then(|credentials| {
+				credentials
+					.splitn(2, ':')
+					.nth(1)
+					.map(|password| password.to_owned())
+			})
+	} else {
+		None
+	}
+}
+
+/// Returns the response to send instead if the request may not use the admin endpoints, which are
+/// disabled without `ADMIN_TOKEN`; see `presented_token`.
+pub(crate) fn admin_denied(
+	req: &Request<Body>,
+	state: &AppState,
+) -> Option<Result<Response<Body>>> {
+	let token = match &state.admin_token {
+		Some(token) => token,
+		None => return Some(not_found()),
+	};
+	let given = presented_token(req);
+	let authorized = given.map_or(false, |given| {
+		ring::constant_time::verify_slices_are_equal(
+			given.as_bytes(),
+			token.as_bytes(),
+		)
+		.is_ok()
+	});
+	if authorized {
+		None
+	} else {
+		Some(
+			Response::builder()
+				.status(StatusCode::UNAUTHORIZED)
+				.header("www-authenticate", "Basic realm=\"processbot\"")
+				.body(Body::from("Invalid admin token."))
+				.ok()
+				.context(Message {
+					msg: format!("Error building response"),
+				}),
+		)
+	}
+}
+
+/// A merge request waiting for checks, and the head SHA it was requested for.
+#[derive(Debug, Serialize)]
+pub struct PendingMerge {
+	pub sha: String,
+	#[serde(flatten)]
+	pub merge_request: MergeRequest,
+}
+
+/// Returns every merge request waiting for checks.
+pub fn pending_merges(storage: &Storage) -> Result<Vec<PendingMerge>> {
+	Ok(storage
+		.scan::<MergeRequest>(Range::All)?
+		.into_iter()
+		.map(|(sha, merge_request)| PendingMerge { sha, merge_request })
+		.collect())
+}
+
+fn pending_merge(storage: &Storage, sha: &str) -> Result<Option<PendingMerge>> {
+	Ok(storage
+		.get::<MergeRequest>(sha)?
+		.map(|merge_request| PendingMerge {
+			sha: sha.to_owned(),
+			merge_request,
+		}))
+}
+
+/// Handles a request to the admin endpoints, once `admin_denied` allowed it.
+pub async fn admin(
+	req: &Request<Body>,
+	path: &[&str],
+	state: &AppState,
+) -> Result<Response<Body>> {
+	let method = req.method();
+	match path {
+		["admin"] if method == Method::GET => status_page(state),
+		["deliveries"] if method == Method::GET => {
+			let limit = req
+				.uri()
+				.query()
+				.unwrap_or("")
+				.split('&')
+				.find_map(|param| param.strip_prefix("limit="))
+				.and_then(|limit| limit.parse::<usize>().ok())
+				.unwrap_or(50);
+			let deliveries = delivery::recent_deliveries(&state.db, limit)?
+				.iter()
+				.map(delivery::Delivery::summary)
+				.collect::<Vec<delivery::Delivery>>();
+			json_response(StatusCode::OK, serde_json::json!(deliveries))
+		}
+		["deliveries", delivery_id, "replay"] if method == Method::POST => {
+			replay_delivery(delivery_id, state).await
+		}
+		["merges"] if method == Method::GET => json_response(
+			StatusCode::OK,
+			serde_json::json!(pending_merges(&state.db)?),
+		),
+		["merges", sha] if method == Method::GET => {
+			merge_details(sha, state).await
+		}
+		["merges", sha] if method == Method::DELETE => {
+			cancel_merge(sha, state).await
+		}
+		["merges", sha, "evaluate"] if method == Method::POST => {
+			evaluate_merge(sha, state).await
+		}
+		["features"] if method == Method::GET => json_response(
+			StatusCode::OK,
+			serde_json::json!({
+				"features": Feature::ALL,
+				"toggles": features::toggles(&state.db)?,
+			}),
+		),
+		["features", owner, feature, action] if method == Method::POST => {
+			match toggle_action(action) {
+				Some(enabled) => set_feature(owner, feature, enabled, state),
+				None => not_found(),
+			}
+		}
+		["features", owner, repo_name, feature, action]
+			if method == Method::POST =>
+		{
+			match toggle_action(action) {
+				Some(enabled) => set_feature(
+					&format!("{}/{}", owner, repo_name),
+					feature,
+					enabled,
+					state,
+				),
+				None => not_found(),
+			}
+		}
+		["features", owner, feature] if method == Method::DELETE => {
+			set_feature(owner, feature, None, state)
+		}
+		["features", owner, repo_name, feature] if method == Method::DELETE => {
+			set_feature(
+				&format!("{}/{}", owner, repo_name),
+				feature,
+				None,
+				state,
+			)
+		}
+		_ => not_found(),
+	}
+}
+
+async fn replay_delivery(
+	delivery_id: &str,
+	state: &AppState,
+) -> Result<Response<Body>> {
+	let delivery = match delivery::find_delivery(&state.db, delivery_id)? {
+		Some(delivery) => delivery,
+		None => return not_found(),
+	};
+	let body = match &delivery.body {
+		Some(body) => body,
+		None => {
+			return json_response(
+				StatusCode::BAD_REQUEST,
+				serde_json::json!({
+					"error": "Only deliveries with a valid signature can be replayed"
+				}),
+			)
+		}
+	};
+	log::info!("Replaying delivery {}", delivery.id);
+	let (outcome, error) =
+		match handle_event(&delivery.event, body.as_bytes(), state).await {
+			Ok(outcome) => (outcome, None),
+			Err(e) => (delivery::Outcome::Failed, Some(e.to_string())),
+		};
+	json_response(
+		StatusCode::OK,
+		serde_json::json!({ "outcome": outcome, "error": error }),
+	)
+}
+
+/// A pending merge with the current state of its pull request.
+async fn merge_details(sha: &str, state: &AppState) -> Result<Response<Body>> {
+	let pending = match pending_merge(&state.db, sha)? {
+		Some(pending) => pending,
+		None => return not_found(),
+	};
+	let MergeRequest {
+		owner,
+		repo_name,
+		number,
+		..
+	} = &pending.merge_request;
+	let github_bot = state.github_bot.for_account(owner).await?;
+	let pr = github_bot.pull_request(owner, repo_name, *number).await?;
+	let status = github_bot.status(owner, repo_name, sha).await?;
+	let checks = github_bot.check_runs(owner, repo_name, sha).await?;
+	json_response(
+		StatusCode::OK,
+		serde_json::json!({
+			"merge": pending,
+			"head_sha": pr.head.sha,
+			// a push since the merge was requested cancels it on the next evaluation
+			"head_changed": pr.head.sha != sha,
+			"state": pr.state,
+			"mergeable": pr.mergeable,
+			"status": status.state,
+			"checks": checks
+				.check_runs
+				.iter()
+				.map(|check| serde_json::json!({
+					"status": check.status,
+					"conclusion": check.conclusion,
+				}))
+				.collect::<Vec<serde_json::Value>>(),
+		}),
+	)
+}
+
+async fn cancel_merge(sha: &str, state: &AppState) -> Result<Response<Body>> {
+	let pending = match pending_merge(&state.db, sha)? {
+		Some(pending) => pending,
+		None => return not_found(),
+	};
+	let MergeRequest {
+		owner,
+		repo_name,
+		number,
+		html_url,
+		..
+	} = &pending.merge_request;
+	log::info!("Merge of {} cancelled from the admin API", html_url);
+	state.db.delete::<MergeRequest>(sha)?;
+	let github_bot = state.github_bot.for_account(owner).await?;
+	let _ = github_bot
+		.create_issue_comment(
+			owner,
+			repo_name,
+			*number,
+			"Merge cancelled by an administrator.",
+		)
+		.await
+		.map_err(|e| {
+			log::error!("Error posting comment: {}", e);
+		});
+	json_response(StatusCode::OK, serde_json::json!({ "cancelled": pending }))
+}
+
+/// Checks a pending merge as if its checks had just completed, merging it if they are green.
+async fn evaluate_merge(sha: &str, state: &AppState) -> Result<Response<Body>> {
+	let pending = match pending_merge(&state.db, sha)? {
+		Some(pending) => pending,
+		None => return not_found(),
+	};
+	log::info!(
+		"Re-evaluating merge of {} from the admin API",
+		pending.merge_request.html_url
+	);
+	let github_bot = state
+		.github_bot
+		.for_account(&pending.merge_request.owner)
+		.await?;
+	let error =
+		match checks_and_status(&github_bot, &state.bot_config, sha, &state.db)
+			.await
+		{
+			Ok(()) => None,
+			Err(e) => {
+				let message = e.to_string();
+				handle_error(e, state).await;
+				Some(message)
+			}
+		};
+	json_response(
+		StatusCode::OK,
+		serde_json::json!({
+			"pending": pending_merge(&state.db, sha)?.is_some(),
+			"error": error,
+		}),
+	)
+}
+
+/// Whether the action of a feature endpoint turns the feature on or off.
+fn toggle_action(action: &str) -> Option<Option<bool>> {
+	match action {
+		"enable" => Some(Some(true)),
+		"disable" => Some(Some(false)),
+		_ => None,
+	}
+}
+
+/// Turns a feature on or off in an organization or repository, or resets it if `enabled` is
+/// `None`.
+fn set_feature(
+	scope: &str,
+	feature: &str,
+	enabled: Option<bool>,
+	state: &AppState,
+) -> Result<Response<Body>> {
+	let feature = match feature.parse::<Feature>() {
+		Ok(feature) => feature,
+		Err(e) => {
+			return json_response(
+				StatusCode::BAD_REQUEST,
+				serde_json::json!({ "error": e }),
+			)
+		}
+	};
+	match enabled {
+		Some(true) => log::info!("Enabling {} in {}", feature, scope),
+		Some(false) => log::info!("Disabling {} in {}", feature, scope),
+		None => log::info!("Resetting {} in {}", feature, scope),
+	}
+	features::set_toggle(&state.db, scope, feature, enabled)?;
+	json_response(
+		StatusCode::OK,
+		serde_json::json!({
+			"scope": scope,
+			"feature": feature,
+			"enabled": enabled,
+		}),
+	)
+}
+
+fn escape_html(s: &str) -> String {
+	s.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+}
+
+/// A page listing pending merges and recent webhook deliveries.
+fn status_page(state: &AppState) -> Result<Response<Body>> {
+	let merges = pending_merges(&state.db)?
+		.iter()
+		.map(|pending| {
+			format!(
+				"<tr><td><a href=\"{url}\">{url}</a></td><td>{requested_by}</td><td><code>{sha}</code></td></tr>",
+				url = escape_html(&pending.merge_request.html_url),
+				requested_by = escape_html(&pending.merge_request.requested_by),
+				sha = escape_html(&pending.sha),
+			)
+		})
+		.collect::<String>();
+	let deliveries =
+		delivery::recent_deliveries(&state.db, STATUS_PAGE_DELIVERIES)?
+			.iter()
+			.map(|delivery| {
+				format!(
+					"<tr><td>{received_at}</td><td><code>{id}</code></td><td>{event}</td><td>{outcome:?}</td><td>{error}</td></tr>",
+					received_at = delivery.received_at.format("%Y-%m-%d %H:%M:%S"),
+					id = escape_html(&delivery.id),
+					event = escape_html(&delivery.event),
+					outcome = delivery.outcome,
+					error = escape_html(delivery.error.as_deref().unwrap_or("")),
+				)
+			})
+			.collect::<String>();
+	let page = format!(
+		r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>Processbot</title></head>
+<body>
+<h1>Processbot</h1>
+<h2>Pending merges</h2>
+<table>
+<tr><th>Pull request</th><th>Requested by</th><th>Head</th></tr>
+{merges}
+</table>
+<p>{deferred} pull request(s) deferred until the rate limit resets.</p>
+<h2>Recent deliveries</h2>
+<table>
+<tr><th>Received</th><th>ID</th><th>Event</th><th>Outcome</th><th>Error</th></tr>
+{deliveries}
+</table>
+</body>
+</html>
+"#,
+		merges = merges,
+		deferred = state.deferred.lock().len(),
+		deliveries = deliveries,
+	);
+	Response::builder()
+		.status(StatusCode::OK)
+		.header("content-type", "text/html; charset=utf-8")
+		.body(Body::from(page))
+		.ok()
+		.context(Message {
+			msg: format!("Error building response"),
+		})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_pending_merges() {
+		let storage = Storage::temporary().expect("storage");
+		let merge_request = MergeRequest {
+			owner: "paritytech".to_owned(),
+			repo_name: "substrate".to_owned(),
+			number: 1234,
+			html_url: "https://github.com/paritytech/substrate/pull/1234"
+				.to_owned(),
+			requested_by: "sjeohp".to_owned(),
+			requested_at: chrono::Utc::now(),
+		};
+		let sha = "5c9a1f2e26e7fd5b8a4d7c3f1b0d4e6a8c2b7f10";
+		storage.put(sha, &merge_request).expect("put");
+
+		let pending = pending_merges(&storage).expect("pending merges");
+		assert_eq!(pending.len(), 1);
+		assert_eq!(pending[0].sha, sha);
+		assert_eq!(pending[0].merge_request.number, 1234);
+		assert!(pending_merge(&storage, sha).expect("get").is_some());
+		assert!(pending_merge(&storage, "5c9a1f2e").expect("get").is_none());
+	}
+
+	#[test]
+	fn test_presented_token() {
+		let request = |method: Method, authorization: &str| {
+			Request::builder()
+				.method(method)
+				.header(hyper::header::AUTHORIZATION, authorization)
+				.body(Body::empty())
+				.expect("request")
+		};
+		// "admin:secret"
+		let basic = "Basic YWRtaW46c2VjcmV0";
+		assert_eq!(
+			presented_token(&request(Method::GET, basic)).as_deref(),
+			Some("secret")
+		);
+		assert_eq!(presented_token(&request(Method::POST, basic)), None);
+		assert_eq!(presented_token(&request(Method::DELETE, basic)), None);
+		assert_eq!(
+			presented_token(&request(Method::POST, "Bearer secret")).as_deref(),
+			Some("secret")
+		);
+		assert_eq!(presented_token(&request(Method::GET, "secret")), None);
+	}
+
+	#[test]
+	fn test_escape_html() {
+		assert_eq!(
+			escape_html("<script>alert(\"&\")</script>"),
+			"&lt;script&gt;alert(&quot;&amp;&quot;)&lt;/script&gt;"
+		);
+	}
+}
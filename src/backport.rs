@@ -0,0 +1,335 @@
+use snafu::ResultExt;
+use std::path::Path;
+use std::process::ExitStatus;
+use tokio::process::Command;
+
+use crate::{constants::*, error::*, github_bot::GithubBot, metrics, Result};
+
+/// Outcome of cherry-picking a merged pull request onto a release branch.
+#[derive(Debug, PartialEq)]
+pub enum Backport {
+	/// The backport branch was pushed and is ready for a pull request.
+	Pushed { branch: String },
+	/// The cherry-pick did not apply cleanly; contains the conflicting paths.
+	Conflicts { paths: Vec<String> },
+}
+
+/// Name of the branch created to hold a backport of `number` to `target`.
+pub fn backport_branch_name(number: i64, target: &str) -> String {
+	format!("backport-{}-to-{}", number, target)
+}
+
+/// Parse the target branch from a `bot backport <branch>` comment.
+pub fn backport_parse(body: &str) -> Option<&str> {
+	let body = body.trim();
+	let command = body.get(..BACKPORT_REQUEST.len())?;
+	let rest = body.get(BACKPORT_REQUEST.len()..)?;
+	if !command.eq_ignore_ascii_case(BACKPORT_REQUEST)
+		|| !rest.starts_with(char::is_whitespace)
+	{
+		return None;
+	}
+	let mut rest = rest.split_whitespace();
+	match (rest.next(), rest.next()) {
+		(Some(branch), None) => Some(branch),
+		_ => None,
+	}
+}
+
+/// Return the target branch of a `backport-to-<branch>` label.
+pub fn backport_label_target(label: &str) -> Option<&str> {
+	if label.starts_with(BACKPORT_LABEL_PREFIX)
+		&& label.len() > BACKPORT_LABEL_PREFIX.len()
+	{
+		label.get(BACKPORT_LABEL_PREFIX.len()..)
+	} else {
+		None
+	}
+}
+
+/// Directory of the worktree a backport of `number` to `target` is made in, next to the clone of
+/// `repo` so that concurrent jobs never share a working tree.
+pub fn backport_worktree(repo: &str, number: i64, target: &str) -> String {
+	format!(
+		"{}-backport-{}-to-{}",
+		repo,
+		number,
+		target.replace('/', "-")
+	)
+}
+
+/// Cherry-pick a merged pull request onto a new branch created from `target` and push it to the
+/// base repository. A merge commit is picked against its first parent; otherwise the commits of
+/// the pull request, ie. those reachable from `head_sha` but not from the parent of
+/// `merge_commit_sha`, are picked one by one, so that squashed and rebased merges both carry over
+/// the whole pull request.
+pub async fn backport(
+	github_bot: &GithubBot,
+	owner: &str,
+	repo: &str,
+	number: i64,
+	merge_commit_sha: &str,
+	head_sha: &str,
+	target: &str,
+) -> Result<Backport> {
+	let _timer = metrics::GIT_JOBS.start_timer(&["backport"]);
+	let branch = backport_branch_name(number, target);
+	let worktree = backport_worktree(repo, number, target);
+	let res = backport_inner(
+		github_bot,
+		owner,
+		repo,
+		number,
+		merge_commit_sha,
+		head_sha,
+		target,
+		&branch,
+		&worktree,
+	)
+	.await;
+	// a failed cleanup is left to the next job, which does it again, and must not hide what
+	// became of the backport
+	log::info!("Removing backport worktree.");
+	if let Err(e) = remove_worktree(repo, &worktree).await {
+		log::error!("Error removing backport worktree {}: {}", worktree, e);
+	}
+	log::info!("Deleting backport branch.");
+	if let Err(e) = delete_branch(repo, &branch).await {
+		log::error!("Error deleting backport branch {}: {}", branch, e);
+	}
+	res
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn backport_inner(
+	github_bot: &GithubBot,
+	owner: &str,
+	repo: &str,
+	number: i64,
+	merge_commit_sha: &str,
+	head_sha: &str,
+	target: &str,
+	branch: &str,
+	worktree: &str,
+) -> Result<Backport> {
+	let token = github_bot.client.auth_key().await?;
+	// clone in case the local clone doesn't exist
+	log::info!("Cloning repo.");
+	let clone = Command::new("git")
+		.arg("clone")
+		.arg("-v")
+		.arg(github_bot.remote_url(&token, owner, repo)?)
+		.spawn()
+		.context(Tokio)?
+		.await
+		.context(Tokio)?;
+	// cloning fails when the clone is already there
+	if !clone.success() && !Path::new(repo).exists() {
+		return Err(Error::Message {
+			msg: format!(
+				"Failed cloning `{}/{}` ({}); backport aborted.",
+				owner, repo, clone
+			),
+		});
+	}
+	// fetch origin for the target branch and the merged commit, and the head of the pull
+	// request, which is kept after its branch is deleted
+	log::info!("Fetching origin.");
+	git_ok(repo, &["fetch", "origin"]).await?;
+	git_ok(repo, &["fetch", "origin", &format!("pull/{}/head", number)])
+		.await?;
+	// a worktree or branch left by a job that did not finish would stop the new one being added
+	remove_worktree(repo, worktree).await?;
+	delete_branch(repo, branch).await?;
+	// add a worktree on the backport branch
+	log::info!("Adding backport worktree.");
+	let add = git(
+		repo,
+		&[
+			"worktree",
+			"add",
+			"-b",
+			branch,
+			&format!("../{}", worktree),
+			&format!("origin/{}", target),
+		],
+	)
+	.await?;
+	if !add.success() {
+		return Err(Error::Message {
+			msg: format!(
+				"Branch `{}` does not exist; backport aborted.",
+				target
+			),
+		});
+	}
+	// merge commits need a mainline to cherry-pick against
+	let is_merge_commit = git(
+		worktree,
+		&["rev-parse", "--verify", &format!("{}^2", merge_commit_sha)],
+	)
+	.await?
+	.success();
+	let cherry_pick = if is_merge_commit {
+		log::info!("Cherry-picking {}.", merge_commit_sha);
+		git(
+			worktree,
+			&["cherry-pick", "-x", "-m", "1", merge_commit_sha],
+		)
+		.await?
+	} else {
+		let commits = git_output(
+			worktree,
+			&[
+				"rev-list",
+				"--reverse",
+				"--no-merges",
+				&format!("{}^..{}", merge_commit_sha, head_sha),
+			],
+		)
+		.await?;
+		let mut args = vec!["cherry-pick", "-x"];
+		args.extend(commits.lines());
+		if args.len() == 2 {
+			return Err(Error::Message {
+				msg: format!(
+					"Found no commits of the pull request to cherry-pick onto `{}`; backport aborted.",
+					target
+				),
+			});
+		}
+		log::info!("Cherry-picking {} commits.", args.len() - 2);
+		git(worktree, &args).await?
+	};
+	if cherry_pick.success() {
+		// push
+		log::info!("Pushing backport branch.");
+		let push = git(worktree, &["push", "origin", branch]).await?;
+		if !push.success() {
+			return Err(Error::Message {
+				msg: format!("Failed pushing `{}`; backport aborted.", branch),
+			});
+		}
+		Ok(Backport::Pushed {
+			branch: branch.to_string(),
+		})
+	} else {
+		// list conflicts
+		let paths =
+			git_output(worktree, &["diff", "--name-only", "--diff-filter=U"])
+				.await?
+				.lines()
+				.map(|s| s.to_string())
+				.collect();
+		// abort cherry-pick; the worktree is removed regardless
+		log::info!("Aborting cherry-pick.");
+		if let Err(e) = git_ok(worktree, &["cherry-pick", "--abort"]).await {
+			log::warn!("{}", e);
+		}
+		Ok(Backport::Conflicts { paths })
+	}
+}
+
+/// Remove a backport worktree, whether or not it exists.
+async fn remove_worktree(repo: &str, worktree: &str) -> Result<()> {
+	if Path::new(worktree).exists() {
+		let remove = git(
+			repo,
+			&["worktree", "remove", "--force", &format!("../{}", worktree)],
+		)
+		.await?;
+		// a directory git does not know as a worktree is removed all the same
+		if !remove.success() || Path::new(worktree).exists() {
+			tokio::fs::remove_dir_all(worktree).await.context(Tokio)?;
+		}
+	}
+	git_ok(repo, &["worktree", "prune"]).await
+}
+
+/// Delete a local branch, whether or not it exists.
+async fn delete_branch(repo: &str, branch: &str) -> Result<()> {
+	let exists = git(
+		repo,
+		&[
+			"rev-parse",
+			"--verify",
+			"--quiet",
+			&format!("refs/heads/{}", branch),
+		],
+	)
+	.await?
+	.success();
+	if exists {
+		git_ok(repo, &["branch", "-D", branch]).await?;
+	}
+	Ok(())
+}
+
+async fn git(dir: &str, args: &[&str]) -> Result<ExitStatus> {
+	Command::new("git")
+		.args(args)
+		.current_dir(format!("./{}", dir))
+		.spawn()
+		.context(Tokio)?
+		.await
+		.context(Tokio)
+}
+
+/// Runs git, failing with the command and its exit status unless it succeeds.
+async fn git_ok(dir: &str, args: &[&str]) -> Result<()> {
+	let status = git(dir, args).await?;
+	if status.success() {
+		Ok(())
+	} else {
+		Err(Error::Message {
+			msg: format!(
+				"`git {}` failed ({}); backport aborted.",
+				args.join(" "),
+				status
+			),
+		})
+	}
+}
+
+async fn git_output(dir: &str, args: &[&str]) -> Result<String> {
+	let output = Command::new("git")
+		.args(args)
+		.current_dir(format!("./{}", dir))
+		.output()
+		.await
+		.context(Tokio)?;
+	String::from_utf8(output.stdout).context(Utf8)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_backport_parse() {
+		assert_eq!(backport_parse("bot backport v0.8"), Some("v0.8"));
+		assert_eq!(
+			backport_parse("  Bot Backport release/Polkadot-v0.8 \n"),
+			Some("release/Polkadot-v0.8")
+		);
+		assert_eq!(backport_parse("bot backport"), None);
+		assert_eq!(backport_parse("bot backport a b"), None);
+		assert_eq!(backport_parse("bot backportv0.8"), None);
+		assert_eq!(backport_parse("bot merge"), None);
+	}
+
+	#[test]
+	fn test_backport_label_target() {
+		assert_eq!(backport_label_target("backport-to-v0.8"), Some("v0.8"));
+		assert_eq!(backport_label_target("backport-to-"), None);
+		assert_eq!(backport_label_target("B1-backport"), None);
+	}
+
+	#[test]
+	fn test_backport_worktree() {
+		assert_eq!(
+			backport_worktree("polkadot", 1234, "release/v0.8"),
+			"polkadot-backport-1234-to-release-v0.8"
+		);
+	}
+}
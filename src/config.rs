@@ -1,266 +1,859 @@
 /*
-Processbot looks for configuration variables in `.env` in the root directory. Eg. `MATRIX_USER=annoying_bot@parity.io`.
+Processbot reads its settings from a config file, if one is given with `--config PATH` or `CONFIG_PATH`, then from environment variables, which override the file. Variables can also be set in `.env` in the working directory. Eg. `MATRIX_SILENT=true`.
+
+The config file is TOML, or YAML if its name ends in `.yml` or `.yaml`. Its keys are the names of the variables below in lower case, eg. `webhook_port = 8080`. Lists may be written as arrays, eg. `webhook_secret = ["new", "old"]`.
+
+Secrets, ie. `WEBHOOK_SECRET`, `ADMIN_TOKEN`, `MATRIX_ACCESS_TOKEN` and `GITLAB_PRIVATE_TOKEN`, can instead be read from a file named by the same variable followed by `_FILE`, eg. `MATRIX_ACCESS_TOKEN_FILE=/run/secrets/matrix`. A trailing newline is ignored.
+
+Every problem with the settings is reported at startup. `parity-processbot --print-config` prints the settings in effect as a config file, with secrets redacted, along with any problems.
 
 `PRIVATE_KEY_PATH`: Path to the private key associated with the installed Processbot app.
 
 `GITHUB_APP_ID`: App ID associated with the installed Processbot app.
 
-`DB_PATH`: Path to an existing `rocksdb` database or that path at which a database will be created.
+`INSTALLATION_LOGIN`: Optional organization or user the app is installed on, which the bot acts on behalf of for webhooks that do not name an installation. Other webhooks are handled on behalf of the installation they were delivered for, and pending merges on behalf of the installation on the owner of their repository.
+
+`GITHUB_API_URL`: Optional base URL of the Github REST API, eg. `https://github.example.com/api/v3` for Github Enterprise Server or the address of a local mock. Defaults to `https://api.github.com`.
+
+`GITHUB_URL`: Optional base URL of the Github web interface, used to clone repositories and recognise links in pull request descriptions. Defaults to `https://github.com`.
+
+`WEBHOOK_SECRET`: Secret the Github App signs webhooks with. To rotate it, list the new and old secrets separated by commas until Github uses the new one.
+
+`WEBHOOK_PORT`: Port the webhooks and admin endpoints are served on.
 
-`MAIN_TICK_SECS`: Seconds between cycles of the main bot loop.
+`ADMIN_TOKEN`: Optional token for the admin endpoints, eg. `GET /merges`, sent as a bearer token or as the password of basic authentication. They are disabled if it is not set.
 
-`BAMBOO_TOKEN`: API Key used to access the BambooHR API.
+`SHUTDOWN_TIMEOUT_SECS`: Optional seconds to wait on SIGTERM for the webhook being handled, including any git job, before exiting regardless. Defaults to 25, which fits the default termination grace period of Kubernetes.
 
-`BAMBOO_TICK_SECS`: Seconds between updating data pulled from the BambooHR API. This can take some time and is likely to change only infrequently, so the value should be larger than `MAIN_TICK_SECS`.
+`DB_PATH`: Path to an existing database directory or that path at which a database will be created.
 
-`MATRIX_SILENT`: If `true`, do not send Matrix notifications.
+`STORAGE_BACKEND`: Optional store of the database: `rocksdb`, `file` for a log file read into memory, or `memory` to keep nothing once the bot exits. Defaults to `rocksdb`, or `file` when built without the `rocksdb` feature. Records are not moved between backends.
 
-`MATRIX_HOMESERVER`: Matrix homeserver.
+`MAIN_TICK_SECS`: Optional seconds between checks of pending merge requests in case a webhook was missed. Defaults to 300.
 
-`MATRIX_ACCESS_TOKEN`: Matrix access token.
+`MATRIX_SILENT`: Optional; if `true`, do not send Matrix notifications, and the other Matrix settings are optional. Defaults to `false`.
 
-`MATRIX_DEFAULT_CHANNEL_ID`: ID of a channel the bot should use when specific project details are unavailable.
+`MATRIX_HOMESERVER`: Matrix homeserver. Required unless `MATRIX_SILENT` is `true`.
 
-`STATUS_FAILURE_PING`: Seconds between notifications that a pull request has failed checks, sent privately to the pull request author, via Matrix.
+`MATRIX_ACCESS_TOKEN`: Matrix access token. Required unless `MATRIX_SILENT` is `true`.
 
-`ISSUE_NOT_ASSIGNED_TO_PR_AUTHOR_PING`: Seconds between notifications that the issue relevant to a pull request has not been assigned to the author of the pull
-request, sent privately to the issue assignee and project owner, then publicly to the project room, via Matrix.
+`MATRIX_DEFAULT_CHANNEL_ID`: ID of a channel the bot should use when specific project details are unavailable. Required unless `MATRIX_SILENT` is `true`.
 
-`NO_PROJECT_AUTHOR_IS_CORE_PING`: Seconds between notifications that a pull request opened by a core developer has no project attached, sent privately to the
-pull request author or publicly to the default channel if the author's Matrix handle cannot be found.
+`MERGE_REQUEST_EXPIRY`: Optional seconds after which a merge request still waiting for checks is dropped, with a comment on the pull request. Defaults to a week.
 
-`NO_PROJECT_AUTHOR_IS_CORE_CLOSE_PR`: Seconds before closing a pull request opened by a core developer that has no project attached.
+`MIN_REVIEWERS`: Optional minimum number of reviewers needed before a pull request can be accepted. Defaults to 2.
 
-`NO_PROJECT_AUTHOR_UNKNOWN_CLOSE_PR`: Seconds before closing a pull request opened by an external developer that has no project attached.
+`CORE_DEVS_TEAM`: Optional slug of the team whose approvals count towards MIN_REVIEWERS. Defaults to `core-devs`.
 
-`PROJECT_CONFIRMATION_TIMEOUT`: Seconds before reverting an unconfirmed change of project by a non-whitelisted developer (currently unimplemented).
+`TEAM_LEADS_TEAM`: Optional slug of the team whose members may merge on their own request or approve a merge alone. Defaults to `substrateteamleads`.
 
-`MIN_REVIEWERS`: Minimum number of reviewers needed before a pull request can be accepted.
+`COMPANION_REPOS`: Optional comma separated repositories whose merged pull requests update the companion pull request they link to. Defaults to `substrate`.
 
-`REVIEW_REQUEST_PING`: Seconds between notifications requesting reviews on a pull request, sent publicly to the relevant project room, via Matrix.
+`COMPARE_REPOS`: Optional comma separated repositories accepting `bot compare substrate`. Defaults to `polkadot`.
 
-`PRIVATE_REVIEW_REMINDER_PING`: Seconds between notifications reminding a reviewer to review a pull request, sent privately to the reviewer, via Matrix.
+Each of the four settings above, whether `bot burnin` is accepted and the Gitlab job it starts can be overridden per repository in `.github/processbot.toml` on its default branch; see README.md.
 
-`PUBLIC_REVIEW_REMINDER_PING`: Seconds between notifications reminding a reviewer to review a pull request, sent publicly to the relevant project room, via Matrix.
+`LABEL_RULES_PATH`: Optional path to a JSON file of label rules: mutually exclusive label groups, labels required before merging, and labels applied to pull requests changing matching paths.
 
-`TEST_REPO_NAME`: Name of a Github repository to be used for testing.
+`WIP_RULES_PATH`: Optional path to a JSON file of labels and title patterns marking a pull request as a work in progress, with optional overrides per repository. Draft pull requests are always treated as a work in progress.
+
+The four Gitlab settings below are only used by `bot burnin`. They are optional, but once one is set the others are required; without them `bot burnin` is refused.
 
 `GITLAB_HOSTNAME`: Hostname of the Gitlab server used for burn-in deployment related CI jobs.
 
 `GITLAB_PROJECT`: Name of the project in Gitlab where CI jobs for burn-in deployments can be found.
 
+`GITLAB_JOB_NAME`: Name of the Gitlab CI job building the binary that is deployed for a burn-in.
+
 `GITLAB_PRIVATE_TOKEN`: Authentication token for the Gitlab server at GITLAB_HOSTNAME.
 */
 
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{
+	github_bot::GithubBot, labels::LabelRules, matrix, storage, wip::WipRules,
+};
+
+/// Variables earlier releases required, which are ignored with a warning.
+const OBSOLETE_VARIABLES: &[&str] = &[
+	"ENVIRONMENT",
+	"TEST_INSTALLATION_LOGIN",
+	"BAMBOO_TOKEN",
+	"BAMBOO_TICK_SECS",
+	"CORE_SORTING_REPO_NAME",
+	"LOGS_ROOM_ID",
+	"STATUS_FAILURE_PING",
+	"ISSUE_NOT_ADDRESSED_PING",
+	"ISSUE_NOT_ASSIGNED_TO_PR_AUTHOR_PING",
+	"NO_PROJECT_AUTHOR_IS_CORE_PING",
+	"NO_PROJECT_AUTHOR_IS_CORE_CLOSE_PR",
+	"NO_PROJECT_AUTHOR_UNKNOWN_CLOSE_PR",
+	"PROJECT_CONFIRMATION_TIMEOUT",
+	"REVIEW_REQUEST_PING",
+	"PRIVATE_REVIEW_REMINDER_PING",
+	"PUBLIC_REVIEW_REMINDER_PING",
+	"PUBLIC_REVIEW_REMINDER_DELAY",
+];
+
 #[derive(Debug, Clone)]
 pub struct MainConfig {
-	pub environment: String,
-	pub test_repo: String,
-	pub installation_login: String,
-	pub webhook_secret: String,
-	pub webhook_port: String,
+	pub installation_login: Option<String>,
+	pub github_api_url: String,
+	pub github_url: String,
+	/// the first is current; the others are accepted while rotating
+	pub webhook_secrets: Vec<String>,
+	pub webhook_port: u16,
+	pub admin_token: Option<String>,
+	pub shutdown_timeout_secs: u64,
 	pub db_path: String,
-	pub bamboo_token: String,
-	pub private_key: Vec<u8>,
+	pub storage_backend: storage::Backend,
+	pub app_id: u64,
+	/// parsed at startup so that a bad key fails fast
+	pub private_key: jsonwebtoken::EncodingKey,
+	/// empty if `matrix_silent` and not set
 	pub matrix_homeserver: String,
 	pub matrix_access_token: String,
 	pub matrix_default_channel_id: String,
 	pub main_tick_secs: u64,
-	pub bamboo_tick_secs: u64,
 	/// if true then matrix notifications will not be sent
 	pub matrix_silent: bool,
-	pub gitlab_hostname: String,
-	pub gitlab_project: String,
-	pub gitlab_job_name: String,
-	pub gitlab_private_token: String,
+	/// `None` if no Gitlab setting is set, which turns `bot burnin` away
+	pub gitlab: Option<GitlabConfig>,
+}
+
+/// The Gitlab server `bot burnin` starts CI jobs on.
+#[derive(Debug, Clone)]
+pub struct GitlabConfig {
+	pub hostname: String,
+	pub project: String,
+	pub job_name: String,
+	pub private_token: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct BotConfig {
+	/// seconds before a pending merge request expires
+	pub merge_request_expiry: u64,
+	/// mininum number of reviewers
+	pub min_reviewers: usize,
+	/// team whose approvals count towards `min_reviewers`
+	pub core_devs_team: String,
+	/// team whose members may merge on their own or approve alone
+	pub team_leads_team: String,
+	/// repositories whose merged pull requests update their companion
+	pub companion_repos: Vec<String>,
+	/// repositories accepting `bot compare substrate`
+	pub compare_repos: Vec<String>,
+	/// rules for exclusive, required and path-based labels
+	pub label_rules: LabelRules,
+	/// rules for labels and titles marking a pull request as a work in progress
+	pub wip_rules: WipRules,
+}
+
+/// A setting as it was read, for printing.
+struct Setting {
+	name: &'static str,
+	value: Option<String>,
+	secret: bool,
+}
+
+pub struct Config {
+	pub main: MainConfig,
+	pub bot: BotConfig,
+	/// obsolete settings that are still set
+	pub warnings: Vec<String>,
+	settings: Vec<Setting>,
+}
+
+/// Settings that are not usable, with what was read of them.
+pub struct InvalidConfig {
+	pub problems: Vec<String>,
+	settings: Vec<Setting>,
 }
 
-impl MainConfig {
-	pub fn from_env() -> Self {
+impl InvalidConfig {
+	/// Renders the settings that were read as a TOML config file, with secrets redacted.
+	pub fn render(&self) -> String {
+		render(&self.settings)
+	}
+}
+
+impl Config {
+	/// Reads `.env`, then the config file at `path` or `CONFIG_PATH` if either is set, then the
+	/// environment. Returns every problem found if the settings are not usable, including those of
+	/// the config file.
+	pub fn load(path: Option<PathBuf>) -> Result<Self, InvalidConfig> {
 		dotenv::dotenv().ok();
+		let (file, file_problems) = match path
+			.or_else(|| dotenv::var("CONFIG_PATH").ok().map(PathBuf::from))
+		{
+			Some(path) => read_config_file(&path),
+			None => (BTreeMap::new(), vec![]),
+		};
+		Self::from_sources(file, file_problems, &|name| dotenv::var(name).ok())
+	}
 
-		let environment = dotenv::var("ENVIRONMENT").expect("ENVIRONMENT");
-		let test_repo = dotenv::var("TEST_REPO_NAME").expect("TEST_REPO_NAME");
-		let installation_login =
-			dotenv::var("INSTALLATION_LOGIN").expect("INSTALLATION_LOGIN");
-		let webhook_secret =
-			dotenv::var("WEBHOOK_SECRET").expect("WEBHOOK_SECRET");
-		let webhook_port = dotenv::var("WEBHOOK_PORT").expect("WEBHOOK_PORT");
-		let db_path = dotenv::var("DB_PATH").expect("DB_PATH");
-		let bamboo_token = dotenv::var("BAMBOO_TOKEN").expect("BAMBOO_TOKEN");
-		let matrix_homeserver =
-			dotenv::var("MATRIX_HOMESERVER").expect("MATRIX_HOMESERVER");
-		let matrix_access_token =
-			dotenv::var("MATRIX_ACCESS_TOKEN").expect("MATRIX_ACCESS_TOKEN");
-		let matrix_default_channel_id =
-			dotenv::var("MATRIX_DEFAULT_CHANNEL_ID")
-				.expect("MATRIX_DEFAULT_CHANNEL_ID");
-		let main_tick_secs = dotenv::var("MAIN_TICK_SECS")
-			.expect("MAIN_TICK_SECS")
-			.parse::<u64>()
-			.expect("parse MAIN_TICK_SECS");
-		let bamboo_tick_secs = dotenv::var("BAMBOO_TICK_SECS")
-			.expect("BAMBOO_TICK_SECS")
-			.parse::<u64>()
-			.expect("parse BAMBOO_TICK_SECS");
-		let matrix_silent = dotenv::var("MATRIX_SILENT")
-			.expect("MATRIX_SILENT")
-			.parse::<bool>()
-			.expect("failed parsing MATRIX_SILENT");
-
-		let private_key_path =
-			dotenv::var("PRIVATE_KEY_PATH").expect("PRIVATE_KEY_PATH");
-		let private_key = std::fs::read(&private_key_path)
-			.expect("Couldn't find private key.");
+	fn from_sources(
+		file: BTreeMap<String, String>,
+		file_problems: Vec<String>,
+		env: &dyn Fn(&str) -> Option<String>,
+	) -> Result<Self, InvalidConfig> {
+		let mut loader = Loader {
+			file,
+			env,
+			settings: vec![],
+			problems: file_problems,
+		};
+
+		let installation_login = loader.optional("INSTALLATION_LOGIN");
+		let app_id = loader.required("GITHUB_APP_ID");
+		let private_key = loader.private_key("PRIVATE_KEY_PATH");
+		let github_api_url =
+			loader.url("GITHUB_API_URL", Some(GithubBot::DEFAULT_BASE_URL));
+		let github_url =
+			loader.url("GITHUB_URL", Some(GithubBot::DEFAULT_BASE_HTML_URL));
+		let webhook_secrets = loader
+			.secret("WEBHOOK_SECRET", true)
+			.unwrap_or_default()
+			.split(',')
+			.map(|secret| secret.trim().to_owned())
+			.filter(|secret| !secret.is_empty())
+			.collect::<Vec<String>>();
+		let webhook_port = loader.required("WEBHOOK_PORT");
+		let admin_token = loader.secret("ADMIN_TOKEN", false);
+		let shutdown_timeout_secs =
+			loader.with_default("SHUTDOWN_TIMEOUT_SECS", 25);
+		let db_path = loader.required("DB_PATH");
+		let storage_backend =
+			loader.with_default("STORAGE_BACKEND", storage::Backend::default());
+		let main_tick_secs = loader.with_default("MAIN_TICK_SECS", 300);
+
+		let matrix_silent = loader.with_default("MATRIX_SILENT", false);
+		let matrix_homeserver: String =
+			loader.required_if("MATRIX_HOMESERVER", !matrix_silent);
+		loader.check_url("MATRIX_HOMESERVER", &matrix_homeserver);
+		let matrix_access_token = loader
+			.secret("MATRIX_ACCESS_TOKEN", !matrix_silent)
+			.unwrap_or_default();
+		let matrix_default_channel_id: String =
+			loader.required_if("MATRIX_DEFAULT_CHANNEL_ID", !matrix_silent);
+		if !matrix_default_channel_id.is_empty()
+			&& !matrix::is_room_id(&matrix_default_channel_id)
+		{
+			loader.problems.push(format!(
+				"MATRIX_DEFAULT_CHANNEL_ID: `{}` is not a Matrix room ID",
+				matrix_default_channel_id
+			));
+		}
 
+		// only `bot burnin` uses Gitlab, so its settings are all set or none
+		let gitlab_configured = [
+			"GITLAB_HOSTNAME",
+			"GITLAB_PROJECT",
+			"GITLAB_JOB_NAME",
+			"GITLAB_PRIVATE_TOKEN",
+			"GITLAB_PRIVATE_TOKEN_FILE",
+		]
+		.iter()
+		.any(|name| loader.is_set(name));
 		let gitlab_hostname =
-			dotenv::var("GITLAB_HOSTNAME").expect("GITLAB_HOSTNAME");
+			loader.required_if("GITLAB_HOSTNAME", gitlab_configured);
 		let gitlab_project =
-			dotenv::var("GITLAB_PROJECT").expect("GITLAB_PROJECT");
+			loader.required_if("GITLAB_PROJECT", gitlab_configured);
 		let gitlab_job_name =
-			dotenv::var("GITLAB_JOB_NAME").expect("GITLAB_JOB_NAME");
-		let gitlab_private_token =
-			dotenv::var("GITLAB_PRIVATE_TOKEN").expect("GITLAB_PRIVATE_TOKEN");
-
-		Self {
-			environment,
-			test_repo,
-			installation_login,
-			webhook_secret,
-			webhook_port,
-			db_path,
-			bamboo_token,
-			private_key,
-			matrix_homeserver,
-			matrix_access_token,
-			matrix_default_channel_id,
-			main_tick_secs,
-			bamboo_tick_secs,
-			matrix_silent,
-			gitlab_hostname,
-			gitlab_project,
-			gitlab_job_name,
-			gitlab_private_token,
+			loader.required_if("GITLAB_JOB_NAME", gitlab_configured);
+		let gitlab_private_token = loader
+			.secret("GITLAB_PRIVATE_TOKEN", gitlab_configured)
+			.unwrap_or_default();
+		let gitlab = if gitlab_configured {
+			Some(GitlabConfig {
+				hostname: gitlab_hostname,
+				project: gitlab_project,
+				job_name: gitlab_job_name,
+				private_token: gitlab_private_token,
+			})
+		} else {
+			None
+		};
+
+		let merge_request_expiry =
+			loader.with_default("MERGE_REQUEST_EXPIRY", 7 * 24 * 60 * 60);
+		let min_reviewers = loader.with_default("MIN_REVIEWERS", 2);
+		let core_devs_team =
+			loader.with_default("CORE_DEVS_TEAM", "core-devs".to_owned());
+		let team_leads_team = loader
+			.with_default("TEAM_LEADS_TEAM", "substrateteamleads".to_owned());
+		let companion_repos = loader.list("COMPANION_REPOS", "substrate");
+		let compare_repos = loader.list("COMPARE_REPOS", "polkadot");
+		let label_rules = loader.rules::<LabelRules>("LABEL_RULES_PATH");
+		let wip_rules = loader.rules::<WipRules>("WIP_RULES_PATH");
+
+		let mut warnings = vec![];
+		for key in loader.file.keys() {
+			let name = key.to_uppercase();
+			let known = loader.settings.iter().any(|setting| {
+				setting.name == name
+					|| (setting.secret
+						&& name.strip_suffix("_FILE") == Some(setting.name))
+			});
+			if OBSOLETE_VARIABLES.contains(&name.as_str()) {
+				warnings.push(format!("`{}` is no longer used", key));
+			} else if !known {
+				loader.problems.push(format!(
+					"unknown setting `{}` in the config file",
+					key
+				));
+			}
+		}
+		for name in OBSOLETE_VARIABLES.iter() {
+			if env(name).is_some() {
+				warnings.push(format!("{} is no longer used", name));
+			}
 		}
+
+		let private_key = match private_key {
+			Some(private_key) if loader.problems.is_empty() => private_key,
+			_ => {
+				return Err(InvalidConfig {
+					problems: loader.problems,
+					settings: loader.settings,
+				})
+			}
+		};
+		Ok(Self {
+			main: MainConfig {
+				installation_login,
+				github_api_url,
+				github_url,
+				webhook_secrets,
+				webhook_port,
+				admin_token,
+				shutdown_timeout_secs,
+				db_path,
+				storage_backend,
+				app_id,
+				private_key,
+				matrix_homeserver,
+				matrix_access_token,
+				matrix_default_channel_id,
+				main_tick_secs,
+				matrix_silent,
+				gitlab,
+			},
+			bot: BotConfig {
+				merge_request_expiry,
+				min_reviewers,
+				core_devs_team,
+				team_leads_team,
+				companion_repos,
+				compare_repos,
+				label_rules,
+				wip_rules,
+			},
+			warnings,
+			settings: loader.settings,
+		})
+	}
+
+	/// Renders the settings in effect as a TOML config file, with secrets redacted.
+	pub fn render(&self) -> String {
+		render(&self.settings)
 	}
 }
 
-#[derive(Debug, Clone)]
-pub struct BotConfig {
-	/// seconds between pings
-	pub status_failure_ping: u64,
-	/// seconds between pings
-	pub issue_not_addressed_ping: u64,
-	/// seconds between pings
-	pub issue_not_assigned_to_pr_author_ping: u64,
-	/// seconds between pings
-	pub no_project_author_is_core_ping: u64,
-	/// seconds before pr gets closed
-	pub no_project_author_is_core_close_pr: u64,
-	/// seconds before pr gets closed
-	pub no_project_author_unknown_close_pr: u64,
-	/// seconds before unconfirmed change gets reverted
-	pub project_confirmation_timeout: u64,
-	/// seconds between pings
-	pub review_request_ping: u64,
-	/// seconds between pings
-	pub private_review_reminder_ping: u64,
-	/// seconds between pings
-	pub public_review_reminder_ping: u64,
-	/// seconds before public review reminders begin
-	pub public_review_reminder_delay: u64,
-	/// mininum number of reviewers
-	pub min_reviewers: usize,
-	/// name of repo for issues without a project
-	pub core_sorting_repo_name: String,
-	/// matrix room id for sending app logs
-	pub logs_room_id: String,
+fn render(settings: &[Setting]) -> String {
+	settings
+		.iter()
+		.map(|setting| {
+			let key = setting.name.to_lowercase();
+			match &setting.value {
+				Some(_) if setting.secret => {
+					format!("{} = \"<redacted>\"\n", key)
+				}
+				// JSON strings are valid TOML strings
+				Some(value) => format!(
+					"{} = {}\n",
+					key,
+					serde_json::Value::String(value.to_owned())
+				),
+				None => format!("# {} is not set\n", key),
+			}
+		})
+		.collect()
 }
 
-impl BotConfig {
-	pub fn from_env() -> Self {
-		dotenv::dotenv().ok();
-		Self {
-			status_failure_ping: dotenv::var("STATUS_FAILURE_PING")
-				.expect("STATUS_FAILURE_PING")
-				.parse::<u64>()
-				.expect("failed parsing STATUS_FAILURE_PING"),
-
-			issue_not_addressed_ping: dotenv::var("ISSUE_NOT_ADDRESSED_PING")
-				.expect("ISSUE_NOT_ADDRESSED_PING")
-				.parse::<u64>()
-				.expect("failed parsing ISSUE_NOT_ADDRESSED_PING"),
-
-			issue_not_assigned_to_pr_author_ping: dotenv::var(
-				"ISSUE_NOT_ASSIGNED_TO_PR_AUTHOR_PING",
-			)
-			.expect("ISSUE_NOT_ASSIGNED_TO_PR_AUTHOR_PING")
-			.parse::<u64>()
-			.expect("failed parsing ISSUE_NOT_ASSIGNED_TO_PR_AUTHOR_PING"),
-
-			no_project_author_is_core_ping: dotenv::var(
-				"NO_PROJECT_AUTHOR_IS_CORE_PING",
-			)
-			.expect("NO_PROJECT_AUTHOR_IS_CORE_PING")
-			.parse::<u64>()
-			.expect("failed parsing NO_PROJECT_AUTHOR_IS_CORE_PING"),
-
-			no_project_author_is_core_close_pr: dotenv::var(
-				"NO_PROJECT_AUTHOR_IS_CORE_CLOSE_PR",
-			)
-			.expect("NO_PROJECT_AUTHOR_IS_CORE_CLOSE_PR")
-			.parse::<u64>()
-			.expect("failed parsing NO_PROJECT_AUTHOR_IS_CORE_CLOSE_PR"),
-
-			no_project_author_unknown_close_pr: dotenv::var(
-				"NO_PROJECT_AUTHOR_UNKNOWN_CLOSE_PR",
-			)
-			.expect("NO_PROJECT_AUTHOR_UNKNOWN_CLOSE_PR")
-			.parse::<u64>()
-			.expect("failed parsing NO_PROJECT_AUTHOR_UNKNOWN_CLOSE_PR"),
-
-			project_confirmation_timeout: dotenv::var(
-				"PROJECT_CONFIRMATION_TIMEOUT",
-			)
-			.expect("PROJECT_CONFIRMATION_TIMEOUT")
-			.parse::<u64>()
-			.expect("failed parsing PROJECT_CONFIRMATION_TIMEOUT"),
-
-			review_request_ping: dotenv::var("REVIEW_REQUEST_PING")
-				.expect("REVIEW_REQUEST_PING")
-				.parse::<u64>()
-				.expect("failed parsing REVIEW_REQUEST_PING"),
-
-			private_review_reminder_ping: dotenv::var(
-				"PRIVATE_REVIEW_REMINDER_PING",
-			)
-			.expect("PRIVATE_REVIEW_REMINDER_PING")
-			.parse::<u64>()
-			.expect("failed parsing PRIVATE_REVIEW_REMINDER_PING"),
-
-			public_review_reminder_ping: dotenv::var(
-				"PUBLIC_REVIEW_REMINDER_PING",
-			)
-			.expect("PUBLIC_REVIEW_REMINDER_PING")
-			.parse::<u64>()
-			.expect("failed parsing PUBLIC_REVIEW_REMINDER_PING"),
-
-			public_review_reminder_delay: dotenv::var(
-				"PUBLIC_REVIEW_REMINDER_DELAY",
-			)
-			.expect("PUBLIC_REVIEW_REMINDER_DELAY")
-			.parse::<u64>()
-			.expect("failed parsing PUBLIC_REVIEW_REMINDER_DELAY"),
-
-			min_reviewers: dotenv::var("MIN_REVIEWERS")
-				.expect("MIN_REVIEWERS")
-				.parse::<usize>()
-				.expect("failed parsing MIN_REVIEWERS"),
-
-			core_sorting_repo_name: dotenv::var("CORE_SORTING_REPO_NAME")
-				.expect("CORE_SORTING_REPO_NAME"),
-
-			logs_room_id: dotenv::var("LOGS_ROOM_ID").expect("LOGS_ROOM_ID"),
+/// Reads the settings of a TOML config file, or a YAML one if named so, keyed in lower case, with
+/// the problems found in it. Settings that can be read are returned even if others cannot.
+fn read_config_file(path: &Path) -> (BTreeMap<String, String>, Vec<String>) {
+	let mut settings = BTreeMap::new();
+	let contents = match std::fs::read_to_string(path) {
+		Ok(contents) => contents,
+		Err(e) => {
+			return (settings, vec![format!("{}: {}", path.display(), e)])
+		}
+	};
+	let values = match path.extension().and_then(|ext| ext.to_str()) {
+		Some("yml") | Some("yaml") => serde_yaml::from_str::<
+			BTreeMap<String, serde_json::Value>,
+		>(&contents)
+		.map_err(|e| e.to_string()),
+		_ => toml::from_str::<BTreeMap<String, serde_json::Value>>(&contents)
+			.map_err(|e| e.to_string()),
+	};
+	let values = match values {
+		Ok(values) => values,
+		Err(e) => {
+			return (settings, vec![format!("{}: {}", path.display(), e)])
+		}
+	};
+
+	let mut problems = vec![];
+	for (key, value) in values {
+		match setting_value(&value) {
+			Some(value) => {
+				settings.insert(key.to_lowercase(), value);
+			}
+			None => problems.push(format!(
+				"{}: `{}` should be a string, number, boolean or list of strings",
+				path.display(),
+				key
+			)),
+		}
+	}
+	(settings, problems)
+}
+
+/// Converts a value of the config file to the string an environment variable would hold.
+fn setting_value(value: &serde_json::Value) -> Option<String> {
+	match value {
+		serde_json::Value::String(value) => Some(value.to_owned()),
+		serde_json::Value::Number(value) => Some(value.to_string()),
+		serde_json::Value::Bool(value) => Some(value.to_string()),
+		serde_json::Value::Array(items) => items
+			.iter()
+			.map(|item| item.as_str().map(str::to_owned))
+			.collect::<Option<Vec<String>>>()
+			.map(|items| items.join(",")),
+		_ => None,
+	}
+}
+
+/// Reads settings, collecting every problem instead of stopping at the first.
+struct Loader<'a> {
+	file: BTreeMap<String, String>,
+	env: &'a dyn Fn(&str) -> Option<String>,
+	settings: Vec<Setting>,
+	problems: Vec<String>,
+}
+
+impl Loader<'_> {
+	fn from_env(&self, name: &str) -> Option<String> {
+		(self.env)(name).filter(|value| !value.is_empty())
+	}
+
+	/// Whether the setting `name` is set at all.
+	fn is_set(&self, name: &str) -> bool {
+		self.from_env(name)
+			.or_else(|| self.from_file(name))
+			.is_some()
+	}
+
+	fn from_file(&self, name: &str) -> Option<String> {
+		self.file
+			.get(&name.to_lowercase())
+			.filter(|value| !value.is_empty())
+			.cloned()
+	}
+
+	/// Records the setting `name` and parses it if it is set.
+	fn optional<T>(&mut self, name: &'static str) -> Option<T>
+	where
+		T: FromStr,
+		T::Err: Display,
+	{
+		let value = self.from_env(name).or_else(|| self.from_file(name));
+		self.settings.push(Setting {
+			name,
+			value: value.clone(),
+			secret: false,
+		});
+		match value?.parse() {
+			Ok(value) => Some(value),
+			Err(e) => {
+				self.problems.push(format!("{}: {}", name, e));
+				None
+			}
+		}
+	}
+
+	/// Returns the default of `T` after recording a problem if the setting is not usable.
+	fn required<T>(&mut self, name: &'static str) -> T
+	where
+		T: FromStr + Default,
+		T::Err: Display,
+	{
+		let problems = self.problems.len();
+		match self.optional(name) {
+			Some(value) => value,
+			None => {
+				if self.problems.len() == problems {
+					self.problems.push(format!("{} is required", name));
+				}
+				T::default()
+			}
+		}
+	}
+
+	/// Like `required` if the setting is `needed`, and otherwise like `optional` with the default
+	/// of `T` when it is not set.
+	fn required_if<T>(&mut self, name: &'static str, needed: bool) -> T
+	where
+		T: FromStr + Default,
+		T::Err: Display,
+	{
+		if needed {
+			self.required(name)
+		} else {
+			self.optional(name).unwrap_or_default()
 		}
 	}
+
+	fn with_default<T>(&mut self, name: &'static str, default: T) -> T
+	where
+		T: FromStr + ToString,
+		T::Err: Display,
+	{
+		match self.optional(name) {
+			Some(value) => value,
+			None => {
+				if let Some(setting) = self.settings.last_mut() {
+					setting.value.get_or_insert_with(|| default.to_string());
+				}
+				default
+			}
+		}
+	}
+
+	/// Reads a comma separated list, which the config file may give as an array.
+	fn list(&mut self, name: &'static str, default: &str) -> Vec<String> {
+		self.with_default(name, default.to_owned())
+			.split(',')
+			.map(|item| item.trim().to_owned())
+			.filter(|item| !item.is_empty())
+			.collect()
+	}
+
+	/// Reads the secret `name`, from the file named by `{name}_FILE` if it is not set itself.
+	/// The environment takes precedence over the config file for either.
+	fn secret(&mut self, name: &'static str, required: bool) -> Option<String> {
+		let path_name = format!("{}_FILE", name);
+		let value = if let Some(value) = self.from_env(name) {
+			Some(value)
+		} else if let Some(path) = self.from_env(&path_name) {
+			self.read_secret(&path_name, &path)
+		} else if let Some(value) = self.from_file(name) {
+			Some(value)
+		} else if let Some(path) = self.from_file(&path_name) {
+			self.read_secret(&path_name, &path)
+		} else {
+			if required {
+				self.problems
+					.push(format!("{} or {} is required", name, path_name));
+			}
+			None
+		};
+		self.settings.push(Setting {
+			name,
+			value: value.clone(),
+			secret: true,
+		});
+		value
+	}
+
+	fn read_secret(&mut self, name: &str, path: &str) -> Option<String> {
+		match std::fs::read_to_string(path) {
+			Ok(secret) => Some(secret.trim_end_matches('\n').to_owned()),
+			Err(e) => {
+				self.problems
+					.push(format!("{}: couldn't read {}: {}", name, path, e));
+				None
+			}
+		}
+	}
+
+	fn url(&mut self, name: &'static str, default: Option<&str>) -> String {
+		let url: String = match default {
+			Some(default) => self.with_default(name, default.to_owned()),
+			None => self.required(name),
+		};
+		self.check_url(name, &url);
+		url
+	}
+
+	/// Records a problem if `url`, read from the setting `name`, is set but not a URL.
+	fn check_url(&mut self, name: &str, url: &str) {
+		if !url.is_empty() {
+			if let Err(e) = url::Url::parse(url) {
+				self.problems
+					.push(format!("{}: `{}` is not a URL: {}", name, url, e));
+			}
+		}
+	}
+
+	fn private_key(
+		&mut self,
+		name: &'static str,
+	) -> Option<jsonwebtoken::EncodingKey> {
+		let path = self.required::<String>(name);
+		if path.is_empty() {
+			return None;
+		}
+		let key = std::fs::read(&path)
+			.map_err(|e| format!("couldn't read {}: {}", path, e))
+			.and_then(|pem| {
+				jsonwebtoken::EncodingKey::from_rsa_pem(&pem).map_err(|e| {
+					format!(
+						"{} should be an RSA private key in PEM format: {}",
+						path, e
+					)
+				})
+			});
+		match key {
+			Ok(key) => Some(key),
+			Err(e) => {
+				self.problems.push(format!("{}: {}", name, e));
+				None
+			}
+		}
+	}
+
+	/// Reads the JSON rules at the path the setting `name` holds, if any. Patterns in the rules
+	/// are compiled as they are read, so a malformed one is reported here.
+	fn rules<T>(&mut self, name: &'static str) -> T
+	where
+		T: DeserializeOwned + Default,
+	{
+		let path = match self.optional::<String>(name) {
+			Some(path) => path,
+			None => return T::default(),
+		};
+		let rules = std::fs::read_to_string(&path)
+			.map_err(|e| format!("couldn't read {}: {}", path, e))
+			.and_then(|contents| {
+				serde_json::from_str::<T>(&contents)
+					.map_err(|e| format!("{} is malformed: {}", path, e))
+			});
+		match rules {
+			Ok(rules) => rules,
+			Err(e) => {
+				self.problems.push(format!("{}: {}", name, e));
+				T::default()
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn loader<'a>(
+		file: &[(&str, &str)],
+		env: &'a dyn Fn(&str) -> Option<String>,
+	) -> Loader<'a> {
+		Loader {
+			file: file
+				.iter()
+				.map(|(key, value)| (key.to_string(), value.to_string()))
+				.collect(),
+			env,
+			settings: vec![],
+			problems: vec![],
+		}
+	}
+
+	#[test]
+	fn test_layers() {
+		let secret_path = std::env::temp_dir()
+			.join(format!("processbot-secret-{}", std::process::id()));
+		std::fs::write(&secret_path, "from file\n").expect("write secret");
+		let env = |name: &str| match name {
+			"WEBHOOK_PORT" => Some("9000".to_owned()),
+			"GITLAB_PRIVATE_TOKEN_FILE" => {
+				Some(secret_path.display().to_string())
+			}
+			_ => None,
+		};
+		let mut loader = loader(
+			&[
+				("webhook_port", "8080"),
+				("min_reviewers", "3"),
+				("companion_repos", "substrate, cumulus"),
+				("matrix_access_token", "from config"),
+			],
+			&env,
+		);
+		assert_eq!(loader.required::<u16>("WEBHOOK_PORT"), 9000);
+		assert_eq!(loader.with_default("MIN_REVIEWERS", 2), 3);
+		assert_eq!(loader.with_default("MAIN_TICK_SECS", 300), 300);
+		assert_eq!(
+			loader.list("COMPANION_REPOS", "substrate"),
+			vec!["substrate", "cumulus"]
+		);
+		assert_eq!(
+			loader.secret("MATRIX_ACCESS_TOKEN", true),
+			Some("from config".to_owned())
+		);
+		assert_eq!(
+			loader.secret("GITLAB_PRIVATE_TOKEN", true),
+			Some("from file".to_owned())
+		);
+		assert_eq!(loader.secret("ADMIN_TOKEN", false), None);
+		assert!(loader.problems.is_empty());
+		std::fs::remove_file(&secret_path).expect("remove secret");
+
+		assert_eq!(
+			render(&loader.settings),
+			"webhook_port = \"9000\"\n\
+			 min_reviewers = \"3\"\n\
+			 main_tick_secs = \"300\"\n\
+			 companion_repos = \"substrate, cumulus\"\n\
+			 matrix_access_token = \"<redacted>\"\n\
+			 gitlab_private_token = \"<redacted>\"\n\
+			 # admin_token is not set\n"
+		);
+	}
+
+	#[test]
+	fn test_problems() {
+		let env = |name: &str| match name {
+			"WEBHOOK_PORT" => Some("eighty".to_owned()),
+			"GITHUB_URL" => Some("github.com".to_owned()),
+			"BAMBOO_TOKEN" => Some("token".to_owned()),
+			_ => None,
+		};
+		let file = [("min_reviewer", "2"), ("matrix_silent", "yes")]
+			.iter()
+			.map(|(key, value)| (key.to_string(), value.to_string()))
+			.collect();
+		let file_problems = vec!["processbot.toml: expected `=`".to_owned()];
+		let problems = match Config::from_sources(file, file_problems, &env) {
+			Ok(_) => panic!("the settings should not be usable"),
+			Err(invalid) => invalid.problems,
+		};
+		for expected in [
+			"processbot.toml: expected `=`",
+			"PRIVATE_KEY_PATH is required",
+			"WEBHOOK_SECRET or WEBHOOK_SECRET_FILE is required",
+			"WEBHOOK_PORT: invalid digit found in string",
+			"MATRIX_SILENT: provided string was not `true` or `false`",
+			"unknown setting `min_reviewer` in the config file",
+		]
+		.iter()
+		{
+			assert!(
+				problems.iter().any(|problem| problem == expected),
+				"{:?} should include {:?}",
+				problems,
+				expected
+			);
+		}
+		assert!(problems
+			.iter()
+			.any(|problem| problem.starts_with("GITHUB_URL: `github.com`")));
+	}
+
+	#[test]
+	fn test_optional_services() {
+		let env = |name: &str| match name {
+			"MATRIX_SILENT" => Some("true".to_owned()),
+			"GITLAB_PROJECT" => Some("parity/polkadot".to_owned()),
+			_ => None,
+		};
+		let invalid = match Config::from_sources(BTreeMap::new(), vec![], &env)
+		{
+			Ok(_) => panic!("the settings should not be usable"),
+			Err(invalid) => invalid,
+		};
+		assert!(
+			!invalid
+				.problems
+				.iter()
+				.any(|problem| problem.starts_with("MATRIX_")),
+			"{:?} should not require Matrix settings",
+			invalid.problems
+		);
+		for expected in [
+			"GITLAB_HOSTNAME is required",
+			"GITLAB_JOB_NAME is required",
+			"GITLAB_PRIVATE_TOKEN or GITLAB_PRIVATE_TOKEN_FILE is required",
+		]
+		.iter()
+		{
+			assert!(
+				invalid.problems.iter().any(|problem| problem == expected),
+				"{:?} should include {:?}",
+				invalid.problems,
+				expected
+			);
+		}
+		assert!(invalid.render().contains("matrix_silent = \"true\"\n"));
+	}
+
+	#[test]
+	fn test_read_config_file() {
+		let dir = std::env::temp_dir()
+			.join(format!("processbot-config-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).expect("create dir");
+
+		let toml_path = dir.join("processbot.toml");
+		std::fs::write(
+			&toml_path,
+			"webhook_port = 8080\nMATRIX_SILENT = true\nwebhook_secret = [\"new\", \"old\"]\n",
+		)
+		.expect("write");
+		let (settings, problems) = read_config_file(&toml_path);
+		assert!(problems.is_empty());
+		assert_eq!(
+			settings.get("webhook_port").map(String::as_str),
+			Some("8080")
+		);
+		assert_eq!(
+			settings.get("matrix_silent").map(String::as_str),
+			Some("true")
+		);
+		assert_eq!(
+			settings.get("webhook_secret").map(String::as_str),
+			Some("new,old")
+		);
+
+		let yaml_path = dir.join("processbot.yml");
+		std::fs::write(
+			&yaml_path,
+			"db_path: /var/lib/processbot\ngitlab: {}\n",
+		)
+		.expect("write");
+		let (settings, problems) = read_config_file(&yaml_path);
+		assert_eq!(
+			settings.get("db_path").map(String::as_str),
+			Some("/var/lib/processbot")
+		);
+		assert_eq!(
+			problems,
+			vec![format!(
+				"{}: `gitlab` should be a string, number, boolean or list of strings",
+				yaml_path.display()
+			)]
+		);
+
+		let (settings, problems) = read_config_file(&dir.join("missing.toml"));
+		assert!(settings.is_empty());
+		assert_eq!(problems.len(), 1);
+
+		std::fs::remove_dir_all(&dir).expect("remove dir");
+	}
 }
@@ -12,15 +12,38 @@ pub const AUTO_MERGE_INVALIDATED: &str =
 pub const COMPARE_RELEASE_REQUEST: &str = "bot compare substrate";
 pub const REBASE: &str = "bot rebase";
 pub const BURNIN_REQUEST: &str = "bot burnin";
+pub const BACKPORT_REQUEST: &str = "bot backport";
+
+pub const BACKPORT_LABEL_PREFIX: &str = "backport-to-";
 
 pub const FEATURES_KEY: &str = "features";
 
 pub const PROJECT_NEEDS_BACKLOG: &str =
 	"@{owner}, {project_url} needs a backlog column.";
 
-pub const MISMATCHED_PROCESS_FILE: &str = "Process.toml for repo {repo_url} lists projects that do not exist in the repo, so it will be treated as invalid.";
+pub const PROCESS_FILE: &str = "Process.json";
+pub const PROCESS_TOML_FILE: &str = "Process.toml";
+pub const PROCESS_YAML_FILE: &str = ".github/process.yml";
+
+pub const PROCESS_FILE_CHECK: &str = "processbot/process-file";
+
+pub const MISMATCHED_PROCESS_FILE: &str = "{process_file} for repo {repo_url} lists projects that do not exist in the repo or users that are not members of the organization, so it will be treated as invalid.";
+
+pub const MALFORMED_PROCESS_FILE: &str = "{process_file} for repo {repo_url} is malformed or missing some fields. Please ensure that every listed project contains an owner and a matrix_room_id.";
+
+pub const CONFLICTING_PROCESS_FILES: &str = "{process_file} for repo {repo_url} is valid, but other Process files are present and will be ignored.";
+
+pub const VALID_PROCESS_FILE: &str =
+	"{process_file} for repo {repo_url} is valid.";
+
+pub const REPO_CONFIG_FILE: &str = ".github/processbot.toml";
+
+pub const REPO_CONFIG_CHECK: &str = "processbot/repo-config";
+
+pub const INVALID_REPO_CONFIG: &str = "{repo_config_file} for repo {repo_url} is invalid, so the global settings will be used instead.";
 
-pub const MALFORMED_PROCESS_FILE: &str = "Process.toml for repo {repo_url} is malformed or missing some fields. Please ensure that every listed project contains an owner and a matrix_room_id.";
+pub const VALID_REPO_CONFIG: &str =
+	"{repo_config_file} for repo {repo_url} is valid.";
 
 pub const WARN_FOR_NO_ISSUE: &str = "@{author}, this will be closed if it does not explicitly mention the issue it addresses.";
 
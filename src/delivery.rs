@@ -0,0 +1,319 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	storage::{Range, Record, Storage},
+	Result,
+};
+
+/// Deliveries are remembered for this long; GitHub redelivers failed webhooks within a few days.
+pub const DELIVERY_TTL_SECS: i64 = 3 * 24 * 60 * 60;
+
+/// The delivery log keeps this many of the most recent deliveries.
+pub const MAX_LOGGED_DELIVERIES: usize = 1000;
+
+/// A delivery that was handled, keyed by its `X-GitHub-Delivery` ID.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HandledDelivery {
+	pub handled_at: i64,
+}
+
+impl Record for HandledDelivery {
+	const CF: &'static str = "handled_deliveries";
+	const VERSION: u16 = 1;
+}
+
+/// Index of handled deliveries by the time they were handled, so expired ones are found without
+/// scanning every delivery.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HandledDeliveryTime {
+	pub delivery_id: String,
+}
+
+impl Record for HandledDeliveryTime {
+	const CF: &'static str = "handled_delivery_times";
+	const VERSION: u16 = 1;
+}
+
+fn delivery_time_key(timestamp: i64, delivery_id: &str) -> String {
+	// zero-padded so that keys sort by time
+	format!("{:020}:{}", timestamp, delivery_id)
+}
+
+/// Returns true if the delivery with the `X-GitHub-Delivery` ID `delivery_id` was already handled.
+pub fn delivery_seen(storage: &Storage, delivery_id: &str) -> Result<bool> {
+	Ok(storage.get::<HandledDelivery>(delivery_id)?.is_some())
+}
+
+/// Remembers that a delivery was handled and forgets deliveries older than `DELIVERY_TTL_SECS`.
+pub fn record_delivery(storage: &Storage, delivery_id: &str) -> Result<()> {
+	let now = Utc::now().timestamp();
+	storage.put(delivery_id, &HandledDelivery { handled_at: now })?;
+	storage.put(
+		&delivery_time_key(now, delivery_id),
+		&HandledDeliveryTime {
+			delivery_id: delivery_id.to_owned(),
+		},
+	)?;
+	prune_deliveries(storage, now - DELIVERY_TTL_SECS)
+}
+
+/// Forgets deliveries handled before `cutoff`.
+fn prune_deliveries(storage: &Storage, cutoff: i64) -> Result<()> {
+	let expired = storage.scan::<HandledDeliveryTime>(Range::Before(
+		&delivery_time_key(cutoff, ""),
+	))?;
+	for (key, HandledDeliveryTime { delivery_id }) in expired {
+		storage.delete::<HandledDelivery>(&delivery_id)?;
+		storage.delete::<HandledDeliveryTime>(&key)?;
+	}
+	Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+	/// The payload was handled without error.
+	Handled,
+	/// The delivery was already handled.
+	Duplicate,
+	/// The event, or its action, is not one the bot handles.
+	Ignored,
+	/// The signature did not match any webhook secret. Such deliveries are only counted, not
+	/// logged.
+	InvalidSignature,
+	/// The payload could not be parsed or handling it failed.
+	Failed,
+	/// The bot stopped before handling finished.
+	Interrupted,
+}
+
+impl Outcome {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Outcome::Handled => "handled",
+			Outcome::Duplicate => "duplicate",
+			Outcome::Ignored => "ignored",
+			Outcome::InvalidSignature => "invalid_signature",
+			Outcome::Failed => "failed",
+			Outcome::Interrupted => "interrupted",
+		}
+	}
+}
+
+/// A webhook as received, with what became of it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Delivery {
+	/// `X-GitHub-Delivery` header.
+	pub id: String,
+	/// `X-GitHub-Event` header.
+	pub event: String,
+	pub received_at: DateTime<Utc>,
+	pub headers: Vec<(String, String)>,
+	/// Hex-encoded SHA-256 of the body.
+	pub body_sha256: String,
+	/// Left out when listing deliveries.
+	pub body: Option<String>,
+	pub outcome: Outcome,
+	pub error: Option<String>,
+}
+
+impl Delivery {
+	pub fn new(headers: &hyper::HeaderMap, body: &[u8]) -> Self {
+		let header = |name: &str| {
+			headers
+				.get(name)
+				.and_then(|v| v.to_str().ok())
+				.unwrap_or_default()
+				.to_owned()
+		};
+		Self {
+			id: header("x-github-delivery"),
+			event: header("x-github-event"),
+			received_at: Utc::now(),
+			headers: headers
+				.iter()
+				.map(|(name, value)| {
+					(
+						name.to_string(),
+						String::from_utf8_lossy(value.as_bytes()).into_owned(),
+					)
+				})
+				.collect(),
+			body_sha256: base16::encode_lower(
+				ring::digest::digest(&ring::digest::SHA256, body).as_ref(),
+			),
+			body: Some(String::from_utf8_lossy(body).into_owned()),
+			outcome: Outcome::Handled,
+			error: None,
+		}
+	}
+
+	/// Returns the delivery without its body, for listing.
+	pub fn summary(&self) -> Self {
+		Self {
+			body: None,
+			..self.clone()
+		}
+	}
+
+	fn key(&self) -> String {
+		// sorts by the time of receipt
+		format!("{:020}:{}", self.received_at.timestamp_nanos(), self.id)
+	}
+}
+
+impl Record for Delivery {
+	const CF: &'static str = "delivery_log";
+	const VERSION: u16 = 1;
+}
+
+/// What became of a logged delivery, kept under the key of its `Delivery` so that finishing a
+/// delivery does not write its headers and body a second time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeliveryOutcome {
+	pub outcome: Outcome,
+	pub error: Option<String>,
+}
+
+impl Record for DeliveryOutcome {
+	const CF: &'static str = "delivery_outcomes";
+	const VERSION: u16 = 1;
+}
+
+/// Adds a delivery to the log, dropping the oldest beyond `MAX_LOGGED_DELIVERIES`.
+pub fn log_delivery(storage: &Storage, delivery: &Delivery) -> Result<()> {
+	storage.put(&delivery.key(), delivery)?;
+	let keys = storage.keys::<Delivery>(Range::All)?;
+	let expired = keys.len().saturating_sub(MAX_LOGGED_DELIVERIES);
+	for key in keys.iter().take(expired) {
+		storage.delete::<Delivery>(key)?;
+		storage.delete::<DeliveryOutcome>(key)?;
+	}
+	Ok(())
+}
+
+/// Records the outcome of a delivery added with `log_delivery`, replacing the one it was logged
+/// with.
+pub fn log_outcome(
+	storage: &Storage,
+	delivery: &Delivery,
+	outcome: &DeliveryOutcome,
+) -> Result<()> {
+	storage.put(&delivery.key(), outcome)
+}
+
+/// Returns up to `limit` logged deliveries, most recent first.
+pub fn recent_deliveries(
+	storage: &Storage,
+	limit: usize,
+) -> Result<Vec<Delivery>> {
+	storage
+		.latest::<Delivery>(limit)?
+		.into_iter()
+		.map(|(key, mut delivery)| {
+			if let Some(DeliveryOutcome { outcome, error }) =
+				storage.get::<DeliveryOutcome>(&key)?
+			{
+				delivery.outcome = outcome;
+				delivery.error = error;
+			}
+			Ok(delivery)
+		})
+		.collect()
+}
+
+/// Returns the most recent logged delivery with the `X-GitHub-Delivery` ID `delivery_id`.
+pub fn find_delivery(
+	storage: &Storage,
+	delivery_id: &str,
+) -> Result<Option<Delivery>> {
+	Ok(recent_deliveries(storage, MAX_LOGGED_DELIVERIES)?
+		.into_iter()
+		.find(|delivery| delivery.id == delivery_id))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use chrono::Duration;
+
+	#[test]
+	fn test_deliveries() {
+		let db = Storage::temporary().expect("storage");
+		assert!(!delivery_seen(&db, "a").expect("seen"));
+		record_delivery(&db, "a").expect("record");
+		assert!(delivery_seen(&db, "a").expect("seen"));
+		assert!(!delivery_seen(&db, "b").expect("seen"));
+
+		// pretend "b" was handled a week ago
+		let old = Utc::now().timestamp() - 7 * 24 * 60 * 60;
+		db.put("b", &HandledDelivery { handled_at: old })
+			.expect("put");
+		db.put(
+			&delivery_time_key(old, "b"),
+			&HandledDeliveryTime {
+				delivery_id: "b".to_owned(),
+			},
+		)
+		.expect("put");
+		assert!(delivery_seen(&db, "b").expect("seen"));
+		record_delivery(&db, "c").expect("record");
+		assert!(!delivery_seen(&db, "b").expect("seen"));
+		assert!(delivery_seen(&db, "a").expect("seen"));
+		assert!(delivery_seen(&db, "c").expect("seen"));
+	}
+
+	#[test]
+	fn test_delivery_log() {
+		let db = Storage::temporary().expect("storage");
+		let mut headers = hyper::HeaderMap::new();
+		headers.insert("x-github-event", "ping".parse().expect("header"));
+		let received_at = Utc::now();
+		for i in 0..MAX_LOGGED_DELIVERIES + 5 {
+			headers.insert(
+				"x-github-delivery",
+				format!("delivery-{}", i).parse().expect("header"),
+			);
+			let mut delivery = Delivery::new(&headers, b"{}");
+			delivery.received_at = received_at + Duration::seconds(i as i64);
+			delivery.outcome = Outcome::Interrupted;
+			log_delivery(&db, &delivery).expect("log");
+			if i % 2 == 1 {
+				let outcome = DeliveryOutcome {
+					outcome: Outcome::Failed,
+					error: Some("Error: oops".to_owned()),
+				};
+				log_outcome(&db, &delivery, &outcome).expect("log outcome");
+			}
+		}
+
+		let recent = recent_deliveries(&db, 2).expect("recent");
+		assert_eq!(
+			recent.iter().map(|d| d.id.as_str()).collect::<Vec<&str>>(),
+			vec!["delivery-1004", "delivery-1003"]
+		);
+		assert_eq!(recent[0].event, "ping");
+		assert_eq!(recent[0].body.as_deref(), Some("{}"));
+		assert_eq!(
+			recent[0].body_sha256,
+			"44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+		);
+		assert_eq!(recent[0].outcome, Outcome::Interrupted);
+		assert_eq!(recent[1].outcome, Outcome::Failed);
+		assert_eq!(recent[1].error.as_deref(), Some("Error: oops"));
+		assert_eq!(recent[0].summary().body, None);
+
+		assert_eq!(
+			recent_deliveries(&db, usize::MAX).expect("all").len(),
+			MAX_LOGGED_DELIVERIES
+		);
+		assert!(find_delivery(&db, "delivery-4").expect("find").is_none());
+		assert!(find_delivery(&db, "delivery-5").expect("find").is_some());
+		// the outcomes of dropped deliveries go with them
+		assert_eq!(
+			db.keys::<DeliveryOutcome>(Range::All).expect("keys").len(),
+			MAX_LOGGED_DELIVERIES / 2
+		);
+	}
+}
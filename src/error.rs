@@ -48,6 +48,16 @@ pub enum Error {
 	#[snafu(display("Missing approval."))]
 	Approval {},
 
+	#[snafu(display("Work in progress: {}", reason))]
+	WorkInProgress {
+		reason: String,
+	},
+
+	#[snafu(display("Label requirements not met: {}", reason))]
+	Labels {
+		reason: String,
+	},
+
 	#[snafu(display("Error: {}", msg))]
 	Message {
 		msg: String,
@@ -60,6 +70,12 @@ pub enum Error {
 		body: serde_json::Value,
 	},
 
+	/// A GraphQL query returned errors instead of data.
+	#[snafu(display("GraphQL errors: {}", messages.join("; ")))]
+	Graphql {
+		messages: Vec<String>,
+	},
+
 	/// An error occurred while sending or receiving a HTTP request or response
 	/// respectively.
 	#[snafu(display("Source: {}", source))]
@@ -77,10 +93,10 @@ pub enum Error {
 	#[snafu(display("Missing data"))]
 	MissingData {},
 
-	/// An error occurred while retrieving or setting values in Rocks DB.
+	/// An error occurred while retrieving or setting values in the store.
 	#[snafu(display("Source: {}", source))]
 	Db {
-		source: rocksdb::Error,
+		source: Box<dyn std::error::Error + Send + Sync>,
 	},
 
 	/// An error occurred while parsing or serializing JSON.
@@ -114,6 +130,7 @@ pub enum Error {
 		body: Option<String>,
 	},
 
+	#[snafu(display("Failed creating JWT: {}", source))]
 	Jwt {
 		source: jsonwebtoken::errors::Error,
 	},
@@ -123,6 +140,12 @@ pub enum Error {
 		source: bincode::Error,
 	},
 
+	#[snafu(display("Unsupported version {} of record in {}", version, cf))]
+	RecordVersion {
+		cf: &'static str,
+		version: u16,
+	},
+
 	GitlabJobNotFound {
 		commit_sha: String,
 	},
@@ -173,6 +196,22 @@ impl Error {
 			issue: issue,
 		}
 	}
+
+	/// Returns why a merge failed or was refused, if that is what this error means, as a
+	/// metrics label.
+	pub fn merge_failure_reason(&self) -> Option<&'static str> {
+		match self {
+			Error::Merge { .. } => Some("rejected"),
+			Error::Companion { .. } => Some("companion"),
+			Error::ChecksFailed { .. } => Some("checks_failed"),
+			Error::HeadChanged { .. } => Some("head_changed"),
+			Error::ProcessInfo {} => Some("process_info"),
+			Error::Approval {} => Some("approval"),
+			Error::WorkInProgress { .. } => Some("work_in_progress"),
+			Error::Labels { .. } => Some("labels"),
+			_ => None,
+		}
+	}
 }
 
 impl From<curl::Error> for Error {
@@ -0,0 +1,251 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{
+	constants::*,
+	storage::{Range, Record, Storage},
+	Result,
+};
+
+/// A command or automation that can be turned off for an organization or a repository.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Feature {
+	/// `bot merge` and `bot merge force`, and merging the pull requests waiting for checks;
+	/// `bot merge cancel` works regardless
+	Merge,
+	/// `bot compare substrate`
+	Compare,
+	/// `bot rebase`
+	Rebase,
+	/// `bot burnin`
+	Burnin,
+	/// `bot backport` and `backport-to-<branch>` labels
+	Backport,
+	/// updating the companion of a merged pull request
+	Companion,
+	/// labels applied to pull requests changing matching paths
+	PathLabels,
+	/// the check run of pull requests changing a Process file
+	ProcessFileCheck,
+	/// the check run of pull requests changing `.github/processbot.toml`
+	RepoConfigCheck,
+}
+
+impl Feature {
+	pub const ALL: &'static [Feature] = &[
+		Feature::Merge,
+		Feature::Compare,
+		Feature::Rebase,
+		Feature::Burnin,
+		Feature::Backport,
+		Feature::Companion,
+		Feature::PathLabels,
+		Feature::ProcessFileCheck,
+		Feature::RepoConfigCheck,
+	];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Feature::Merge => "merge",
+			Feature::Compare => "compare",
+			Feature::Rebase => "rebase",
+			Feature::Burnin => "burnin",
+			Feature::Backport => "backport",
+			Feature::Companion => "companion",
+			Feature::PathLabels => "path-labels",
+			Feature::ProcessFileCheck => "process-file-check",
+			Feature::RepoConfigCheck => "repo-config-check",
+		}
+	}
+
+	/// Returns the feature of a command returned by `command_name`, or nothing if the command
+	/// cannot be turned off.
+	pub fn of_command(command: &str) -> Option<Feature> {
+		match command {
+			AUTO_MERGE_REQUEST | AUTO_MERGE_FORCE => Some(Feature::Merge),
+			COMPARE_RELEASE_REQUEST => Some(Feature::Compare),
+			REBASE => Some(Feature::Rebase),
+			BURNIN_REQUEST => Some(Feature::Burnin),
+			BACKPORT_REQUEST => Some(Feature::Backport),
+			_ => None,
+		}
+	}
+}
+
+impl fmt::Display for Feature {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.name())
+	}
+}
+
+impl FromStr for Feature {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Feature::ALL
+			.iter()
+			.find(|feature| feature.name() == s)
+			.copied()
+			.ok_or_else(|| {
+				format!(
+					"unknown feature `{}`; expected one of {}",
+					s,
+					Feature::ALL
+						.iter()
+						.map(|feature| feature.name())
+						.collect::<Vec<&str>>()
+						.join(", ")
+				)
+			})
+	}
+}
+
+/// Whether a feature is on in an organization, keyed `<owner>:<feature>`, or in a repository,
+/// keyed `<owner>/<repo>:<feature>`. Features without a toggle are on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeatureToggle {
+	pub enabled: bool,
+	pub changed_at: DateTime<Utc>,
+}
+
+impl Record for FeatureToggle {
+	const CF: &'static str = FEATURES_KEY;
+	const VERSION: u16 = 1;
+}
+
+/// A toggle as listed by the admin API.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScopedToggle {
+	/// `<owner>` or `<owner>/<repo>`
+	pub scope: String,
+	pub feature: Feature,
+	#[serde(flatten)]
+	pub toggle: FeatureToggle,
+}
+
+fn toggle_key(scope: &str, feature: Feature) -> String {
+	format!("{}:{}", scope, feature)
+}
+
+/// Returns whether `feature` is on in a repository: its own toggle takes precedence over that of
+/// its organization.
+pub fn enabled(
+	storage: &Storage,
+	owner: &str,
+	repo_name: &str,
+	feature: Feature,
+) -> Result<bool> {
+	for scope in [format!("{}/{}", owner, repo_name), owner.to_owned()].iter() {
+		if let Some(toggle) =
+			storage.get::<FeatureToggle>(&toggle_key(scope, feature))?
+		{
+			return Ok(toggle.enabled);
+		}
+	}
+	Ok(true)
+}
+
+/// Turns `feature` on or off in `scope`, or removes its toggle if `enabled` is `None`.
+pub fn set_toggle(
+	storage: &Storage,
+	scope: &str,
+	feature: Feature,
+	enabled: Option<bool>,
+) -> Result<()> {
+	let key = toggle_key(scope, feature);
+	match enabled {
+		Some(enabled) => storage.put(
+			&key,
+			&FeatureToggle {
+				enabled,
+				changed_at: Utc::now(),
+			},
+		),
+		None => storage.delete::<FeatureToggle>(&key),
+	}
+}
+
+/// Returns every toggle, ordered by key.
+pub fn toggles(storage: &Storage) -> Result<Vec<ScopedToggle>> {
+	Ok(storage
+		.scan::<FeatureToggle>(Range::All)?
+		.into_iter()
+		.filter_map(|(key, toggle)| {
+			let mut parts = key.rsplitn(2, ':');
+			let feature = parts.next()?;
+			let scope = parts.next()?;
+			match feature.parse() {
+				Ok(feature) => Some(ScopedToggle {
+					scope: scope.to_owned(),
+					feature,
+					toggle,
+				}),
+				Err(e) => {
+					log::warn!("Ignoring toggle {}: {}", key, e);
+					None
+				}
+			}
+		})
+		.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_toggles() {
+		let storage = Storage::temporary().expect("storage");
+		let is_enabled = |repo_name: &str, feature: Feature| {
+			enabled(&storage, "paritytech", repo_name, feature)
+				.expect("enabled")
+		};
+		assert!(is_enabled("substrate", Feature::Burnin));
+
+		set_toggle(&storage, "paritytech", Feature::Burnin, Some(false))
+			.expect("set");
+		set_toggle(
+			&storage,
+			"paritytech/polkadot",
+			Feature::Burnin,
+			Some(true),
+		)
+		.expect("set");
+		assert!(!is_enabled("substrate", Feature::Burnin));
+		assert!(is_enabled("polkadot", Feature::Burnin));
+		assert!(is_enabled("substrate", Feature::Rebase));
+		assert_eq!(
+			toggles(&storage)
+				.expect("toggles")
+				.iter()
+				.map(|t| (t.scope.as_str(), t.feature, t.toggle.enabled))
+				.collect::<Vec<_>>(),
+			vec![
+				("paritytech/polkadot", Feature::Burnin, true),
+				("paritytech", Feature::Burnin, false),
+			]
+		);
+
+		set_toggle(&storage, "paritytech", Feature::Burnin, None)
+			.expect("remove");
+		assert!(is_enabled("substrate", Feature::Burnin));
+	}
+
+	#[test]
+	fn test_feature_names() {
+		for feature in Feature::ALL {
+			assert_eq!(feature.name().parse(), Ok(*feature));
+			assert_eq!(
+				serde_json::to_value(feature).expect("serialize"),
+				serde_json::Value::String(feature.name().to_owned())
+			);
+		}
+		assert_eq!(Feature::of_command(AUTO_MERGE_FORCE), Some(Feature::Merge));
+		// a pending merge can always be cancelled
+		assert_eq!(Feature::of_command(AUTO_MERGE_CANCEL), None);
+		assert!("merges".parse::<Feature>().is_err());
+	}
+}
@@ -59,6 +59,13 @@ pub struct PullRequest {
 	pub repository: Option<Repository>,
 }
 
+impl PullRequest {
+	/// Returns the default branch of the repository the pull request is made to.
+	pub fn default_branch(&self) -> &str {
+		self.base.repo.default_branch.as_deref().unwrap_or("master")
+	}
+}
+
 impl GithubIssue for PullRequest {
 	fn number(&self) -> i64 {
 		self.number
@@ -151,6 +158,17 @@ impl GithubIssue for Issue {
 	}
 }
 
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PullRequestFile {
+	pub sha: Option<String>,
+	pub filename: String,
+	pub status: Option<String>,
+	pub additions: Option<i64>,
+	pub deletions: Option<i64>,
+	pub changes: Option<i64>,
+	pub previous_filename: Option<String>,
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Organization {
 	pub login: String,
@@ -310,6 +328,39 @@ pub struct Project {
 	pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectV2 {
+	pub id: Option<String>,
+	pub number: i64,
+	pub title: String,
+	pub closed: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectV2Item {
+	pub project: ProjectV2,
+	/// Value of the project's `Status` field, which replaces classic project columns.
+	#[serde(rename = "fieldValueByName")]
+	pub status: Option<ProjectV2FieldValue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectV2FieldValue {
+	pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphqlResponse<T> {
+	pub data: Option<T>,
+	#[serde(default)]
+	pub errors: Vec<GraphqlError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphqlError {
+	pub message: String,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ProjectCardContentType {
 	Issue,
@@ -744,6 +795,8 @@ pub enum IssueCommentAction {
 	Created,
 	Edited,
 	Deleted,
+	#[serde(other)]
+	Unknown,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -753,6 +806,8 @@ pub enum CheckRunAction {
 	Completed,
 	Rerequested,
 	RequestedAction,
+	#[serde(other)]
+	Unknown,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -797,6 +852,7 @@ pub struct HeadRepo {
 	pub url: String,
 	pub name: String,
 	pub owner: Option<User>,
+	pub default_branch: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -822,7 +878,7 @@ pub struct Branch {
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(untagged, rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
 pub enum PullRequestAction {
 	Opened,
 	Edited,
@@ -832,33 +888,41 @@ pub enum PullRequestAction {
 	ReviewRequested,
 	ReviewRequestRemoved,
 	ReadyForReview,
+	ConvertedToDraft,
 	Labeled,
 	Unlabeled,
+	#[serde(rename = "synchronize")]
 	Synchronized,
 	Locked,
 	Unlocked,
 	Reopened,
+	#[serde(other)]
+	Unknown,
 }
 
+/// A webhook payload, tagged by the event named in its `X-GitHub-Event` header; see
+/// `Payload::from_event`.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(untagged, rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
 pub enum Payload {
 	PullRequest {
 		action: PullRequestAction,
 		number: i64,
 		pull_request: PullRequest,
 		repository: Repository,
+		label: Option<Label>,
 	},
 	IssueComment {
 		action: IssueCommentAction,
 		issue: Issue,
 		comment: Comment,
 	},
+	#[serde(rename = "status")]
 	CommitStatus {
 		sha: String,
 		state: StatusState,
-		description: String,
-		target_url: String,
+		description: Option<String>,
+		target_url: Option<String>,
 		repository: Repository,
 		branches: Vec<Branch>,
 	},
@@ -866,4 +930,290 @@ pub enum Payload {
 		action: CheckRunAction,
 		check_run: CheckRun,
 	},
+	Push {
+		#[serde(rename = "ref")]
+		ref_field: String,
+		commits: Vec<PushCommit>,
+		repository: RepositoryRef,
+	},
+	Membership {
+		member: Login,
+		team: TeamRef,
+		organization: Login,
+	},
+	/// Only `member_added` and `member_removed` events have a membership.
+	#[serde(rename = "organization")]
+	OrganizationMembership {
+		membership: Option<Membership>,
+		organization: Login,
+	},
+	Team {
+		team: TeamRef,
+		organization: Login,
+	},
+}
+
+impl Payload {
+	/// `X-GitHub-Event` header values of the events the bot handles.
+	pub const EVENTS: &'static [&'static str] = &[
+		"pull_request",
+		"issue_comment",
+		"status",
+		"check_run",
+		"push",
+		"membership",
+		"organization",
+		"team",
+	];
+
+	/// Parses the body of a webhook whose `X-GitHub-Event` header is `event`, or returns `None`
+	/// for events the bot does not handle.
+	pub fn from_event(
+		event: &str,
+		body: &[u8],
+	) -> serde_json::Result<Option<Self>> {
+		if !Self::EVENTS.contains(&event) {
+			return Ok(None);
+		}
+		let body = serde_json::from_slice::<serde_json::Value>(body)?;
+		serde_json::from_value(serde_json::json!({ event: body })).map(Some)
+	}
+}
+
+/// The installation a webhook was delivered for; sent with every event once the app is
+/// installed on more than one account.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookInstallation {
+	pub installation: Option<InstallationRef>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstallationRef {
+	pub id: i64,
+}
+
+/// A repository as it appears in push events, whose timestamps are not formatted like the REST
+/// API's.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RepositoryRef {
+	pub name: String,
+	pub owner: Login,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Login {
+	pub login: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TeamRef {
+	pub id: i64,
+	pub slug: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Membership {
+	pub user: Login,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PushCommit {
+	#[serde(default)]
+	pub added: Vec<String>,
+	#[serde(default)]
+	pub removed: Vec<String>,
+	#[serde(default)]
+	pub modified: Vec<String>,
+}
+
+impl PushCommit {
+	pub fn paths(&self) -> impl Iterator<Item = &String> {
+		self.added
+			.iter()
+			.chain(self.removed.iter())
+			.chain(self.modified.iter())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(event: &str, fixture: &str) -> Option<Payload> {
+		Payload::from_event(event, fixture.as_bytes())
+			.unwrap_or_else(|e| panic!("parsing {} event: {}", event, e))
+	}
+
+	#[test]
+	fn test_payload_fixtures() {
+		assert!(matches!(
+			parse(
+				"pull_request",
+				include_str!("../fixtures/webhooks/pull_request.opened.json")
+			),
+			Some(Payload::PullRequest {
+				action: PullRequestAction::Opened,
+				number: 2,
+				..
+			})
+		));
+		assert!(matches!(
+			parse(
+				"pull_request",
+				include_str!(
+					"../fixtures/webhooks/pull_request.synchronize.json"
+				)
+			),
+			Some(Payload::PullRequest {
+				action: PullRequestAction::Synchronized,
+				..
+			})
+		));
+		assert!(matches!(
+			parse(
+				"pull_request",
+				include_str!("../fixtures/webhooks/pull_request.labeled.json")
+			),
+			Some(Payload::PullRequest {
+				action: PullRequestAction::Labeled,
+				label: Some(Label { ref name, .. }),
+				..
+			}) if name == "bug"
+		));
+		assert!(matches!(
+			parse(
+				"pull_request",
+				include_str!("../fixtures/webhooks/pull_request.closed.json")
+			),
+			Some(Payload::PullRequest {
+				action: PullRequestAction::Closed,
+				pull_request: PullRequest {
+					merged: Some(false),
+					..
+				},
+				..
+			})
+		));
+		assert!(matches!(
+			parse(
+				"pull_request",
+				include_str!("../fixtures/webhooks/pull_request.assigned.json")
+			),
+			Some(Payload::PullRequest {
+				action: PullRequestAction::Assigned,
+				..
+			})
+		));
+		assert!(matches!(
+			parse(
+				"issue_comment",
+				include_str!("../fixtures/webhooks/issue_comment.created.json")
+			),
+			Some(Payload::IssueComment {
+				action: IssueCommentAction::Created,
+				comment: Comment { ref body, .. },
+				issue: Issue {
+					pull_request: None,
+					..
+				},
+			}) if body.starts_with("You are totally right!")
+		));
+		assert!(matches!(
+			parse("status", include_str!("../fixtures/webhooks/status.json")),
+			Some(Payload::CommitStatus {
+				state: StatusState::Success,
+				description: None,
+				..
+			})
+		));
+		assert!(matches!(
+			parse(
+				"check_run",
+				include_str!("../fixtures/webhooks/check_run.completed.json")
+			),
+			Some(Payload::CheckRun {
+				action: CheckRunAction::Completed,
+				check_run: CheckRun {
+					ref status,
+					ref pull_requests,
+					..
+				},
+			}) if status == "completed" && pull_requests.len() == 1
+		));
+		assert!(matches!(
+			parse("push", include_str!("../fixtures/webhooks/push.json")),
+			Some(Payload::Push { ref ref_field, ref commits, .. })
+				if ref_field == "refs/tags/simple-tag" && commits.is_empty()
+		));
+		assert!(matches!(
+			parse(
+				"membership",
+				include_str!("../fixtures/webhooks/membership.added.json")
+			),
+			Some(Payload::Membership {
+				member: Login { ref login },
+				team: TeamRef { id: 3253328, .. },
+				..
+			}) if login == "Codertocat"
+		));
+		assert!(matches!(
+			parse(
+				"organization",
+				include_str!(
+					"../fixtures/webhooks/organization.member_added.json"
+				)
+			),
+			Some(Payload::OrganizationMembership {
+				membership: Some(_),
+				..
+			})
+		));
+		assert!(matches!(
+			parse(
+				"organization",
+				include_str!(
+					"../fixtures/webhooks/organization.member_invited.json"
+				)
+			),
+			Some(Payload::OrganizationMembership {
+				membership: None,
+				..
+			})
+		));
+		assert!(matches!(
+			parse(
+				"team",
+				include_str!("../fixtures/webhooks/team.edited.json")
+			),
+			Some(Payload::Team { .. })
+		));
+		assert_eq!(
+			parse("ping", include_str!("../fixtures/webhooks/ping.json")),
+			None
+		);
+	}
+
+	#[test]
+	fn test_push_commit_paths() {
+		let commit = PushCommit {
+			added: vec!["Process.json".to_owned()],
+			removed: vec![],
+			modified: vec!["README.md".to_owned()],
+		};
+		assert_eq!(
+			commit.paths().collect::<Vec<_>>(),
+			vec!["Process.json", "README.md"]
+		);
+	}
+
+	#[test]
+	fn test_payload_mismatched_event() {
+		// a payload is only parsed as the event it was delivered for
+		assert!(Payload::from_event(
+			"issue_comment",
+			include_str!("../fixtures/webhooks/pull_request.opened.json")
+				.as_bytes()
+		)
+		.is_err());
+	}
 }
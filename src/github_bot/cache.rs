@@ -0,0 +1,148 @@
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::metrics;
+
+/// Values kept for a limited time, counting how often lookups find them. Expired values are
+/// dropped when a value is inserted, so the cache only holds what was fetched within the TTL.
+pub struct Cache<V> {
+	name: &'static str,
+	ttl: Duration,
+	entries: Mutex<HashMap<String, (Instant, V)>>,
+	hits: AtomicU64,
+	misses: AtomicU64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CacheStats {
+	pub name: &'static str,
+	pub entries: usize,
+	pub hits: u64,
+	pub misses: u64,
+}
+
+impl CacheStats {
+	/// Fraction of lookups that found a value, or 0 if there were none.
+	pub fn hit_rate(&self) -> f64 {
+		let lookups = self.hits + self.misses;
+		if lookups == 0 {
+			0.0
+		} else {
+			self.hits as f64 / lookups as f64
+		}
+	}
+}
+
+impl<V: Clone> Cache<V> {
+	pub fn new(name: &'static str, ttl: Duration) -> Self {
+		Self {
+			name,
+			ttl,
+			entries: Mutex::new(HashMap::new()),
+			hits: AtomicU64::new(0),
+			misses: AtomicU64::new(0),
+		}
+	}
+
+	/// Returns the value for `key` if it has not expired.
+	pub fn get(&self, key: &str) -> Option<V> {
+		let mut entries = self.entries.lock();
+		let value = match entries.get(key) {
+			Some((inserted, value)) if inserted.elapsed() < self.ttl => {
+				Some(value.clone())
+			}
+			Some(_) => {
+				entries.remove(key);
+				None
+			}
+			None => None,
+		};
+		if value.is_some() {
+			self.hits.fetch_add(1, Ordering::Relaxed);
+			metrics::CACHE_LOOKUPS.inc(&[self.name, "hit"]);
+		} else {
+			self.misses.fetch_add(1, Ordering::Relaxed);
+			metrics::CACHE_LOOKUPS.inc(&[self.name, "miss"]);
+		}
+		self.set_entries_gauge(entries.len());
+		value
+	}
+
+	/// Inserts a value, dropping those that have expired.
+	pub fn insert(&self, key: String, value: V) {
+		let mut entries = self.entries.lock();
+		let ttl = self.ttl;
+		entries.retain(|_, (inserted, _)| inserted.elapsed() < ttl);
+		entries.insert(key, (Instant::now(), value));
+		self.set_entries_gauge(entries.len());
+	}
+
+	pub fn invalidate(&self, key: &str) {
+		let mut entries = self.entries.lock();
+		entries.remove(key);
+		self.set_entries_gauge(entries.len());
+	}
+
+	/// Removes every value whose key starts with `prefix`.
+	pub fn invalidate_prefix(&self, prefix: &str) {
+		let mut entries = self.entries.lock();
+		entries.retain(|key, _| !key.starts_with(prefix));
+		self.set_entries_gauge(entries.len());
+	}
+
+	fn set_entries_gauge(&self, len: usize) {
+		metrics::CACHE_ENTRIES.set(&[self.name], len as f64);
+	}
+
+	pub fn stats(&self) -> CacheStats {
+		CacheStats {
+			name: self.name,
+			entries: self.entries.lock().len(),
+			hits: self.hits.load(Ordering::Relaxed),
+			misses: self.misses.load(Ordering::Relaxed),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_cache() {
+		let cache = Cache::new("test", Duration::from_secs(60));
+		assert_eq!(cache.get("paritytech/substrate"), None);
+		cache.insert("paritytech/substrate".to_string(), 1);
+		cache.insert("paritytech/polkadot".to_string(), 2);
+		assert_eq!(cache.get("paritytech/substrate"), Some(1));
+		cache.invalidate_prefix("paritytech/sub");
+		assert_eq!(cache.get("paritytech/substrate"), None);
+		assert_eq!(cache.get("paritytech/polkadot"), Some(2));
+		assert_eq!(
+			cache.stats(),
+			CacheStats {
+				name: "test",
+				entries: 1,
+				hits: 2,
+				misses: 2,
+			}
+		);
+		assert_eq!(cache.stats().hit_rate(), 0.5);
+
+		assert_eq!(metrics::CACHE_LOOKUPS.get(&["test", "hit"]), 2);
+		assert_eq!(metrics::CACHE_LOOKUPS.get(&["test", "miss"]), 2);
+
+		let expired = Cache::new("expired", Duration::from_secs(0));
+		expired.insert("paritytech/substrate".to_string(), 1);
+		assert_eq!(expired.get("paritytech/substrate"), None);
+		assert_eq!(expired.stats().entries, 0);
+
+		let expiring = Cache::new("expiring", Duration::from_millis(50));
+		expiring.insert("paritytech/substrate".to_string(), 1);
+		std::thread::sleep(Duration::from_millis(60));
+		expiring.insert("paritytech/polkadot".to_string(), 2);
+		assert_eq!(expiring.stats().entries, 1);
+	}
+}
@@ -17,7 +17,7 @@ impl GithubBot {
 		self.client
 			.get(format!(
 				"{base_url}/repos/{owner}/{repo}/issues/{number}",
-				base_url = Self::BASE_URL,
+				base_url = self.base_url,
 				owner = owner,
 				repo = repo.name,
 				number = number
@@ -50,7 +50,7 @@ impl GithubBot {
 				.map(|num| {
 					self.client.get(format!(
 						"{base_url}/repos/{owner}/{repo}/issues/{issue_number}",
-						base_url = Self::BASE_URL,
+						base_url = self.base_url,
 						owner = owner,
 						repo = &repo_name,
 						issue_number = num
@@ -73,7 +73,7 @@ impl GithubBot {
 		self.client
 			.get_all(format!(
 				"{base_url}/repos/{owner}/{repo_name}/issues/{issue_number}/events",
-				base_url = Self::BASE_URL,
+				base_url = self.base_url,
 				owner = owner,
 				repo_name = repo_name,
 				issue_number = issue_number
@@ -115,7 +115,7 @@ impl GithubBot {
 	) -> Result<github::Issue> {
 		let url = format!(
 			"{base_url}/repos/{owner}/{repo}/issues",
-			base_url = Self::BASE_URL,
+			base_url = self.base_url,
 			owner = owner,
 			repo = repo_name,
 		);
@@ -141,7 +141,7 @@ impl GithubBot {
 	) -> Result<Vec<github::Comment>> {
 		let url = format!(
 			"{base}/repos/{owner}/{repo}/issues/{issue_number}/comments",
-			base = Self::BASE_URL,
+			base = self.base_url,
 			owner = owner,
 			repo = repo_name,
 			issue_number = issue_number
@@ -159,7 +159,7 @@ impl GithubBot {
 	) -> Result<()> {
 		let url = format!(
 			"{base}/repos/{owner}/{repo}/issues/{issue_number}/comments",
-			base = Self::BASE_URL,
+			base = self.base_url,
 			owner = owner,
 			repo = repo_name,
 			issue_number = issue_number
@@ -183,7 +183,7 @@ impl GithubBot {
 	{
 		let url = format!(
 			"{base_url}/repos/{owner}/{repo}/issues/{issue_number}/assignees",
-			base_url = Self::BASE_URL,
+			base_url = self.base_url,
 			owner = owner,
 			repo = repo_name.as_ref(),
 			issue_number = issue_number
@@ -208,7 +208,7 @@ impl GithubBot {
 	{
 		let url = format!(
 			"{base_url}/repos/{owner}/{repo}/issues/{issue_number}",
-			base_url = Self::BASE_URL,
+			base_url = self.base_url,
 			owner = owner,
 			repo = repo_name.as_ref(),
 			issue_number = issue_number
@@ -232,20 +232,28 @@ mod tests {
 	fn test_issues() {
 		dotenv::dotenv().ok();
 
-		let installation = dotenv::var("TEST_INSTALLATION_LOGIN")
-			.expect("TEST_INSTALLATION_LOGIN");
+		let installation =
+			dotenv::var("INSTALLATION_LOGIN").expect("INSTALLATION_LOGIN");
 		let private_key_path =
 			dotenv::var("PRIVATE_KEY_PATH").expect("PRIVATE_KEY_PATH");
-		let private_key = std::fs::read(&private_key_path)
-			.expect("Couldn't find private key.");
+		let private_key = jsonwebtoken::EncodingKey::from_rsa_pem(
+			&std::fs::read(&private_key_path)
+				.expect("Couldn't find private key."),
+		)
+		.expect("private key");
+		let app_id = dotenv::var("GITHUB_APP_ID")
+			.expect("GITHUB_APP_ID")
+			.parse::<u64>()
+			.expect("parse GITHUB_APP_ID");
 		let test_repo_name =
 			dotenv::var("TEST_REPO_NAME").expect("TEST_REPO_NAME");
 
 		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
 		rt.block_on(async {
-			let github_bot = GithubBot::new(private_key, &installation)
-				.await
-				.expect("github_bot");
+			let github_bot =
+				GithubBot::new(app_id, private_key, Some(&installation))
+					.await
+					.expect("github_bot");
 			let repo = github_bot
 				.repository(&test_repo_name)
 				.await
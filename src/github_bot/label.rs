@@ -0,0 +1,76 @@
+use crate::{error::*, github, Result};
+
+use url::Url;
+
+use super::GithubBot;
+
+impl GithubBot {
+	/// Returns the labels on an issue or pull request.
+	pub async fn labels(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		issue_number: i64,
+	) -> Result<Vec<github::Label>> {
+		self.client
+			.get_all(format!(
+				"{base_url}/repos/{owner}/{repo}/issues/{issue_number}/labels",
+				base_url = self.base_url,
+				owner = owner,
+				repo = repo_name,
+				issue_number = issue_number
+			))
+			.await
+	}
+
+	/// Adds labels to an issue or pull request.
+	pub async fn add_labels(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		issue_number: i64,
+		labels: &[String],
+	) -> Result<()> {
+		let url = format!(
+			"{base_url}/repos/{owner}/{repo}/issues/{issue_number}/labels",
+			base_url = self.base_url,
+			owner = owner,
+			repo = repo_name,
+			issue_number = issue_number
+		);
+		self.client
+			.post_response(&url, &serde_json::json!({ "labels": labels }))
+			.await
+			.map(|_| ())
+	}
+
+	/// Removes a label from an issue or pull request.
+	pub async fn remove_label(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		issue_number: i64,
+		label: &str,
+	) -> Result<()> {
+		// label names may contain spaces and other characters that need escaping
+		let mut url = Url::parse(&self.base_url)
+			.map_err(|e| Error::ParseUrl { source: e })?;
+		url.path_segments_mut()
+			.map_err(|()| Error::UrlCannotBeBase {
+				url: self.base_url.to_string(),
+			})?
+			.extend(&[
+				"repos",
+				owner,
+				repo_name,
+				"issues",
+				&issue_number.to_string(),
+				"labels",
+				label,
+			]);
+		self.client
+			.delete_response(url.as_str(), &serde_json::json!({}))
+			.await
+			.map(|_| ())
+	}
+}
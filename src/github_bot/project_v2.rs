@@ -0,0 +1,114 @@
+use crate::{github, Result};
+
+use serde::Deserialize;
+
+use super::GithubBot;
+
+const REPOSITORY_PROJECTS_QUERY: &str = r#"
+query($owner: String!, $repo: String!) {
+	repository(owner: $owner, name: $repo) {
+		projectsV2(first: 100) {
+			nodes { id number title closed }
+		}
+	}
+}
+"#;
+
+const PULL_REQUEST_PROJECT_ITEMS_QUERY: &str = r#"
+query($owner: String!, $repo: String!, $number: Int!) {
+	repository(owner: $owner, name: $repo) {
+		pullRequest(number: $number) {
+			projectItems(first: 100) {
+				nodes {
+					project { id number title closed }
+					fieldValueByName(name: "Status") {
+						... on ProjectV2ItemFieldSingleSelectValue { name }
+					}
+				}
+			}
+		}
+	}
+}
+"#;
+
+#[derive(Deserialize)]
+struct Nodes<T> {
+	nodes: Vec<Option<T>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RepositoryProjects {
+	repository: Option<RepositoryProjectsInner>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RepositoryProjectsInner {
+	projects_v2: Nodes<github::ProjectV2>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PullRequestProjectItems {
+	repository: Option<PullRequestProjectItemsRepository>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PullRequestProjectItemsRepository {
+	pull_request: Option<PullRequestProjectItemsInner>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PullRequestProjectItemsInner {
+	project_items: Nodes<github::ProjectV2Item>,
+}
+
+impl GithubBot {
+	/// Returns projects (v2) linked to a repository.
+	pub async fn projects_v2(
+		&self,
+		owner: &str,
+		repo_name: &str,
+	) -> Result<Vec<github::ProjectV2>> {
+		let data: RepositoryProjects = self
+			.client
+			.graphql(
+				REPOSITORY_PROJECTS_QUERY,
+				serde_json::json!({ "owner": owner, "repo": repo_name }),
+			)
+			.await?;
+		Ok(data
+			.repository
+			.map(|repo| repo.projects_v2.nodes.into_iter().flatten().collect())
+			.unwrap_or_default())
+	}
+
+	/// Returns the project (v2) items of a pull request, ie. the projects it has been added to and
+	/// its status in each.
+	pub async fn pull_request_project_items(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		number: i64,
+	) -> Result<Vec<github::ProjectV2Item>> {
+		let data: PullRequestProjectItems = self
+			.client
+			.graphql(
+				PULL_REQUEST_PROJECT_ITEMS_QUERY,
+				serde_json::json!({
+					"owner": owner,
+					"repo": repo_name,
+					"number": number,
+				}),
+			)
+			.await?;
+		Ok(data
+			.repository
+			.and_then(|repo| repo.pull_request)
+			.map(|pr| pr.project_items.nodes.into_iter().flatten().collect())
+			.unwrap_or_default())
+	}
+}
@@ -24,7 +24,7 @@ impl GithubBot {
 		self.client
 			.get(format!(
 				"{base_url}/repos/{owner}/{repo}/pulls/{pull_number}",
-				base_url = Self::BASE_URL,
+				base_url = self.base_url,
 				owner = owner,
 				repo = repo_name,
 				pull_number = pull_number
@@ -41,7 +41,7 @@ impl GithubBot {
 		self.client
 			.get_all(format!(
 				"{base_url}/repos/{owner}/{repo}/pulls?head={head}",
-				base_url = Self::BASE_URL,
+				base_url = self.base_url,
 				owner = owner,
 				repo = repo_name,
 				head = head,
@@ -50,6 +50,24 @@ impl GithubBot {
 			.map(|v| v.first().cloned())
 	}
 
+	/// Returns the files changed by a pull request.
+	pub async fn pull_request_files(
+		&self,
+		owner: &str,
+		repo_name: &str,
+		pull_number: i64,
+	) -> Result<Vec<github::PullRequestFile>> {
+		self.client
+			.get_all(format!(
+				"{base_url}/repos/{owner}/{repo}/pulls/{pull_number}/files",
+				base_url = self.base_url,
+				owner = owner,
+				repo = repo_name,
+				pull_number = pull_number
+			))
+			.await
+	}
+
 	/// Creates a new pull request to merge `head` into `base`.
 	pub async fn create_pull_request<A>(
 		&self,
@@ -65,7 +83,7 @@ impl GithubBot {
 	{
 		let url = format!(
 			"{base_url}/repos/{owner}/{repo}/pulls",
-			base_url = Self::BASE_URL,
+			base_url = self.base_url,
 			owner = owner,
 			repo = repo_name.as_ref(),
 		);
@@ -93,7 +111,7 @@ impl GithubBot {
 	) -> Result<()> {
 		let url = format!(
 			"{base_url}/repos/{owner}/{repo}/pulls/{number}/merge",
-			base_url = Self::BASE_URL,
+			base_url = self.base_url,
 			owner = owner,
 			repo = repo_name,
 			number = number,
@@ -117,7 +135,7 @@ impl GithubBot {
 	{
 		let url = format!(
 			"{base_url}/repos/{owner}/{repo}/pulls/{pull_number}",
-			base_url = Self::BASE_URL,
+			base_url = self.base_url,
 			owner = owner,
 			repo = repo_name.as_ref(),
 			pull_number = pull_number
@@ -138,18 +156,26 @@ mod tests {
 	fn test_get_pr() {
 		dotenv::dotenv().ok();
 
-		let installation = dotenv::var("TEST_INSTALLATION_LOGIN")
-			.expect("TEST_INSTALLATION_LOGIN");
+		let installation =
+			dotenv::var("INSTALLATION_LOGIN").expect("INSTALLATION_LOGIN");
 		let private_key_path =
 			dotenv::var("PRIVATE_KEY_PATH").expect("PRIVATE_KEY_PATH");
-		let private_key = std::fs::read(&private_key_path)
-			.expect("Couldn't find private key.");
+		let private_key = jsonwebtoken::EncodingKey::from_rsa_pem(
+			&std::fs::read(&private_key_path)
+				.expect("Couldn't find private key."),
+		)
+		.expect("private key");
+		let app_id = dotenv::var("GITHUB_APP_ID")
+			.expect("GITHUB_APP_ID")
+			.parse::<u64>()
+			.expect("parse GITHUB_APP_ID");
 
 		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
 		rt.block_on(async {
-			let github_bot = GithubBot::new(private_key, &installation)
-				.await
-				.expect("github_bot");
+			let github_bot =
+				GithubBot::new(app_id, private_key, Some(&installation))
+					.await
+					.expect("github_bot");
 			let _ = dbg!(
 				github_bot
 					.pull_request("paritytech", "substrate", 6276)
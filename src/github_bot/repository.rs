@@ -14,7 +14,7 @@ impl GithubBot {
 	{
 		let url = format!(
 			"{base_url}/repos/{owner}/{repo_name}",
-			base_url = Self::BASE_URL,
+			base_url = self.base_url,
 			owner = owner,
 			repo_name = repo_name
 		);
@@ -32,20 +32,28 @@ mod tests {
 	fn test_repositories() {
 		dotenv::dotenv().ok();
 
-		let installation = dotenv::var("TEST_INSTALLATION_LOGIN")
-			.expect("TEST_INSTALLATION_LOGIN");
+		let installation =
+			dotenv::var("INSTALLATION_LOGIN").expect("INSTALLATION_LOGIN");
 		let private_key_path =
 			dotenv::var("PRIVATE_KEY_PATH").expect("PRIVATE_KEY_PATH");
-		let private_key = std::fs::read(&private_key_path)
-			.expect("Couldn't find private key.");
+		let private_key = jsonwebtoken::EncodingKey::from_rsa_pem(
+			&std::fs::read(&private_key_path)
+				.expect("Couldn't find private key."),
+		)
+		.expect("private key");
+		let app_id = dotenv::var("GITHUB_APP_ID")
+			.expect("GITHUB_APP_ID")
+			.parse::<u64>()
+			.expect("parse GITHUB_APP_ID");
 		let test_repo_name =
 			dotenv::var("TEST_REPO_NAME").expect("TEST_REPO_NAME");
 
 		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
 		rt.block_on(async {
-			let github_bot = GithubBot::new(private_key, &installation)
-				.await
-				.expect("github_bot");
+			let github_bot =
+				GithubBot::new(app_id, private_key, Some(&installation))
+					.await
+					.expect("github_bot");
 			assert!(github_bot.repository(&test_repo_name).await.is_ok());
 		});
 	}
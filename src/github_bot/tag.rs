@@ -12,7 +12,7 @@ impl GithubBot {
 	) -> Result<github::Ref> {
 		let url = format!(
 			"{base_url}/repos/{owner}/{repo}/git/ref/tags/{tag_name}",
-			base_url = Self::BASE_URL,
+			base_url = self.base_url,
 			owner = owner,
 			repo = repo_name,
 			tag_name = tag_name,
@@ -30,19 +30,27 @@ mod tests {
 	#[test]
 	fn test_tag() {
 		dotenv::dotenv().ok();
-		let installation = dotenv::var("TEST_INSTALLATION_LOGIN")
-			.expect("TEST_INSTALLATION_LOGIN");
+		let installation =
+			dotenv::var("INSTALLATION_LOGIN").expect("INSTALLATION_LOGIN");
 		let private_key_path =
 			dotenv::var("PRIVATE_KEY_PATH").expect("PRIVATE_KEY_PATH");
-		let private_key = std::fs::read(&private_key_path)
-			.expect("Couldn't find private key.");
+		let private_key = jsonwebtoken::EncodingKey::from_rsa_pem(
+			&std::fs::read(&private_key_path)
+				.expect("Couldn't find private key."),
+		)
+		.expect("private key");
+		let app_id = dotenv::var("GITHUB_APP_ID")
+			.expect("GITHUB_APP_ID")
+			.parse::<u64>()
+			.expect("parse GITHUB_APP_ID");
 		let test_repo_name =
 			dotenv::var("TEST_REPO_NAME").expect("TEST_REPO_NAME");
 		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
 		rt.block_on(async {
-			let github_bot = GithubBot::new(private_key, &installation)
-				.await
-				.expect("github_bot");
+			let github_bot =
+				GithubBot::new(app_id, private_key, Some(&installation))
+					.await
+					.expect("github_bot");
 			let release = dbg!(github_bot
 				.latest_release(&test_repo_name)
 				.await
@@ -5,13 +5,19 @@ use super::GithubBot;
 impl GithubBot {
 	/// Returns the team with a given team slug (eg. 'core-devs').
 	pub async fn team(&self, owner: &str, slug: &str) -> Result<github::Team> {
+		let key = format!("{}/{}", owner, slug);
+		if let Some(team) = self.team_cache.get(&key) {
+			return Ok(team);
+		}
 		let url = format!(
 			"{base_url}/orgs/{owner}/teams/{slug}",
-			base_url = Self::BASE_URL,
+			base_url = self.base_url,
 			owner = owner,
 			slug = slug
 		);
-		self.client.get(url).await
+		let team: github::Team = self.client.get(url).await?;
+		self.team_cache.insert(key, team.clone());
+		Ok(team)
 	}
 
 	/// Returns members of the team with a id.
@@ -19,9 +25,16 @@ impl GithubBot {
 		&self,
 		team_id: i64,
 	) -> Result<Vec<github::User>> {
-		self.client
-			.get_all(format!("{}/teams/{}/members", Self::BASE_URL, team_id))
-			.await
+		let key = team_id.to_string();
+		if let Some(members) = self.team_members_cache.get(&key) {
+			return Ok(members);
+		}
+		let members: Vec<github::User> = self
+			.client
+			.get_all(format!("{}/teams/{}/members", self.base_url, team_id))
+			.await?;
+		self.team_members_cache.insert(key, members.clone());
+		Ok(members)
 	}
 }
 
@@ -35,18 +48,26 @@ mod tests {
 	fn test_teams() {
 		dotenv::dotenv().ok();
 
-		let installation = dotenv::var("TEST_INSTALLATION_LOGIN")
-			.expect("TEST_INSTALLATION_LOGIN");
+		let installation =
+			dotenv::var("INSTALLATION_LOGIN").expect("INSTALLATION_LOGIN");
 		let private_key_path =
 			dotenv::var("PRIVATE_KEY_PATH").expect("PRIVATE_KEY_PATH");
-		let private_key = std::fs::read(&private_key_path)
-			.expect("Couldn't find private key.");
+		let private_key = jsonwebtoken::EncodingKey::from_rsa_pem(
+			&std::fs::read(&private_key_path)
+				.expect("Couldn't find private key."),
+		)
+		.expect("private key");
+		let app_id = dotenv::var("GITHUB_APP_ID")
+			.expect("GITHUB_APP_ID")
+			.parse::<u64>()
+			.expect("parse GITHUB_APP_ID");
 
 		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
 		rt.block_on(async {
-			let github_bot = GithubBot::new(private_key, &installation)
-				.await
-				.expect("github_bot");
+			let github_bot =
+				GithubBot::new(app_id, private_key, Some(&installation))
+					.await
+					.expect("github_bot");
 			let team = github_bot.team("core-devs").await.expect("team");
 			let _members = github_bot
 				.team_members(team.id)
@@ -56,8 +56,15 @@ impl GitlabBot {
 		})
 	}
 
-	pub fn build_artifact(&self, commit_sha: &str) -> Result<Job> {
-		let job = self.fetch_job(commit_sha)?;
+	/// Starts the CI job building the binary of a commit, which is named `ci_job_name` unless
+	/// a repository overrides it.
+	pub fn build_artifact(
+		&self,
+		commit_sha: &str,
+		ci_job_name: Option<&str>,
+	) -> Result<Job> {
+		let job = self
+			.fetch_job(commit_sha, ci_job_name.unwrap_or(&self.ci_job_name))?;
 
 		// JobStatus is used by the caller to decide what message to post on Github/Matrix.
 		let status = match job.status.to_lowercase().trim() {
@@ -89,12 +96,16 @@ impl GitlabBot {
 		})
 	}
 
-	fn fetch_job(&self, commit_sha: &str) -> Result<GitlabJob> {
+	fn fetch_job(
+		&self,
+		commit_sha: &str,
+		ci_job_name: &str,
+	) -> Result<GitlabJob> {
 		let pipeline = self.fetch_pipeline_for_commit(commit_sha)?;
 		let jobs = self.fetch_jobs_for_pipeline(pipeline.id)?;
 
 		for job in jobs {
-			if job.name == self.ci_job_name {
+			if job.name == ci_job_name {
 				return Ok(job);
 			}
 		}
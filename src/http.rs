@@ -1,23 +1,141 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::SystemTime;
 
-use crate::{error, github, Result};
+use crate::{error, github, github_bot::cache::Cache, metrics, Result};
 
 use chrono::{DateTime, Duration, Utc};
 use hyperx::header::TypedHeaders;
-use reqwest::{header, IntoUrl, Method, RequestBuilder, Response};
+use reqwest::{header, IntoUrl, Method, RequestBuilder, Response, StatusCode};
 use serde::Serialize;
 use snafu::{OptionExt, ResultExt};
 
-#[derive(Default)]
+/// Requests are retried at most this many times.
+const MAX_RETRIES: u32 = 5;
+/// Retries of a request wait at most this long in total. Webhooks are handled one at a time
+/// while holding the state of the bot, so every other webhook waits on the retries too.
+const MAX_RETRY_WAIT: std::time::Duration = std::time::Duration::from_secs(15);
+/// Installations are rarely added or removed, so their IDs are kept a while.
+const INSTALLATION_IDS_TTL: std::time::Duration =
+	std::time::Duration::from_secs(60 * 60);
+/// Below this many remaining requests, low-priority work is deferred until the quota resets.
+const LOW_QUOTA: u64 = 500;
+/// App JWTs are backdated by this many seconds in case our clock is ahead of GitHub's.
+const JWT_CLOCK_SKEW_SECS: i64 = 60;
+/// GitHub refuses app JWTs valid for longer than ten minutes.
+const JWT_LIFETIME_SECS: i64 = 9 * 60;
+
+/// A client making requests on behalf of one installation of the app. Clients made by
+/// `for_installation` and `for_account` share their access tokens, quotas and installation IDs.
+#[derive(Clone)]
 pub struct Client {
 	pub client: reqwest::Client,
-	private_key: Vec<u8>,
-	installation_login: String,
+	shared: Arc<Shared>,
+	/// Installation requests are made on behalf of, or `None` for that of `installation_login`.
+	installation: Option<i64>,
+}
+
+struct Shared {
+	app_id: u64,
+	encoding_key: jsonwebtoken::EncodingKey,
+	/// Account whose installation is used by clients made for no installation, eg. for
+	/// webhooks without one.
+	installation_login: Option<String>,
+	/// REST API root, eg. `https://api.github.com`.
+	base_url: String,
+	/// Installation IDs by account login, as listed by `/app/installations`.
+	installation_ids: Cache<i64>,
+	/// Installation access tokens and their expiry, by installation ID.
+	tokens: parking_lot::Mutex<HashMap<i64, (DateTime<Utc>, String)>>,
+	/// Each installation has its own quota.
+	rate_limits: parking_lot::Mutex<HashMap<i64, RateLimit>>,
+}
+
+/// The REST API quota reported by the last response from GitHub.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RateLimit {
+	pub limit: u64,
+	pub remaining: u64,
+	pub reset: DateTime<Utc>,
+}
+
+impl RateLimit {
+	fn from_headers(headers: &header::HeaderMap) -> Option<Self> {
+		let value = |name: &str| {
+			headers
+				.get(name)
+				.and_then(|v| v.to_str().ok())
+				.and_then(|v| v.parse::<u64>().ok())
+		};
+		Some(Self {
+			limit: value("x-ratelimit-limit")?,
+			remaining: value("x-ratelimit-remaining")?,
+			reset: DateTime::from_utc(
+				chrono::NaiveDateTime::from_timestamp(
+					value("x-ratelimit-reset")? as i64,
+					0,
+				),
+				Utc,
+			),
+		})
+	}
+
+	/// Returns true if few requests remain before the quota resets.
+	pub fn is_low(&self) -> bool {
+		self.remaining < LOW_QUOTA && self.reset > Utc::now()
+	}
+}
+
+/// Exponential backoff with up to 100% jitter: 1-2s, 2-4s, 4-8s...
+fn backoff(attempt: u32) -> std::time::Duration {
+	let base = 1000 * 2u64.pow(attempt);
+	let jitter = SystemTime::now()
+		.duration_since(SystemTime::UNIX_EPOCH)
+		.map(|d| d.subsec_nanos() as u64 % base)
+		.unwrap_or(0);
+	std::time::Duration::from_millis(base + jitter)
+}
+
+/// Returns how long to wait before retrying a request that failed with `status`, or `None` if it
+/// should not be retried. A request refused by a rate limit was not carried out, so it is retried
+/// whatever its method; after a server error only an `idempotent` request is, since GitHub may
+/// have carried it out.
+fn retry_delay(
+	status: StatusCode,
+	headers: &header::HeaderMap,
+	body: &str,
+	attempt: u32,
+	idempotent: bool,
+) -> Option<std::time::Duration> {
+	let rate_limited = status == StatusCode::FORBIDDEN
+		|| status == StatusCode::TOO_MANY_REQUESTS;
+	if let Some(secs) = headers
+		.get(header::RETRY_AFTER)
+		.and_then(|v| v.to_str().ok())
+		.and_then(|v| v.parse::<u64>().ok())
+	{
+		Some(std::time::Duration::from_secs(secs))
+	} else if rate_limited
+		&& RateLimit::from_headers(headers)
+			.map_or(false, |quota| quota.remaining == 0)
+	{
+		// primary rate limit; wait for the quota to reset
+		RateLimit::from_headers(headers)
+			.and_then(|quota| (quota.reset - Utc::now()).to_std().ok())
+			.or(Some(std::time::Duration::from_secs(1)))
+	} else if (rate_limited && body.to_lowercase().contains("rate limit"))
+		|| (status.is_server_error() && idempotent)
+	{
+		// secondary rate limit or server error
+		Some(backoff(attempt))
+	} else {
+		None
+	}
 }
 
 macro_rules! impl_methods_with_body {
-	($($method:ident : $method_response_fn:ident),*) => {
+	($($method:ident : $method_response_fn:ident : $idempotent:expr),*) => {
 		$(
 			pub async fn $method<'b, I, B, T>(&self, url: I, body: &B) -> Result<T>
 			where
@@ -42,25 +160,9 @@ macro_rules! impl_methods_with_body {
 				I: Into<Cow<'b, str>> + Clone,
 				B: Serialize + Clone,
 			{
-				// retry up to 5 times if request times out
-				let mut retries = 0;
-				'retry: loop {
-					let res = self.execute(
-						self.client
-						.$method(&*url.clone().into())
-						.json(&body.clone()),
-					)
-					.await;
-					// retry if timeout
-					if let Err(error::Error::Http { source: e, .. }) = res.as_ref() {
-						if e.is_timeout() && retries < 5 {
-							log::debug!("Request timed out; retrying");
-							retries += 1;
-							continue 'retry;
-						}
-					}
-					return res;
-				}
+				let url = url.into();
+				self.execute($idempotent, || self.client.$method(&*url).json(body))
+					.await
 			}
 
 		)*
@@ -75,36 +177,63 @@ async fn handle_response(response: Response) -> Result<Response> {
 		Ok(response)
 	} else {
 		let text = response.text().await.context(error::Http)?;
+		Err(response_error(status, &text))
+	}
+}
 
-		// Try to decode the response error as JSON otherwise store
-		// it as plain text in a JSON object.
-		let body = if let Ok(value) =
-			serde_json::from_str(&text).context(error::Json)
-		{
+fn response_error(status: StatusCode, text: &str) -> error::Error {
+	// Try to decode the response error as JSON otherwise store
+	// it as plain text in a JSON object.
+	let body =
+		if let Ok(value) = serde_json::from_str(text).context(error::Json) {
 			value
 		} else {
 			serde_json::json!({ "error_message": text })
 		};
 
-		error::Response { status, body }.fail()
-	}
+	error::Error::Response { status, body }
 }
 
 /// HTTP util methods.
 impl Client {
-	pub fn new(private_key: Vec<u8>, installation_login: String) -> Self {
+	pub fn new(
+		app_id: u64,
+		encoding_key: jsonwebtoken::EncodingKey,
+		installation_login: Option<String>,
+		base_url: String,
+	) -> Self {
 		Self {
-			private_key: private_key.into(),
-			installation_login,
-			..Self::default()
+			client: reqwest::Client::new(),
+			shared: Arc::new(Shared {
+				app_id,
+				encoding_key,
+				installation_login,
+				base_url,
+				installation_ids: Cache::new(
+					"installation_ids",
+					INSTALLATION_IDS_TTL,
+				),
+				tokens: Default::default(),
+				rate_limits: Default::default(),
+			}),
+			installation: None,
+		}
+	}
+
+	/// Returns the GraphQL endpoint matching the REST API root; GitHub Enterprise Server serves
+	/// the REST API under `/api/v3` and GraphQL under `/api/graphql`.
+	pub fn graphql_url(&self) -> String {
+		match self.shared.base_url.strip_suffix("/api/v3") {
+			Some(root) => format!("{}/api/graphql", root),
+			None => format!("{}/graphql", self.shared.base_url),
 		}
 	}
 
 	impl_methods_with_body! {
-		post: post_response,
-		put: put_response,
-		patch: patch_response,
-		delete: delete_response
+		post: post_response: false,
+		put: put_response: true,
+		patch: patch_response: false,
+		delete: delete_response: true
 	}
 
 	pub async fn request(
@@ -115,18 +244,91 @@ impl Client {
 		self.client.request(method, url)
 	}
 
+	/// Returns a client making requests on behalf of `installation_id`, or of the installation of
+	/// `installation_login` if `None`, eg. the installation a webhook was delivered for.
+	pub fn for_installation(&self, installation_id: Option<i64>) -> Self {
+		Self {
+			installation: installation_id,
+			..self.clone()
+		}
+	}
+
+	/// Returns a client making requests on behalf of the installation on the account `login`, for
+	/// work that did not come from a webhook.
+	pub async fn for_account(&self, login: &str) -> Result<Self> {
+		let id = self.installation_id_for(login).await?;
+		Ok(self.for_installation(Some(id)))
+	}
+
+	/// Returns the installation this client was made for, if any.
+	pub fn installation(&self) -> Option<i64> {
+		self.installation
+	}
+
+	/// Returns the installation requests are made on behalf of, looking up the installation of
+	/// `installation_login` if the client was made for none.
+	pub async fn installation_id(&self) -> Result<i64> {
+		match (self.installation, &self.shared.installation_login) {
+			(Some(id), _) => Ok(id),
+			(None, Some(login)) => self.installation_id_for(login).await,
+			(None, None) => error::Message {
+				msg: format!(
+					"No installation to make the request on behalf of; set INSTALLATION_LOGIN to use one by default"
+				),
+			}
+			.fail(),
+		}
+	}
+
+	/// Returns the ID of the installation on the account `login`, listing the installations of
+	/// the app if it is not cached.
+	async fn installation_id_for(&self, login: &str) -> Result<i64> {
+		if let Some(id) = self.shared.installation_ids.get(login) {
+			return Ok(id);
+		}
+		let installations = self.installations().await?;
+		for installation in installations.iter() {
+			self.shared
+				.installation_ids
+				.insert(installation.account.login.clone(), installation.id);
+		}
+		installations
+			.iter()
+			.find(|inst| inst.account.login == login)
+			.map(|inst| inst.id)
+			.context(error::MissingData)
+	}
+
+	/// Lists the installations of the app, authenticating as the app itself.
+	pub async fn installations(&self) -> Result<Vec<github::Installation>> {
+		self.jwt_get(&format!("{}/app/installations", self.shared.base_url))
+			.await
+	}
+
+	/// Returns an access token for the installation of this client.
 	pub async fn auth_key(&self) -> Result<String> {
-		log::debug!("auth_key");
-		lazy_static::lazy_static! {
-			static ref TOKEN_CACHE: parking_lot::Mutex<Option<(DateTime<Utc>, String)>> = {
-				parking_lot::Mutex::new(None)
-			};
+		self.installation_token(self.installation_id().await?).await
+	}
+
+	/// Checks that the app can authenticate, and that it can get an access token if the client
+	/// has an installation to fall back on.
+	pub async fn check(&self) -> Result<()> {
+		if self.installation.is_none()
+			&& self.shared.installation_login.is_none()
+		{
+			self.installations().await.map(|_| ())
+		} else {
+			self.auth_key().await.map(|_| ())
 		}
+	}
 
+	async fn installation_token(&self, installation_id: i64) -> Result<String> {
+		log::debug!("installation_token {}", installation_id);
 		let token = {
-			TOKEN_CACHE
+			self.shared
+				.tokens
 				.lock()
-				.as_ref()
+				.get(&installation_id)
 				// Ensure token is not expired if set.
 				.filter(|(time, _)| time > &Utc::now())
 				.map(|(_, token)| token.clone())
@@ -136,24 +338,11 @@ impl Client {
 			return Ok(token);
 		}
 
-		let installations: Vec<github::Installation> = self
-			.jwt_get(&format!(
-				"{}/app/installations",
-				crate::github_bot::GithubBot::BASE_URL
-			))
-			.await?;
-
-		let installation = installations
-			.iter()
-			.find(|inst| inst.account.login == self.installation_login)
-			.context(error::MissingData)?;
-
 		let install_token: github::InstallationToken = self
 			.jwt_post(
 				&format!(
 					"{}/app/installations/{}/access_tokens",
-					crate::github_bot::GithubBot::BASE_URL,
-					installation.id
+					self.shared.base_url, installation_id
 				),
 				&serde_json::json!({}),
 			)
@@ -166,15 +355,128 @@ impl Client {
 		let token = install_token.token;
 
 		{
-			*TOKEN_CACHE.lock() = Some((expiry.clone(), token.clone()))
+			self.shared
+				.tokens
+				.lock()
+				.insert(installation_id, (expiry.clone(), token.clone()))
 		};
 
 		Ok(token)
 	}
 
-	async fn execute(&self, builder: RequestBuilder) -> Result<Response> {
+	/// Returns the quota of the installation of this client reported by the last response from
+	/// GitHub.
+	pub fn rate_limit(&self) -> Option<RateLimit> {
+		let installation_id = self.installation.or_else(|| {
+			self.shared
+				.installation_login
+				.as_ref()
+				.and_then(|login| self.shared.installation_ids.get(login))
+		})?;
+		self.shared
+			.rate_limits
+			.lock()
+			.get(&installation_id)
+			.cloned()
+	}
+
+	/// Returns the last quota reported for each installation.
+	pub fn rate_limits(&self) -> HashMap<i64, RateLimit> {
+		self.shared.rate_limits.lock().clone()
+	}
+
+	/// Returns true if low-priority work should wait for the quota to reset.
+	pub fn quota_low(&self) -> bool {
+		self.rate_limit().map_or(false, |quota| quota.is_low())
+	}
+
+	fn update_rate_limit(
+		&self,
+		installation_id: i64,
+		headers: &header::HeaderMap,
+	) {
+		if let Some(quota) = RateLimit::from_headers(headers) {
+			let mut rate_limits = self.shared.rate_limits.lock();
+			let was_low = rate_limits
+				.get(&installation_id)
+				.map_or(false, RateLimit::is_low);
+			if quota.is_low() && !was_low {
+				log::warn!(
+					"GitHub rate limit of installation {} is low: {} of {} requests remaining until {}",
+					installation_id,
+					quota.remaining,
+					quota.limit,
+					quota.reset
+				);
+			} else {
+				log::debug!(
+					"GitHub rate limit: {} of {} requests remaining until {}",
+					quota.remaining,
+					quota.limit,
+					quota.reset
+				);
+			}
+			metrics::GITHUB_RATE_LIMIT_REMAINING
+				.set(&[&installation_id.to_string()], quota.remaining as f64);
+			rate_limits.insert(installation_id, quota);
+		}
+	}
+
+	/// Sends the request made by `build`, retrying rate limits, and timeouts and server errors if
+	/// the request is `idempotent`, ie. its method is GET, PUT or DELETE.
+	async fn execute<F>(&self, idempotent: bool, build: F) -> Result<Response>
+	where
+		F: Fn() -> RequestBuilder,
+	{
+		let mut attempt = 0;
+		let mut waited = std::time::Duration::from_secs(0);
+		loop {
+			let (delay, error) = match self.send(build()).await {
+				Ok(response) if response.status().is_success() => {
+					return Ok(response)
+				}
+				Ok(response) => {
+					let status = response.status();
+					let headers = response.headers().clone();
+					let text = response.text().await.context(error::Http)?;
+					let error = response_error(status, &text);
+					match retry_delay(
+						status, &headers, &text, attempt, idempotent,
+					) {
+						Some(delay) => (delay, error),
+						None => return Err(error),
+					}
+				}
+				Err(error) => {
+					let timeout = matches!(
+						&error,
+						error::Error::Http { source } if source.is_timeout()
+					);
+					if !timeout || !idempotent {
+						return Err(error);
+					}
+					(backoff(attempt), error)
+				}
+			};
+			if attempt >= MAX_RETRIES || waited + delay > MAX_RETRY_WAIT {
+				log::warn!(
+					"Giving up on request after {} attempt(s); next retry would wait {:?}",
+					attempt + 1,
+					delay
+				);
+				return Err(error);
+			}
+			log::debug!("Request failed; retrying in {:?}", delay);
+			tokio::time::delay_for(delay).await;
+			waited += delay;
+			attempt += 1;
+		}
+	}
+
+	async fn send(&self, builder: RequestBuilder) -> Result<Response> {
+		let installation_id = self.installation_id().await?;
 		let request = builder
-			.bearer_auth(&self.auth_key().await?)
+			.bearer_auth(&self.installation_token(installation_id).await?)
 			.header(
 				header::ACCEPT,
 				"application/vnd.github.starfox-preview+json",
@@ -198,34 +500,20 @@ impl Client {
 
 		log::debug!("{:?}", &request);
 
-		handle_response(
-			self.client.execute(request).await.context(error::Http)?,
-		)
-		.await
+		let _timer =
+			metrics::GITHUB_REQUESTS.start_timer(&[request.method().as_str()]);
+		let response =
+			self.client.execute(request).await.context(error::Http)?;
+		self.update_rate_limit(installation_id, response.headers());
+		Ok(response)
 	}
 
 	fn create_jwt(&self) -> Result<String> {
 		log::debug!("create_jwt");
-		const TEN_MINS_IN_SECONDS: u64 = 10 * 60;
-		lazy_static::lazy_static! {
-			static ref APP_ID: u64 = dotenv::var("GITHUB_APP_ID").unwrap().parse::<u64>().unwrap();
-		}
-		let iat = SystemTime::now()
-			.duration_since(SystemTime::UNIX_EPOCH)
-			.unwrap()
-			.as_secs();
-
-		let body = serde_json::json!({
-			"iat": iat,
-			"exp": iat + TEN_MINS_IN_SECONDS,
-			"iss": *APP_ID,
-		});
-
 		jsonwebtoken::encode(
 			&jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256),
-			&body,
-			&jsonwebtoken::EncodingKey::from_rsa_pem(&self.private_key)
-				.expect("private key should be RSA pem"),
+			&jwt_claims(self.shared.app_id, Utc::now()),
+			&self.shared.encoding_key,
 		)
 		.context(error::Jwt)
 	}
@@ -278,6 +566,39 @@ impl Client {
 			.context(error::Http)
 	}
 
+	/// Send a GraphQL query to GitHub and return the `data` of the response.
+	pub async fn graphql<T>(
+		&self,
+		query: &str,
+		variables: serde_json::Value,
+	) -> Result<T>
+	where
+		T: serde::de::DeserializeOwned,
+	{
+		let response: github::GraphqlResponse<T> = self
+			.post(
+				self.graphql_url(),
+				&serde_json::json!({
+					"query": query,
+					"variables": variables,
+				}),
+			)
+			.await?;
+		match response {
+			github::GraphqlResponse {
+				data: Some(data),
+				errors,
+			} if errors.is_empty() => Ok(data),
+			github::GraphqlResponse { errors, .. } => error::Graphql {
+				messages: errors
+					.into_iter()
+					.map(|e| e.message)
+					.collect::<Vec<String>>(),
+			}
+			.fail(),
+		}
+	}
+
 	/// Get a single entry from a resource in GitHub.
 	pub async fn get<'b, I, T>(&self, url: I) -> Result<T>
 	where
@@ -338,24 +659,9 @@ impl Client {
 		P: Serialize + Clone,
 	{
 		log::debug!("get_response");
-		// retry up to 5 times if request times out
-		let mut retries = 0;
-		'retry: loop {
-			let res = self
-				.execute(
-					self.client.get(&*url.clone().into()).json(&params.clone()),
-				)
-				.await;
-			// retry if timeout
-			if let Err(error::Error::Http { source: e, .. }) = res.as_ref() {
-				if e.is_timeout() && retries < 5 {
-					log::debug!("Request timed out; retrying");
-					retries += 1;
-					continue 'retry;
-				}
-			}
-			return res;
-		}
+		let url = url.into();
+		self.execute(true, || self.client.get(&*url).json(&params))
+			.await
 	}
 
 	// Originally adapted from:
@@ -367,34 +673,281 @@ impl Client {
 		T: serde::de::DeserializeOwned + core::fmt::Debug,
 	{
 		log::debug!("get_all");
-		let mut entities = Vec::new();
-		let mut next = Some(url.into());
+		Ok(self
+			.get_pages::<_, Vec<T>>(url)
+			.await?
+			.into_iter()
+			.flatten()
+			.collect())
+	}
+
+	/// Gets every page of a resource in GitHub, following the `Link` header. Used directly for
+	/// resources that wrap their entries in an object, eg. check runs.
+	pub async fn get_pages<'b, I, T>(&self, url: I) -> Result<Vec<T>>
+	where
+		I: Into<Cow<'b, str>>,
+		T: serde::de::DeserializeOwned,
+	{
+		let mut pages = Vec::new();
+		let mut next = Some(Cow::Owned(with_per_page(&url.into())));
 
 		while let Some(url) = next {
 			log::debug!("getting next");
 			let response =
 				self.get_response(url, serde_json::json!({})).await?;
 
-			next = response
-				.headers()
-				.decode::<hyperx::header::Link>()
-				.ok()
-				.iter()
-				.flat_map(|v| v.values())
-				.find(|link| {
-					link.rel().map_or(false, |rel| {
-						rel.contains(&hyperx::header::RelationType::Next)
-					})
-				})
-				.map(|l| l.link())
-				.map(str::to_owned)
-				.map(Cow::Owned);
-
-			let mut body =
-				response.json::<Vec<T>>().await.context(error::Http)?;
-			entities.append(&mut body);
+			next = next_page(response.headers()).map(Cow::Owned);
+
+			pages.push(response.json::<T>().await.context(error::Http)?);
+		}
+
+		Ok(pages)
+	}
+}
+
+/// Claims of an app JWT issued at `now`.
+fn jwt_claims(app_id: u64, now: DateTime<Utc>) -> serde_json::Value {
+	let iat = now.timestamp() - JWT_CLOCK_SKEW_SECS;
+	serde_json::json!({
+		"iat": iat,
+		"exp": iat + JWT_CLOCK_SKEW_SECS + JWT_LIFETIME_SECS,
+		"iss": app_id,
+	})
+}
+
+/// Ask for the largest page size GitHub allows, unless the URL already specifies one.
+fn with_per_page(url: &str) -> String {
+	if url.contains("per_page=") {
+		url.to_owned()
+	} else if url.contains('?') {
+		format!("{}&per_page=100", url)
+	} else {
+		format!("{}?per_page=100", url)
+	}
+}
+
+/// Returns the URL of the next page from a `Link` header, if any.
+fn next_page(headers: &header::HeaderMap) -> Option<String> {
+	headers
+		.decode::<hyperx::header::Link>()
+		.ok()
+		.iter()
+		.flat_map(|v| v.values())
+		.find(|link| {
+			link.rel().map_or(false, |rel| {
+				rel.contains(&hyperx::header::RelationType::Next)
+			})
+		})
+		.map(|l| l.link().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::server::Incoming;
+	use futures_util::FutureExt;
+	use hyper::service::{make_service_fn, service_fn};
+
+	#[test]
+	fn test_with_per_page() {
+		assert_eq!(
+			with_per_page("https://api.github.com/teams/1/members"),
+			"https://api.github.com/teams/1/members?per_page=100"
+		);
+		assert_eq!(
+			with_per_page("https://api.github.com/repos/a/b/pulls?state=open"),
+			"https://api.github.com/repos/a/b/pulls?state=open&per_page=100"
+		);
+		assert_eq!(
+			with_per_page("https://api.github.com/teams/1/members?per_page=5"),
+			"https://api.github.com/teams/1/members?per_page=5"
+		);
+	}
+
+	#[test]
+	fn test_jwt_claims() {
+		let now = Utc::now();
+		let claims = jwt_claims(81160, now);
+		assert_eq!(claims["iss"], 81160);
+		assert_eq!(claims["iat"], now.timestamp() - 60);
+		assert_eq!(claims["exp"], now.timestamp() + 9 * 60);
+	}
+
+	#[test]
+	fn test_retry_delay() {
+		fn headers(pairs: &[(&'static str, String)]) -> header::HeaderMap {
+			let mut headers = header::HeaderMap::new();
+			for (name, value) in pairs {
+				headers.insert(*name, value.parse().expect("header value"));
+			}
+			headers
 		}
+		let second = std::time::Duration::from_secs(1);
+
+		assert_eq!(
+			retry_delay(
+				StatusCode::FORBIDDEN,
+				&headers(&[("retry-after", "30".to_owned())]),
+				"",
+				0,
+				false
+			),
+			Some(std::time::Duration::from_secs(30))
+		);
+
+		// primary rate limit: wait until the reset
+		let reset = Utc::now() + Duration::seconds(20);
+		let delay = retry_delay(
+			StatusCode::FORBIDDEN,
+			&headers(&[
+				("x-ratelimit-limit", "5000".to_owned()),
+				("x-ratelimit-remaining", "0".to_owned()),
+				("x-ratelimit-reset", reset.timestamp().to_string()),
+			]),
+			"",
+			0,
+			false,
+		)
+		.expect("delay");
+		assert!(delay > 18 * second && delay <= 20 * second);
+
+		// secondary rate limit and server errors back off exponentially
+		let delay = retry_delay(
+			StatusCode::FORBIDDEN,
+			&headers(&[]),
+			r#"{"message": "You have exceeded a secondary rate limit."}"#,
+			2,
+			false,
+		)
+		.expect("delay");
+		assert!(delay >= 4 * second && delay < 8 * second);
+		let delay =
+			retry_delay(StatusCode::BAD_GATEWAY, &headers(&[]), "", 0, true)
+				.expect("delay");
+		assert!(delay >= second && delay < 2 * second);
+		// GitHub may have carried out a POST that failed with a server error
+		assert_eq!(
+			retry_delay(StatusCode::BAD_GATEWAY, &headers(&[]), "", 0, false),
+			None
+		);
+
+		assert_eq!(
+			retry_delay(
+				StatusCode::FORBIDDEN,
+				&headers(&[]),
+				r#"{"message": "Resource not accessible by integration"}"#,
+				0,
+				true
+			),
+			None
+		);
+		assert_eq!(
+			retry_delay(StatusCode::NOT_FOUND, &headers(&[]), "", 0, true),
+			None
+		);
+	}
+
+	#[test]
+	fn test_installation_tokens() {
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		rt.block_on(async {
+			let client = Client::new(
+				1,
+				jsonwebtoken::EncodingKey::from_secret(&[]),
+				None,
+				"http://localhost".to_owned(),
+			);
+			for id in &[2, 3] {
+				client.shared.tokens.lock().insert(
+					*id,
+					(
+						Utc::now() + Duration::minutes(10),
+						format!("token-{}", id),
+					),
+				);
+			}
+			// without INSTALLATION_LOGIN there is nothing to fall back on
+			assert_eq!(client.installation(), None);
+			assert!(client.installation_id().await.is_err());
+
+			let first = client.for_installation(Some(2));
+			let second = first.for_installation(Some(3));
+			assert_eq!(first.auth_key().await.expect("auth_key"), "token-2");
+			assert_eq!(second.installation_id().await.expect("id"), 3);
+			assert_eq!(second.auth_key().await.expect("auth_key"), "token-3");
+			assert_eq!(first.installation(), Some(2));
+			assert_eq!(client.installation(), None);
+		});
+	}
 
-		Ok(entities)
+	#[test]
+	fn test_get_all_pages() {
+		let mut rt = tokio::runtime::Runtime::new().expect("runtime");
+		rt.block_on(async {
+			// serve three pages of two entries each, linking each to the next
+			let listener: &'static _ = Box::leak(Box::new(
+				async_std::net::TcpListener::bind("127.0.0.1:0")
+					.await
+					.expect("bind"),
+			));
+			let addr = listener.local_addr().expect("local addr");
+			let service = make_service_fn(move |_| async move {
+				Ok::<_, hyper::Error>(service_fn(
+					move |req: hyper::Request<hyper::Body>| async move {
+						let query = req.uri().query().unwrap_or("").to_owned();
+						assert!(query.contains("per_page=100"));
+						let page = query
+							.split('&')
+							.find_map(|param| param.strip_prefix("page="))
+							.and_then(|page| page.parse::<i64>().ok())
+							.unwrap_or(1);
+						let mut response = hyper::Response::builder();
+						if page < 3 {
+							response = response.header(
+								"link",
+								format!(
+									"<http://{addr}/members?per_page=100&page={next}>; rel=\"next\", <http://{addr}/members?per_page=100&page=3>; rel=\"last\"",
+									addr = addr,
+									next = page + 1,
+								),
+							);
+						}
+						response.body(hyper::Body::from(
+							serde_json::json!([page * 10, page * 10 + 1])
+								.to_string(),
+						))
+					},
+				))
+			});
+			tokio::spawn(
+				hyper::Server::builder(Incoming(listener.incoming()))
+					.serve(service)
+					.map(|_| ()),
+			);
+
+			let client = Client::new(
+				1,
+				jsonwebtoken::EncodingKey::from_secret(&[]),
+				None,
+				format!("http://{}", addr),
+			)
+			.for_installation(Some(1));
+			// skip authentication against GitHub
+			client.shared.tokens.lock().insert(
+				1,
+				(Utc::now() + Duration::minutes(10), "token".to_owned()),
+			);
+			let members: Vec<i64> = client
+				.get_all(format!("http://{}/members", addr))
+				.await
+				.expect("get_all");
+			assert_eq!(members, vec![10, 11, 20, 21, 30, 31]);
+
+			let pages: Vec<serde_json::Value> = client
+				.get_pages(format!("http://{}/members?page=2", addr))
+				.await
+				.expect("get_pages");
+			assert_eq!(pages.len(), 2);
+		});
 	}
 }
@@ -0,0 +1,214 @@
+use serde::Deserialize;
+
+use crate::{
+	github::Label,
+	patterns::{GlobPattern, RegexPattern},
+};
+
+/// Rules applied to pull request labels. Their patterns are compiled as they are read, so that
+/// rules with a malformed pattern fail to load.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct LabelRules {
+	/// Groups of labels of which a pull request may carry at most one.
+	#[serde(default)]
+	pub exclusive: Vec<LabelGroup>,
+	/// Labels a pull request must carry before it can be merged.
+	#[serde(default)]
+	pub required: Vec<String>,
+	/// Labels applied automatically to pull requests changing matching paths.
+	#[serde(default)]
+	pub paths: Vec<PathLabel>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct LabelGroup {
+	pub name: String,
+	/// Regex matching the names of labels in the group.
+	pub pattern: RegexPattern,
+	/// If true, a pull request must carry exactly one label from the group before it can be
+	/// merged.
+	#[serde(default)]
+	pub required: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct PathLabel {
+	/// Glob matching paths changed by a pull request, eg. `client/network/**`.
+	pub pattern: GlobPattern,
+	pub label: String,
+}
+
+impl LabelGroup {
+	pub fn contains(&self, label: &str) -> bool {
+		self.pattern.is_match(label)
+	}
+}
+
+impl PathLabel {
+	pub fn matches(&self, path: &str) -> bool {
+		self.pattern.matches(path)
+	}
+}
+
+impl LabelRules {
+	/// Returns the labels that share an exclusive group with `added`, and so should be removed
+	/// when `added` is applied.
+	pub fn conflicting_labels(
+		&self,
+		added: &str,
+		labels: &[Label],
+	) -> Vec<String> {
+		self.exclusive
+			.iter()
+			.filter(|group| group.contains(added))
+			.flat_map(|group| {
+				labels
+					.iter()
+					.filter(move |label| {
+						label.name != added && group.contains(&label.name)
+					})
+					.map(|label| label.name.clone())
+			})
+			.collect()
+	}
+
+	/// Returns the reason a pull request with `labels` cannot be merged, if any.
+	pub fn merge_blocked(&self, labels: &[Label]) -> Option<String> {
+		for group in self.exclusive.iter() {
+			let in_group = labels
+				.iter()
+				.filter(|label| group.contains(&label.name))
+				.map(|label| format!("`{}`", label.name))
+				.collect::<Vec<String>>();
+			if in_group.len() > 1 {
+				return Some(format!(
+					"labels {} are mutually exclusive ({})",
+					in_group.join(", "),
+					group.name
+				));
+			}
+			if group.required && in_group.is_empty() {
+				return Some(format!("missing a {} label", group.name));
+			}
+		}
+		self.required
+			.iter()
+			.find(|required| !labels.iter().any(|l| &&l.name == required))
+			.map(|required| format!("missing required label `{}`", required))
+	}
+
+	/// Returns the labels matching any of the changed `paths`.
+	pub fn labels_for_paths(&self, paths: &[String]) -> Vec<String> {
+		let mut labels = self
+			.paths
+			.iter()
+			.filter(|rule| paths.iter().any(|path| rule.matches(path)))
+			.map(|rule| rule.label.clone())
+			.collect::<Vec<String>>();
+		labels.sort();
+		labels.dedup();
+		labels
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn label(name: &str) -> Label {
+		Label {
+			name: name.to_string(),
+			..Label::default()
+		}
+	}
+
+	fn rules() -> LabelRules {
+		serde_json::from_str(
+			r#"{
+				"exclusive": [
+					{ "name": "B", "pattern": "^B[0-9]-", "required": true },
+					{ "name": "C", "pattern": "^C[0-9]-" }
+				],
+				"required": ["A0-please_review"],
+				"paths": [
+					{ "pattern": "client/network/**", "label": "I-networking" },
+					{ "pattern": "**/Cargo.toml", "label": "I-dependencies" }
+				]
+			}"#,
+		)
+		.expect("parse rules")
+	}
+
+	#[test]
+	fn test_conflicting_labels() {
+		let labels =
+			vec![label("B0-silent"), label("B1-note"), label("C1-low")];
+		assert_eq!(
+			rules().conflicting_labels("B1-note", &labels),
+			vec!["B0-silent".to_string()]
+		);
+		assert!(rules()
+			.conflicting_labels("A0-please_review", &labels)
+			.is_empty());
+	}
+
+	#[test]
+	fn test_merge_blocked() {
+		let rules = rules();
+		assert_eq!(
+			rules.merge_blocked(&[label("A0-please_review")]),
+			Some("missing a B label".to_string())
+		);
+		assert_eq!(
+			rules.merge_blocked(&[
+				label("A0-please_review"),
+				label("B0-silent"),
+				label("B1-note")
+			]),
+			Some(
+				"labels `B0-silent`, `B1-note` are mutually exclusive (B)"
+					.to_string()
+			)
+		);
+		assert_eq!(
+			rules.merge_blocked(&[label("B0-silent")]),
+			Some("missing required label `A0-please_review`".to_string())
+		);
+		assert_eq!(
+			rules.merge_blocked(&[
+				label("A0-please_review"),
+				label("B0-silent")
+			]),
+			None
+		);
+		assert_eq!(LabelRules::default().merge_blocked(&[]), None);
+	}
+
+	#[test]
+	fn test_labels_for_paths() {
+		assert_eq!(
+			rules().labels_for_paths(&[
+				"client/network/src/lib.rs".to_string(),
+				"client/network/Cargo.toml".to_string(),
+				"README.md".to_string(),
+			]),
+			vec!["I-dependencies".to_string(), "I-networking".to_string()]
+		);
+		assert!(rules()
+			.labels_for_paths(&["frame/README.md".to_string()])
+			.is_empty());
+	}
+
+	#[test]
+	fn test_invalid_patterns() {
+		let e = serde_json::from_str::<LabelRules>(
+			r#"{ "exclusive": [{ "name": "B", "pattern": "^B[0-9" }] }"#,
+		)
+		.expect_err("invalid regex");
+		assert!(e.to_string().starts_with("pattern '^B[0-9' is invalid"));
+		assert!(serde_json::from_str::<LabelRules>(
+			r#"{ "paths": [{ "pattern": "client/***", "label": "I-client" }] }"#,
+		)
+		.is_err());
+	}
+}
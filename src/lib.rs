@@ -1,19 +1,30 @@
+pub mod admin;
 mod auth;
+pub mod backport;
 pub mod bamboo;
 pub mod companion;
 pub mod config;
 pub mod constants;
+pub mod delivery;
 pub mod error;
+pub mod features;
 pub mod github;
 pub mod github_bot;
 pub mod gitlab_bot;
 pub mod http;
+pub mod labels;
 pub mod matrix;
 pub mod matrix_bot;
+pub mod metrics;
+pub mod patterns;
 pub mod performance;
 pub mod process;
 pub mod rebase;
+pub mod reconcile;
+pub mod repo_config;
 pub mod server;
+pub mod storage;
 pub mod webhook;
+pub mod wip;
 
 pub type Result<T, E = error::Error> = std::result::Result<T, E>;
@@ -1,113 +1,222 @@
-use rocksdb::DB;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
 use parity_processbot::{
-	config::{BotConfig, MainConfig},
-	github_bot, gitlab_bot, matrix_bot,
-	server::*,
-	webhook::*,
+	config::Config, github_bot, gitlab_bot, matrix_bot, process, reconcile,
+	server::*, storage, webhook::*,
 };
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
-	match run().await {
+	let args = std::env::args().skip(1).collect::<Vec<String>>();
+	if args.first().map(String::as_str) == Some("validate") {
+		std::process::exit(validate(&args[1..]));
+	}
+
+	let config_path = args
+		.iter()
+		.position(|arg| arg == "--config")
+		.and_then(|i| args.get(i + 1))
+		.map(PathBuf::from);
+	let print_config = args.iter().any(|arg| arg == "--print-config");
+	let config = match Config::load(config_path) {
+		Ok(config) => config,
+		Err(invalid) => {
+			// what was read helps make sense of the problems
+			if print_config {
+				print!("{}", invalid.render());
+			}
+			eprintln!("Invalid configuration:");
+			for problem in invalid.problems.iter() {
+				eprintln!("- {}", problem);
+			}
+			std::process::exit(2);
+		}
+	};
+	if print_config {
+		print!("{}", config.render());
+		return Ok(());
+	}
+
+	match run(config).await {
 		Err(error) => panic!("{}", error),
 		_ => Ok(()),
 	}
 }
 
-async fn run() -> anyhow::Result<()> {
-	let config = MainConfig::from_env();
+/// `validate [--schema] [PATH]`: check a local Process file, or print its JSON schema. Without a
+/// path, the Process file the bot would use in the current directory is checked.
+/// Exits with 1 if any problems were found.
+fn validate(args: &[String]) -> i32 {
+	if args.iter().any(|arg| arg == "--schema") {
+		println!(
+			"{}",
+			serde_json::to_string_pretty(&process::process_schema())
+				.expect("serialize schema")
+		);
+		return 0;
+	}
+	let (path, format) = match args.first() {
+		Some(path) => match process::ProcessFormat::from_path(path) {
+			Some(format) => (path.as_str(), format),
+			None => {
+				eprintln!("{}: expected a .json, .toml or .yml file", path);
+				return 2;
+			}
+		},
+		None => {
+			let mut present = process::PROCESS_FILES
+				.iter()
+				.filter(|(path, _)| std::path::Path::new(path).exists());
+			match present.next() {
+				Some((path, format)) => {
+					for (ignored, _) in present {
+						eprintln!(
+							"{}: ignored because {} takes precedence",
+							ignored, path
+						);
+					}
+					(*path, *format)
+				}
+				None => {
+					eprintln!("No Process file found in this directory");
+					return 2;
+				}
+			}
+		}
+	};
+	let contents = match std::fs::read_to_string(path) {
+		Ok(contents) => contents,
+		Err(e) => {
+			eprintln!("{}: {}", path, e);
+			return 2;
+		}
+	};
+	let diagnostics = process::check_process_file(format, &contents);
+	for diagnostic in diagnostics.iter() {
+		eprintln!("{}: {}", path, diagnostic);
+	}
+	if diagnostics.is_empty() {
+		println!("{}: ok", path);
+		0
+	} else {
+		1
+	}
+}
+
+async fn run(config: Config) -> anyhow::Result<()> {
+	let Config {
+		main: config,
+		bot: bot_config,
+		warnings,
+		..
+	} = config;
 	env_logger::from_env(env_logger::Env::default().default_filter_or("info"))
 		.init();
+	for warning in warnings.iter() {
+		log::warn!("{}", warning);
+	}
 
-	let db = DB::open_default(&config.db_path)?;
+	let db = storage::Storage::open(config.storage_backend, &config.db_path)?;
 
-	log::info!(
-		"Connecting to Matrix homeserver {}",
-		config.matrix_homeserver,
-	);
-	let matrix_bot = matrix_bot::MatrixBot::new_with_token(
-		&config.matrix_homeserver,
-		&config.matrix_access_token,
-		&config.matrix_default_channel_id,
-		config.matrix_silent,
-	)?;
-
-	log::info!("Connecting to Github account {}", config.installation_login);
-	let github_bot = github_bot::GithubBot::new(
+	let matrix_bot = if config.matrix_silent {
+		log::info!("MATRIX_SILENT is set; Matrix notifications are not sent");
+		matrix_bot::MatrixBot::new_silent()
+	} else {
+		log::info!(
+			"Connecting to Matrix homeserver {}",
+			config.matrix_homeserver,
+		);
+		matrix_bot::MatrixBot::new_with_token(
+			&config.matrix_homeserver,
+			&config.matrix_access_token,
+			&config.matrix_default_channel_id,
+			false,
+		)?
+	};
+
+	log::info!("Connecting to Github as app {}", config.app_id);
+	let github_bot = github_bot::GithubBot::new_with_urls(
+		config.app_id,
 		config.private_key.clone(),
-		&config.installation_login,
+		config.installation_login.as_deref(),
+		&config.github_api_url,
+		&config.github_url,
 	)
 	.await?;
 
-	log::info!("Connecting to Gitlab https://{}", config.gitlab_hostname);
-	let gitlab_bot = gitlab_bot::GitlabBot::new_with_token(
-		&config.gitlab_hostname,
-		&config.gitlab_project,
-		&config.gitlab_job_name,
-		&config.gitlab_private_token,
-	)?;
-
-	// the bamboo queries can take a long time so only wait for it
-	// on launch. subsequently update in the background.
-	/*
-	{
-		let db_write = db.write();
-		if db_write.get(BAMBOO_DATA_KEY).ok().flatten().is_none() {
-			log::info!("Waiting for Bamboo data (may take a few minutes)");
-			match bamboo::github_to_matrix(&config.bamboo_token) {
-				Ok(h) => db_write
-					.put(
-						BAMBOO_DATA_KEY,
-						bincode::serialize(&h).expect("serialize bamboo"),
-					)
-					.expect("put bamboo"),
-				Err(e) => log::error!("Bamboo error: {}", e),
-			}
-		}
+	// authenticate as the app now rather than when the first webhook arrives
+	let installations =
+		github_bot.client.installations().await.map_err(|e| {
+			anyhow::anyhow!(
+				"Couldn't authenticate as GitHub App {}; check GITHUB_APP_ID and PRIVATE_KEY_PATH: {}",
+				config.app_id,
+				e
+			)
+		})?;
+	let logins = installations
+		.iter()
+		.map(|installation| installation.account.login.as_str())
+		.collect::<Vec<&str>>();
+	log::info!("GitHub App installed on {}", logins.join(", "));
+	// webhooks naming their installation are handled all the same
+	match &config.installation_login {
+		Some(login) if !logins.contains(&login.as_str()) => log::warn!(
+			"GitHub App {} is not installed on INSTALLATION_LOGIN {}; webhooks without an installation will fail",
+			config.app_id,
+			login
+		),
+		Some(_) => {}
+		None => log::info!(
+			"INSTALLATION_LOGIN is not set; webhooks without an installation will fail"
+		),
 	}
-	*/
-
-	// let config_clone = config.clone();
-	//	let db_clone = db.clone();
-	/*
-	std::thread::spawn(move || loop {
-		{
-			let db_write = db_clone.write();
-			match bamboo::github_to_matrix(&config_clone.bamboo_token) {
-				Ok(h) => {
-					db_write
-						.put(
-							BAMBOO_DATA_KEY,
-							bincode::serialize(&h).expect("serialize bamboo"),
-						)
-						.expect("put bamboo");
-				},
-				Err(e) => log::error!("Bamboo error: {}", e),
-			}
+
+	let gitlab_bot = match &config.gitlab {
+		Some(gitlab) => {
+			log::info!("Connecting to Gitlab https://{}", gitlab.hostname);
+			Some(gitlab_bot::GitlabBot::new_with_token(
+				&gitlab.hostname,
+				&gitlab.project,
+				&gitlab.job_name,
+				&gitlab.private_token,
+			)?)
 		}
-		std::thread::sleep(Duration::from_secs(config_clone.bamboo_tick_secs));
-	});
-	*/
+		None => {
+			log::info!("Gitlab is not configured; `bot burnin` is refused");
+			None
+		}
+	};
 
 	let app_state = Arc::new(Mutex::new(AppState {
-		db: db,
+		db: Arc::new(db),
 		github_bot: github_bot,
 		matrix_bot: matrix_bot,
 		gitlab_bot: gitlab_bot,
-		bot_config: BotConfig::from_env(),
-		webhook_secret: config.webhook_secret,
+		bot_config: bot_config,
+		webhook_secrets: config.webhook_secrets,
+		admin_token: config.admin_token,
+		deferred: Default::default(),
 	}));
 
+	tokio::spawn(reconcile::reconcile_loop(
+		Arc::clone(&app_state),
+		std::time::Duration::from_secs(config.main_tick_secs),
+	));
+
 	let socket = SocketAddr::new(
 		IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
-		config.webhook_port.parse::<u16>().expect("webhook port"),
+		config.webhook_port,
 	);
 
-	init_server(socket, app_state).await
+	init_server(
+		socket,
+		app_state,
+		std::time::Duration::from_secs(config.shutdown_timeout_secs),
+	)
+	.await
 }
 
 #[cfg(test)]
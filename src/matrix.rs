@@ -64,6 +64,32 @@ pub fn sync(homeserver: &str, access_token: &str) -> Result<String> {
 	String::from_utf8(dst).context(error::Utf8)
 }
 
+/// Checks that the homeserver is reachable and accepts the access token.
+pub fn whoami(homeserver: &str, access_token: &str) -> Result<()> {
+	// in a header, so the token does not end up in proxy and homeserver access logs
+	let mut headers = curl::easy::List::new();
+	headers.append(&format!("Authorization: Bearer {}", access_token))?;
+	let mut handle = Easy::new();
+	handle.http_headers(headers)?;
+	handle.url(
+		format!("{}/_matrix/client/r0/account/whoami", homeserver).as_ref(),
+	)?;
+	handle.get(true)?;
+	handle.timeout(std::time::Duration::from_secs(5))?;
+	{
+		let mut transfer = handle.transfer();
+		// only the status matters
+		transfer.write_function(|data| Ok(data.len()))?;
+		transfer.perform()?;
+	}
+	match handle.response_code()? {
+		200 => Ok(()),
+		code => Err(error::Error::Message {
+			msg: format!("Matrix homeserver responded with status {}", code),
+		}),
+	}
+}
+
 pub fn create_room(
 	homeserver: &str,
 	access_token: &str,
@@ -187,6 +213,19 @@ pub fn parse_id(matrix_id: &str) -> Option<String> {
 	}
 }
 
+/// Regex matching a well formed room ID.
+pub const ROOM_ID_PATTERN: &str = r"^![^:\s]+:[A-Za-z0-9.-]+(:[0-9]+)?$";
+
+lazy_static::lazy_static! {
+	static ref ROOM_ID: Regex =
+		Regex::new(ROOM_ID_PATTERN).expect("room ID pattern is valid");
+}
+
+/// Returns true if `room_id` is a well formed room ID, eg. `!VDLSjvBpNqqgyMDIBO:matrix.parity.io`.
+pub fn is_room_id(room_id: &str) -> bool {
+	ROOM_ID.is_match(room_id)
+}
+
 /*
 /// If the pattern is recognised, return the name.
 /// Otherwise, return None.
@@ -222,4 +261,12 @@ mod tests {
 		assert_eq!(parse_id("joseph"), correct);
 		assert_eq!(parse_id("@joseph:matrix.arsenal.io"), None);
 	}
+
+	#[test]
+	fn test_is_room_id() {
+		assert!(is_room_id("!VDLSjvBpNqqgyMDIBO:matrix.parity.io"));
+		assert!(is_room_id("!VDLSjvBpNqqgyMDIBO:localhost:8448"));
+		assert!(!is_room_id("#processbot:matrix.parity.io"));
+		assert!(!is_room_id("!VDLSjvBpNqqgyMDIBO"));
+	}
 }
@@ -1,12 +1,25 @@
-use parking_lot::RwLock;
-use rocksdb::DB;
-use snafu::ResultExt;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::Arc;
 
-use crate::{error, matrix, Result};
+use crate::{
+	error::Error,
+	matrix,
+	storage::{Record, Storage},
+	Result,
+};
 
-#[derive(Debug)]
+/// The room of private messages to a Matrix user, keyed by their user ID.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatrixRoom {
+	pub room_id: String,
+}
+
+impl Record for MatrixRoom {
+	const CF: &'static str = "matrix_rooms";
+	const VERSION: u16 = 1;
+}
+
+#[derive(Debug, Clone)]
 pub struct MatrixBot {
 	homeserver: String,
 	access_token: String,
@@ -34,6 +47,16 @@ impl MatrixBot {
 			})
 	}
 
+	/// A bot that sends nothing, for when `MATRIX_SILENT` leaves the homeserver unset.
+	pub fn new_silent() -> Self {
+		Self {
+			homeserver: String::new(),
+			access_token: String::new(),
+			default_channel_id: String::new(),
+			silent: true,
+		}
+	}
+
 	pub fn new_with_credentials(
 		homeserver: &str,
 		username: &str,
@@ -53,7 +76,7 @@ impl MatrixBot {
 
 	pub fn message_mapped(
 		&self,
-		db: &Arc<RwLock<DB>>,
+		db: &Storage,
 		github_to_matrix: &HashMap<String, String>,
 		github_login: &str,
 		msg: &str,
@@ -77,7 +100,7 @@ impl MatrixBot {
 
 	pub fn message_mapped_or_default(
 		&self,
-		db: &Arc<RwLock<DB>>,
+		db: &Storage,
 		github_to_matrix: &HashMap<String, String>,
 		github_login: &str,
 		msg: &str,
@@ -101,19 +124,14 @@ impl MatrixBot {
 
 	pub fn send_private_message(
 		&self,
-		db: &Arc<RwLock<DB>>,
+		db: &Storage,
 		user_id: &str,
 		msg: &str,
 	) -> Result<()> {
 		if self.silent {
 			return Ok(());
 		};
-		let db = db.write();
-		if let Some(room_id) = db
-			.get_pinned(user_id)
-			.context(error::Db)?
-			.and_then(|v| String::from_utf8(v.to_vec()).ok())
-		{
+		if let Some(MatrixRoom { room_id }) = db.get::<MatrixRoom>(user_id)? {
 			matrix::send_message(
 				&self.homeserver,
 				&self.access_token,
@@ -123,7 +141,12 @@ impl MatrixBot {
 		} else {
 			matrix::create_room(&self.homeserver, &self.access_token).and_then(
 				|matrix::CreateRoomResponse { room_id }| {
-					db.put(user_id, room_id.as_bytes()).context(error::Db)?;
+					db.put(
+						user_id,
+						&MatrixRoom {
+							room_id: room_id.clone(),
+						},
+					)?;
 					matrix::invite(
 						&self.homeserver,
 						&self.access_token,
@@ -176,6 +199,24 @@ impl MatrixBot {
 		self.send_to_room(&self.default_channel_id, msg)
 	}
 
+	/// Checks that the homeserver is reachable with our access token, on a thread that may block.
+	pub async fn check(&self) -> Result<()> {
+		if self.silent {
+			return Ok(());
+		}
+		let homeserver = self.homeserver.clone();
+		let access_token = self.access_token.clone();
+		tokio::task::spawn_blocking(move || {
+			matrix::whoami(&homeserver, &access_token)
+		})
+		.await
+		.unwrap_or_else(|e| {
+			Err(Error::Message {
+				msg: format!("Matrix check failed to run: {}", e),
+			})
+		})
+	}
+
 	pub fn send_html_to_default(&self, msg: &str) -> Result<()> {
 		if self.silent {
 			return Ok(());
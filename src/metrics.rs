@@ -0,0 +1,387 @@
+//! Counters served at `/metrics` in the Prometheus text format.
+
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Instant;
+
+/// Bucket bounds in seconds of GitHub API requests.
+const REQUEST_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+/// Bucket bounds in seconds of git jobs, which clone and may build.
+const JOB_BUCKETS: &[f64] =
+	&[1.0, 5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0];
+
+lazy_static::lazy_static! {
+	pub static ref WEBHOOKS: Counter = Counter::new(
+		"processbot_webhooks_total",
+		"Webhook deliveries by event and outcome.",
+		&["event", "outcome"],
+	);
+	pub static ref MERGES_ATTEMPTED: Counter = Counter::new(
+		"processbot_merges_attempted_total",
+		"Pull requests the bot asked GitHub to merge.",
+		&[],
+	);
+	pub static ref MERGES_SUCCEEDED: Counter = Counter::new(
+		"processbot_merges_succeeded_total",
+		"Pull requests merged by the bot.",
+		&[],
+	);
+	pub static ref MERGES_FAILED: Counter = Counter::new(
+		"processbot_merges_failed_total",
+		"Merge requests that failed or were aborted, by reason.",
+		&["reason"],
+	);
+	pub static ref COMMANDS: Counter = Counter::new(
+		"processbot_commands_total",
+		"Bot commands posted in pull request comments.",
+		&["command"],
+	);
+	pub static ref GITHUB_REQUESTS: Histogram = Histogram::new(
+		"processbot_github_request_duration_seconds",
+		"Duration of GitHub API requests.",
+		&["method"],
+		REQUEST_BUCKETS,
+	);
+	pub static ref GITHUB_RATE_LIMIT_REMAINING: Gauge = Gauge::new(
+		"processbot_github_rate_limit_remaining",
+		"GitHub API requests remaining until the quota resets, by installation.",
+		&["installation"],
+	);
+	pub static ref CACHE_LOOKUPS: Counter = Counter::new(
+		"processbot_cache_lookups_total",
+		"Lookups in the caches of GitHub responses, by cache and result (hit or miss).",
+		&["cache", "result"],
+	);
+	pub static ref CACHE_ENTRIES: Gauge = Gauge::new(
+		"processbot_cache_entries",
+		"Values held in the caches of GitHub responses.",
+		&["cache"],
+	);
+	pub static ref GIT_JOBS: Histogram = Histogram::new(
+		"processbot_git_job_duration_seconds",
+		"Duration of jobs working on a clone, eg. rebases and companion updates.",
+		&["job"],
+		JOB_BUCKETS,
+	);
+}
+
+/// Renders every metric.
+pub fn render() -> String {
+	let mut out = String::new();
+	WEBHOOKS.render(&mut out);
+	MERGES_ATTEMPTED.render(&mut out);
+	MERGES_SUCCEEDED.render(&mut out);
+	MERGES_FAILED.render(&mut out);
+	COMMANDS.render(&mut out);
+	GITHUB_REQUESTS.render(&mut out);
+	GITHUB_RATE_LIMIT_REMAINING.render(&mut out);
+	CACHE_LOOKUPS.render(&mut out);
+	CACHE_ENTRIES.render(&mut out);
+	GIT_JOBS.render(&mut out);
+	out
+}
+
+fn escape_label_value(value: &str) -> String {
+	value
+		.replace('\\', "\\\\")
+		.replace('"', "\\\"")
+		.replace('\n', "\\n")
+}
+
+/// Formats `{name="value",...}`, or nothing without labels.
+fn format_labels(names: &[&str], values: &[String]) -> String {
+	if names.is_empty() {
+		return String::new();
+	}
+	let labels = names
+		.iter()
+		.zip(values)
+		.map(|(name, value)| {
+			format!("{}=\"{}\"", name, escape_label_value(value))
+		})
+		.collect::<Vec<String>>();
+	format!("{{{}}}", labels.join(","))
+}
+
+fn label_values(values: &[&str]) -> Vec<String> {
+	values.iter().map(|value| (*value).to_owned()).collect()
+}
+
+fn write_header(out: &mut String, name: &str, help: &str, kind: &str) {
+	let _ = writeln!(out, "# HELP {} {}", name, help);
+	let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+pub struct Counter {
+	name: &'static str,
+	help: &'static str,
+	labels: &'static [&'static str],
+	values: Mutex<BTreeMap<Vec<String>, u64>>,
+}
+
+impl Counter {
+	pub fn new(
+		name: &'static str,
+		help: &'static str,
+		labels: &'static [&'static str],
+	) -> Self {
+		let mut values = BTreeMap::new();
+		if labels.is_empty() {
+			// a counter without labels is exported before its first increment
+			values.insert(vec![], 0);
+		}
+		Self {
+			name,
+			help,
+			labels,
+			values: Mutex::new(values),
+		}
+	}
+
+	/// Increments the counter with one value per label.
+	pub fn inc(&self, label_values: &[&str]) {
+		debug_assert_eq!(label_values.len(), self.labels.len());
+		*self
+			.values
+			.lock()
+			.entry(self::label_values(label_values))
+			.or_insert(0) += 1;
+	}
+
+	pub fn get(&self, label_values: &[&str]) -> u64 {
+		self.values
+			.lock()
+			.get(&self::label_values(label_values))
+			.copied()
+			.unwrap_or(0)
+	}
+
+	fn render(&self, out: &mut String) {
+		write_header(out, self.name, self.help, "counter");
+		for (values, count) in self.values.lock().iter() {
+			let _ = writeln!(
+				out,
+				"{}{} {}",
+				self.name,
+				format_labels(self.labels, values),
+				count
+			);
+		}
+	}
+}
+
+pub struct Gauge {
+	name: &'static str,
+	help: &'static str,
+	labels: &'static [&'static str],
+	values: Mutex<BTreeMap<Vec<String>, f64>>,
+}
+
+impl Gauge {
+	pub fn new(
+		name: &'static str,
+		help: &'static str,
+		labels: &'static [&'static str],
+	) -> Self {
+		Self {
+			name,
+			help,
+			labels,
+			values: Mutex::new(BTreeMap::new()),
+		}
+	}
+
+	pub fn set(&self, label_values: &[&str], value: f64) {
+		debug_assert_eq!(label_values.len(), self.labels.len());
+		self.values
+			.lock()
+			.insert(self::label_values(label_values), value);
+	}
+
+	fn render(&self, out: &mut String) {
+		write_header(out, self.name, self.help, "gauge");
+		for (values, value) in self.values.lock().iter() {
+			let _ = writeln!(
+				out,
+				"{}{} {}",
+				self.name,
+				format_labels(self.labels, values),
+				value
+			);
+		}
+	}
+}
+
+/// Observations counted in cumulative buckets, with their sum and count.
+#[derive(Default)]
+struct Observations {
+	buckets: Vec<u64>,
+	sum: f64,
+	count: u64,
+}
+
+pub struct Histogram {
+	name: &'static str,
+	help: &'static str,
+	labels: &'static [&'static str],
+	/// Upper bounds of the buckets, in increasing order.
+	bounds: &'static [f64],
+	values: Mutex<BTreeMap<Vec<String>, Observations>>,
+}
+
+impl Histogram {
+	pub fn new(
+		name: &'static str,
+		help: &'static str,
+		labels: &'static [&'static str],
+		bounds: &'static [f64],
+	) -> Self {
+		Self {
+			name,
+			help,
+			labels,
+			bounds,
+			values: Mutex::new(BTreeMap::new()),
+		}
+	}
+
+	pub fn observe(&self, label_values: &[&str], value: f64) {
+		debug_assert_eq!(label_values.len(), self.labels.len());
+		let mut values = self.values.lock();
+		let observations = values
+			.entry(self::label_values(label_values))
+			.or_insert_with(|| Observations {
+				buckets: vec![0; self.bounds.len()],
+				..Default::default()
+			});
+		for (bound, bucket) in
+			self.bounds.iter().zip(observations.buckets.iter_mut())
+		{
+			if value <= *bound {
+				*bucket += 1;
+			}
+		}
+		observations.sum += value;
+		observations.count += 1;
+	}
+
+	/// Returns a timer observing the seconds until it is dropped.
+	pub fn start_timer(&self, label_values: &[&str]) -> Timer<'_> {
+		Timer {
+			histogram: self,
+			label_values: self::label_values(label_values),
+			start: Instant::now(),
+		}
+	}
+
+	fn render(&self, out: &mut String) {
+		write_header(out, self.name, self.help, "histogram");
+		for (values, observations) in self.values.lock().iter() {
+			let mut names = self.labels.to_vec();
+			names.push("le");
+			let bucket_labels = |le: String| {
+				let mut values = values.clone();
+				values.push(le);
+				format_labels(&names, &values)
+			};
+			for (bound, bucket) in
+				self.bounds.iter().zip(observations.buckets.iter())
+			{
+				let _ = writeln!(
+					out,
+					"{}_bucket{} {}",
+					self.name,
+					bucket_labels(bound.to_string()),
+					bucket
+				);
+			}
+			let _ = writeln!(
+				out,
+				"{}_bucket{} {}",
+				self.name,
+				bucket_labels("+Inf".to_owned()),
+				observations.count
+			);
+			let labels = format_labels(self.labels, values);
+			let _ = writeln!(
+				out,
+				"{}_sum{} {}",
+				self.name, labels, observations.sum
+			);
+			let _ = writeln!(
+				out,
+				"{}_count{} {}",
+				self.name, labels, observations.count
+			);
+		}
+	}
+}
+
+pub struct Timer<'a> {
+	histogram: &'a Histogram,
+	label_values: Vec<String>,
+	start: Instant,
+}
+
+impl Drop for Timer<'_> {
+	fn drop(&mut self) {
+		let label_values = self
+			.label_values
+			.iter()
+			.map(String::as_str)
+			.collect::<Vec<&str>>();
+		self.histogram
+			.observe(&label_values, self.start.elapsed().as_secs_f64());
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_render() {
+		let counter = Counter::new("test_total", "A test counter.", &["event"]);
+		counter.inc(&["pull_request"]);
+		counter.inc(&["pull_request"]);
+		counter.inc(&["say \"hi\""]);
+		assert_eq!(counter.get(&["pull_request"]), 2);
+		let mut out = String::new();
+		counter.render(&mut out);
+		assert_eq!(
+			out,
+			"# HELP test_total A test counter.\n\
+			 # TYPE test_total counter\n\
+			 test_total{event=\"pull_request\"} 2\n\
+			 test_total{event=\"say \\\"hi\\\"\"} 1\n"
+		);
+
+		let unlabelled = Counter::new("test_total", "A test counter.", &[]);
+		let mut out = String::new();
+		unlabelled.render(&mut out);
+		assert!(out.ends_with("\ntest_total 0\n"));
+
+		let histogram = Histogram::new(
+			"test_seconds",
+			"A test histogram.",
+			&["job"],
+			&[1.0, 5.0],
+		);
+		histogram.observe(&["rebase"], 0.5);
+		histogram.observe(&["rebase"], 3.0);
+		histogram.observe(&["rebase"], 10.0);
+		let mut out = String::new();
+		histogram.render(&mut out);
+		assert_eq!(
+			out,
+			"# HELP test_seconds A test histogram.\n\
+			 # TYPE test_seconds histogram\n\
+			 test_seconds_bucket{job=\"rebase\",le=\"1\"} 1\n\
+			 test_seconds_bucket{job=\"rebase\",le=\"5\"} 2\n\
+			 test_seconds_bucket{job=\"rebase\",le=\"+Inf\"} 3\n\
+			 test_seconds_sum{job=\"rebase\"} 13.5\n\
+			 test_seconds_count{job=\"rebase\"} 3\n"
+		);
+	}
+}
@@ -0,0 +1,122 @@
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use std::convert::TryFrom;
+
+/// A regex from the rules the bot loads, compiled once when they are read so that a malformed
+/// pattern is reported at startup rather than silently never matching.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub struct RegexPattern(Regex);
+
+impl RegexPattern {
+	pub fn is_match(&self, text: &str) -> bool {
+		self.0.is_match(text)
+	}
+
+	pub fn as_str(&self) -> &str {
+		self.0.as_str()
+	}
+}
+
+impl TryFrom<String> for RegexPattern {
+	type Error = String;
+
+	fn try_from(pattern: String) -> Result<Self, Self::Error> {
+		Regex::new(&pattern)
+			.map(RegexPattern)
+			.map_err(|e| format!("pattern '{}' is invalid: {}", pattern, e))
+	}
+}
+
+impl PartialEq for RegexPattern {
+	fn eq(&self, other: &Self) -> bool {
+		self.as_str() == other.as_str()
+	}
+}
+
+/// A case-insensitive `RegexPattern`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub struct CaseInsensitivePattern(Regex);
+
+impl CaseInsensitivePattern {
+	pub fn is_match(&self, text: &str) -> bool {
+		self.0.is_match(text)
+	}
+
+	pub fn as_str(&self) -> &str {
+		self.0.as_str()
+	}
+}
+
+impl TryFrom<String> for CaseInsensitivePattern {
+	type Error = String;
+
+	fn try_from(pattern: String) -> Result<Self, Self::Error> {
+		RegexBuilder::new(&pattern)
+			.case_insensitive(true)
+			.build()
+			.map(CaseInsensitivePattern)
+			.map_err(|e| format!("pattern '{}' is invalid: {}", pattern, e))
+	}
+}
+
+impl PartialEq for CaseInsensitivePattern {
+	fn eq(&self, other: &Self) -> bool {
+		self.as_str() == other.as_str()
+	}
+}
+
+/// A glob matching paths, eg. `client/network/**`, compiled once when it is read.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct GlobPattern(glob::Pattern);
+
+impl GlobPattern {
+	pub fn matches(&self, path: &str) -> bool {
+		self.0.matches(path)
+	}
+
+	pub fn as_str(&self) -> &str {
+		self.0.as_str()
+	}
+}
+
+impl TryFrom<String> for GlobPattern {
+	type Error = String;
+
+	fn try_from(pattern: String) -> Result<Self, Self::Error> {
+		glob::Pattern::new(&pattern).map(GlobPattern).map_err(|e| {
+			format!("path pattern '{}' is invalid: {}", pattern, e)
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_patterns() {
+		let label = serde_json::from_str::<RegexPattern>(r#""^B[0-9]-""#)
+			.expect("parse regex");
+		assert!(label.is_match("B1-note"));
+		assert!(!label.is_match("b1-note"));
+		let title =
+			serde_json::from_str::<CaseInsensitivePattern>(r#""^\\[?wip\\b""#)
+				.expect("parse regex");
+		assert!(title.is_match("[WIP] Fix"));
+		let path = serde_json::from_str::<GlobPattern>(r#""**/Cargo.toml""#)
+			.expect("parse glob");
+		assert!(path.matches("client/network/Cargo.toml"));
+
+		let e = serde_json::from_str::<RegexPattern>(r#""^B[0-9""#)
+			.expect_err("invalid regex");
+		assert!(e.to_string().starts_with("pattern '^B[0-9' is invalid"));
+		let e = serde_json::from_str::<GlobPattern>(r#""client/***""#)
+			.expect_err("invalid glob");
+		assert!(e
+			.to_string()
+			.starts_with("path pattern 'client/***' is invalid"));
+	}
+}
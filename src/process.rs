@@ -1,6 +1,327 @@
-use crate::{error, github, github_bot::GithubBot, process, Result};
-use serde::Deserialize;
+use crate::{
+	constants::{PROCESS_FILE, PROCESS_TOML_FILE, PROCESS_YAML_FILE},
+	error, github,
+	github_bot::GithubBot,
+	matrix,
+	patterns::GlobPattern,
+	process, Result,
+};
+use itertools::Itertools;
+use regex::{Captures, Regex};
+use serde::{
+	de::{Deserializer, MapAccess, Visitor},
+	Deserialize,
+};
+use serde_json::{json, Value};
 use snafu::ResultExt;
+use std::fmt;
+
+/// A field of a Process file entry, from which both `process_schema` and `check_process_file`
+/// are derived.
+struct Field {
+	name: &'static str,
+	description: &'static str,
+	required: bool,
+	kind: FieldKind,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FieldKind {
+	/// A non-empty string, eg. a GitHub login.
+	Name,
+	/// Any string.
+	Text,
+	/// A Matrix room ID.
+	RoomId,
+	/// An array of GitHub logins.
+	Logins,
+	/// An array of path globs.
+	Globs,
+}
+
+const FIELDS: &[Field] = &[
+	Field {
+		name: "project_name",
+		description:
+			"Name of a project (classic or v2) linked to the repository.",
+		required: true,
+		kind: FieldKind::Name,
+	},
+	Field {
+		name: "owner",
+		description: "GitHub login of the project owner; must be a member of the organization.",
+		required: true,
+		kind: FieldKind::Name,
+	},
+	Field {
+		name: "delegated_reviewer",
+		description: "GitHub login reviewing on behalf of the owner; must be a member of the organization.",
+		required: false,
+		kind: FieldKind::Name,
+	},
+	Field {
+		name: "whitelist",
+		description: "GitHub logins treated like the owner; each must be a member of the organization.",
+		required: false,
+		kind: FieldKind::Logins,
+	},
+	Field {
+		name: "matrix_room_id",
+		description: "Matrix room ID of the project, eg. !VDLSjvBpNqqgyMDIBO:matrix.parity.io.",
+		required: true,
+		kind: FieldKind::RoomId,
+	},
+	Field {
+		name: "backlog",
+		description: "Name of the project column used as a backlog.",
+		required: false,
+		kind: FieldKind::Text,
+	},
+	Field {
+		name: "paths",
+		description: "Globs matching paths owned by the project, eg. client/network/**. Pull requests changing them belong to the project without being on its board.",
+		required: false,
+		kind: FieldKind::Globs,
+	},
+];
+
+/// Returns the JSON schema describing the Process file, which `Process.schema.json` holds a copy
+/// of for editors.
+pub fn process_schema() -> Value {
+	let properties = FIELDS
+		.iter()
+		.map(|field| {
+			let mut property = match field.kind {
+				FieldKind::Name => json!({ "type": "string", "minLength": 1 }),
+				FieldKind::Text => json!({ "type": "string" }),
+				FieldKind::RoomId => json!({
+					"type": "string",
+					"pattern": matrix::ROOM_ID_PATTERN,
+				}),
+				FieldKind::Logins | FieldKind::Globs => json!({
+					"type": "array",
+					"items": { "type": "string", "minLength": 1 },
+				}),
+			};
+			// serde reads a null optional field as if it were missing
+			if !field.required {
+				let kind = property["type"].clone();
+				property["type"] = json!([kind, "null"]);
+			}
+			property["description"] = json!(field.description);
+			(field.name.to_string(), property)
+		})
+		.collect::<serde_json::Map<String, Value>>();
+	json!({
+		"$schema": "http://json-schema.org/draft-07/schema#",
+		"title": "Process.json",
+		"description": "Projects of a repository, their owners and the Matrix rooms notifications are sent to.",
+		"type": "array",
+		"items": {
+			"type": "object",
+			"required": FIELDS
+				.iter()
+				.filter(|field| field.required)
+				.map(|field| field.name)
+				.collect::<Vec<&str>>(),
+			"additionalProperties": false,
+			"properties": properties,
+		},
+	})
+}
+
+/// Paths a Process file is looked for at, in order of precedence.
+pub const PROCESS_FILES: &[(&str, ProcessFormat)] = &[
+	(PROCESS_FILE, ProcessFormat::Json),
+	(PROCESS_TOML_FILE, ProcessFormat::Toml),
+	(PROCESS_YAML_FILE, ProcessFormat::Yaml),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProcessFormat {
+	Json,
+	/// Each project is a table named after the project, eg. `[Networking]`.
+	Toml,
+	Yaml,
+}
+
+impl fmt::Display for ProcessFormat {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ProcessFormat::Json => write!(f, "JSON"),
+			ProcessFormat::Toml => write!(f, "TOML"),
+			ProcessFormat::Yaml => write!(f, "YAML"),
+		}
+	}
+}
+
+impl ProcessFormat {
+	/// Returns the format of a Process file from the extension of its path.
+	pub fn from_path(path: &str) -> Option<Self> {
+		if path.ends_with(".json") {
+			Some(ProcessFormat::Json)
+		} else if path.ends_with(".toml") {
+			Some(ProcessFormat::Toml)
+		} else if path.ends_with(".yml") || path.ends_with(".yaml") {
+			Some(ProcessFormat::Yaml)
+		} else {
+			None
+		}
+	}
+
+	/// Parse the contents of a Process file into the layout described by `process_schema`.
+	pub fn to_value(self, contents: &str) -> Result<Value, String> {
+		match self {
+			ProcessFormat::Json => {
+				serde_json::from_str(contents).map_err(|e| e.to_string())
+			}
+			ProcessFormat::Yaml => {
+				serde_yaml::from_str(contents).map_err(|e| e.to_string())
+			}
+			ProcessFormat::Toml => {
+				toml::from_str::<Tables>(&quote_table_names(contents))
+					.map_err(|e| e.to_string())
+					.and_then(projects_from_tables)
+			}
+		}
+	}
+
+	pub fn parse(self, contents: &str) -> Result<Vec<ProcessInfo>> {
+		self.to_value(contents)
+			.map_err(|msg| error::Error::Message {
+				msg: format!("Invalid {} Process file: {}", self, msg),
+			})
+			.and_then(|v| serde_json::from_value(v).context(error::Json))
+	}
+}
+
+lazy_static::lazy_static! {
+	/// A table header whose name contains whitespace and is not quoted.
+	static ref UNQUOTED_TABLE_NAME: Regex = Regex::new(
+		r#"(?m)^\[([^\["'\]]*[[:blank:]][^\["'\]]*)\][[:blank:]]*$"#
+	)
+	.expect("table name pattern is valid");
+}
+
+/// Quote TOML table names containing whitespace, eg. `[Smart Contracts]`, which TOML requires but
+/// project names rarely have.
+fn quote_table_names(contents: &str) -> String {
+	UNQUOTED_TABLE_NAME
+		.replace_all(contents, |caps: &Captures| {
+			format!("[\"{}\"]", caps[1].trim())
+		})
+		.to_string()
+}
+
+/// The top-level tables of a TOML Process file in the order they appear, which a `toml::Value`
+/// would sort by name.
+struct Tables(Vec<(String, toml::Value)>);
+
+impl<'de> Deserialize<'de> for Tables {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		struct TablesVisitor;
+
+		impl<'de> Visitor<'de> for TablesVisitor {
+			type Value = Tables;
+
+			fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+				write!(f, "a table per project")
+			}
+
+			fn visit_map<A>(self, mut map: A) -> Result<Tables, A::Error>
+			where
+				A: MapAccess<'de>,
+			{
+				let mut tables = vec![];
+				while let Some(entry) = map.next_entry()? {
+					tables.push(entry);
+				}
+				Ok(Tables(tables))
+			}
+		}
+
+		deserializer.deserialize_map(TablesVisitor)
+	}
+}
+
+/// Turn the tables of a TOML Process file into entries named after them, in document order.
+fn projects_from_tables(tables: Tables) -> Result<Value, String> {
+	tables
+		.0
+		.into_iter()
+		.map(|(name, table)| {
+			let mut table =
+				serde_json::to_value(table).map_err(|e| e.to_string())?;
+			if let Value::Object(fields) = &mut table {
+				fields.entry("project_name").or_insert(Value::String(name));
+			}
+			Ok(table)
+		})
+		.collect::<Result<Vec<Value>, String>>()
+		.map(Value::Array)
+}
+
+/// A Process file fetched from a repository.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProcessFile {
+	pub path: &'static str,
+	pub format: ProcessFormat,
+	pub contents: String,
+	/// Other Process files present in the repository, which are ignored.
+	pub ignored: Vec<&'static str>,
+}
+
+impl ProcessFile {
+	pub fn process(&self) -> Result<Vec<ProcessInfo>> {
+		self.format.parse(&self.contents)
+	}
+
+	/// Returns a diagnostic for each Process file ignored in favour of this one.
+	pub fn conflicts(&self) -> Vec<Diagnostic> {
+		self.ignored
+			.iter()
+			.map(|path| Diagnostic {
+				entry: None,
+				message: format!(
+					"`{}` is ignored because `{}` takes precedence; remove one of them",
+					path, self.path
+				),
+			})
+			.collect()
+	}
+}
+
+/// Fetch the Process file of a repository at `ref_field`, or `None` if there is none.
+pub async fn process_file(
+	github_bot: &GithubBot,
+	owner: &str,
+	repo_name: &str,
+	ref_field: &str,
+) -> Result<Option<ProcessFile>> {
+	let mut found = vec![];
+	for (path, format) in PROCESS_FILES {
+		if let Some(contents) = github_bot
+			.file_contents(owner, repo_name, path, ref_field)
+			.await?
+		{
+			found.push((*path, *format, contents));
+		}
+	}
+
+	let mut found = found.into_iter();
+	match found.next() {
+		Some((path, format, contents)) => Ok(Some(ProcessFile {
+			path,
+			format,
+			contents: decode_contents(contents)?,
+			ignored: found.map(|(path, ..)| path).collect(),
+		})),
+		None => Ok(None),
+	}
+}
 
 #[derive(Clone, Debug)]
 pub struct CombinedProcessInfo(Vec<ProcessInfo>);
@@ -54,6 +375,11 @@ pub struct ProcessInfo {
 	pub whitelist: Vec<String>,
 	pub matrix_room_id: String,
 	pub backlog: Option<String>,
+	/// Globs matching the paths owned by the project, eg. `client/network/**`. Pull requests
+	/// changing matching paths belong to the project whether or not they are on its board. A
+	/// Process file with a malformed glob fails to parse.
+	#[serde(default)]
+	pub paths: Vec<GlobPattern>,
 }
 
 impl ProcessInfo {
@@ -92,6 +418,11 @@ impl ProcessInfo {
 			|| self.is_delegated_reviewer(login)
 			|| self.is_whitelisted(login)
 	}
+
+	/// Checks if the path matches one of the project's path globs.
+	pub fn owns_path(&self, path: &str) -> bool {
+		self.paths.iter().any(|pattern| pattern.matches(path))
+	}
 }
 
 pub async fn get_process(
@@ -101,20 +432,89 @@ pub async fn get_process(
 	issue_number: i64,
 ) -> Result<CombinedProcessInfo> {
 	// get Process file from master
-	let process = github_bot
-		.contents(owner, repo_name, "Process.json", "master")
+	let file = process_file(github_bot, owner, repo_name, "master")
+		.await?
+		.ok_or_else(|| error::Error::Message {
+			msg: format!(
+				"No Process file found in {} (looked for {})",
+				repo_name,
+				PROCESS_FILES.iter().map(|(path, _)| *path).join(", ")
+			),
+		})?;
+	log::info!("Using {} for {}", file.path, repo_name);
+	if !file.ignored.is_empty() {
+		log::warn!(
+			"Ignoring {} in {} as {} takes precedence",
+			file.ignored.join(", "),
+			repo_name,
+			file.path
+		);
+	}
+	let process = file.process()?;
+
+	// projects owning the changed paths take precedence over project boards
+	if process.iter().any(|proc| !proc.paths.is_empty()) {
+		let paths = github_bot
+			.pull_request_files(owner, repo_name, issue_number)
+			.await?
+			.into_iter()
+			.map(|file| file.filename)
+			.collect::<Vec<String>>();
+		let owning = process_matching_paths(&process, &paths);
+		if !owning.is_empty() {
+			return Ok(CombinedProcessInfo(owning));
+		}
+	}
+
+	// projects v2 replace classic projects, which are still checked while repos migrate
+	match projects_v2_process_info(
+		github_bot,
+		owner,
+		repo_name,
+		issue_number,
+		&process,
+	)
+	.await
+	{
+		Ok(combined) if !combined.is_empty() => Ok(combined),
+		v2 => classic_process_info(
+			github_bot,
+			owner,
+			repo_name,
+			issue_number,
+			process,
+		)
 		.await
-		.and_then(process::process_from_contents)?;
+		.or_else(|e| {
+			log::warn!("Error getting classic projects: {}", e);
+			v2
+		}),
+	}
+}
 
-	// repos with no projects can have no valid process info
-	let projects = github_bot.projects(owner, repo_name).await?;
+/// Return the process entries owning any of the given paths.
+fn process_matching_paths(
+	process: &[ProcessInfo],
+	paths: &[String],
+) -> Vec<ProcessInfo> {
+	process
+		.iter()
+		.filter(|proc| paths.iter().any(|path| proc.owns_path(path)))
+		.cloned()
+		.collect::<_>()
+}
 
-	// ignore process entries that do not match a project in the repository
-	let process = process
-		.into_iter()
+/// Keep only the process entries that match one of `project_names`.
+fn process_matching_project_names(
+	process: &[ProcessInfo],
+	project_names: &[&str],
+	repo_name: &str,
+) -> Vec<ProcessInfo> {
+	process
+		.iter()
 		.filter(|proc| {
 			let keep =
-				projects.iter().any(|proj| proj.name == proc.project_name);
+				project_names.iter().any(|name| name == &proc.project_name);
 			if !keep {
 				log::warn!(
 					"'{proc_name}' doesn not match any projects in repository '{repo_name}'",
@@ -124,7 +524,84 @@ pub async fn get_process(
 			}
 			keep
 		})
-		.collect::<Vec<ProcessInfo>>();
+		.cloned()
+		.collect::<Vec<ProcessInfo>>()
+}
+
+/// Return a CombinedProcessInfo struct representing each process entry that matches a project
+/// (v2) the pull request has been given a status in.
+async fn projects_v2_process_info(
+	github_bot: &GithubBot,
+	owner: &str,
+	repo_name: &str,
+	number: i64,
+	process: &[ProcessInfo],
+) -> Result<CombinedProcessInfo> {
+	// ignore process entries that do not match a project linked to the repository
+	let projects = github_bot.projects_v2(owner, repo_name).await?;
+	let process = process_matching_project_names(
+		process,
+		&projects
+			.iter()
+			.map(|proj| proj.title.as_str())
+			.collect::<Vec<&str>>(),
+		repo_name,
+	);
+
+	let items = github_bot
+		.pull_request_project_items(owner, repo_name, number)
+		.await?;
+
+	Ok(CombinedProcessInfo(process_matching_project_items(
+		&process, &items,
+	)))
+}
+
+/// Return process entries matching the projects of the given items, in the order of the items.
+/// Like a classic project card without a column, an item without a status is not recognised.
+fn process_matching_project_items(
+	process: &[ProcessInfo],
+	items: &[github::ProjectV2Item],
+) -> Vec<ProcessInfo> {
+	items
+		.iter()
+		.filter(|item| {
+			item.project.closed != Some(true)
+				&& item
+					.status
+					.as_ref()
+					.and_then(|status| status.name.as_ref())
+					.is_some()
+		})
+		.filter_map(|item| {
+			process
+				.iter()
+				.find(|proc| proc.project_name == item.project.title)
+		})
+		.cloned()
+		.collect::<_>()
+}
+
+/// Return a CombinedProcessInfo struct using classic projects.
+async fn classic_process_info(
+	github_bot: &GithubBot,
+	owner: &str,
+	repo_name: &str,
+	issue_number: i64,
+	process: Vec<ProcessInfo>,
+) -> Result<CombinedProcessInfo> {
+	// repos with no projects can have no valid process info
+	let projects = github_bot.projects(owner, repo_name).await?;
+
+	// ignore process entries that do not match a project in the repository
+	let process = process_matching_project_names(
+		&process,
+		&projects
+			.iter()
+			.map(|proj| proj.name.as_str())
+			.collect::<Vec<&str>>(),
+		repo_name,
+	);
 
 	combined_process_info(
 		github_bot,
@@ -137,13 +614,203 @@ pub async fn get_process(
 	.await
 }
 
-fn process_from_contents(c: github::Contents) -> Result<Vec<ProcessInfo>> {
+/// Decode the text of a file returned by the contents API.
+pub fn decode_contents(c: github::Contents) -> Result<String> {
 	base64::decode(&c.content.replace("\n", ""))
 		.context(error::Base64)
-		.and_then(|b| {
-			let s = String::from_utf8(b).context(error::Utf8)?;
-			serde_json::from_str(&s).context(error::Json)
-		})
+		.and_then(|b| String::from_utf8(b).context(error::Utf8))
+}
+
+/// A problem found in a Process file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+	/// Position of the offending entry in the file, or `None` if the problem concerns the whole
+	/// file.
+	pub entry: Option<usize>,
+	pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.entry {
+			// entries are numbered from 1 for people reading the file
+			Some(i) => write!(f, "entry {}: {}", i + 1, self.message),
+			None => write!(f, "{}", self.message),
+		}
+	}
+}
+
+/// Check the contents of a Process file against `process_schema`, without contacting GitHub.
+pub fn check_process_file(
+	format: ProcessFormat,
+	contents: &str,
+) -> Vec<Diagnostic> {
+	let file_error = |message: String| {
+		vec![Diagnostic {
+			entry: None,
+			message,
+		}]
+	};
+	let value = match format.to_value(contents) {
+		Ok(value) => value,
+		Err(e) => return file_error(format!("invalid {}: {}", format, e)),
+	};
+	let entries = match value.as_array() {
+		Some(entries) => entries,
+		None => {
+			return file_error(
+				"expected an array of projects at the top level".to_string(),
+			)
+		}
+	};
+
+	let mut diagnostics = vec![];
+	let mut project_names = vec![];
+	for (i, entry) in entries.iter().enumerate() {
+		let mut report = |message: String| {
+			diagnostics.push(Diagnostic {
+				entry: Some(i),
+				message,
+			})
+		};
+		let fields = match entry.as_object() {
+			Some(fields) => fields,
+			None => {
+				report("expected an object".to_string());
+				continue;
+			}
+		};
+
+		for field in FIELDS {
+			let name = field.name;
+			let value = match fields.get(name) {
+				None if field.required => {
+					report(format!("missing field `{}`", name));
+					continue;
+				}
+				None => continue,
+				Some(Value::Null) if !field.required => continue,
+				Some(value) => value,
+			};
+			match (field.kind, value) {
+				(FieldKind::Name, Value::String(s))
+				| (FieldKind::RoomId, Value::String(s))
+					if s.trim().is_empty() =>
+				{
+					report(format!("field `{}` is empty", name))
+				}
+				(FieldKind::RoomId, Value::String(room_id))
+					if !matrix::is_room_id(room_id) =>
+				{
+					report(format!(
+						"`{}` is not a Matrix room ID (expected eg. `!VDLSjvBpNqqgyMDIBO:matrix.parity.io`)",
+						room_id
+					))
+				}
+				(FieldKind::Name, Value::String(_))
+				| (FieldKind::Text, Value::String(_))
+				| (FieldKind::RoomId, Value::String(_)) => {}
+				(FieldKind::Name, _)
+				| (FieldKind::Text, _)
+				| (FieldKind::RoomId, _) => {
+					report(format!("field `{}` must be a string", name))
+				}
+				(FieldKind::Logins, Value::Array(users))
+					if users.iter().all(|user| {
+						user.as_str().map_or(false, |user| !user.is_empty())
+					}) => {}
+				(FieldKind::Logins, _) => report(format!(
+					"field `{}` must be an array of GitHub logins",
+					name
+				)),
+				(FieldKind::Globs, Value::Array(paths))
+					if paths.iter().all(|path| {
+						path.as_str().map_or(false, |path| !path.is_empty())
+					}) =>
+				{
+					for path in paths.iter().filter_map(Value::as_str) {
+						if let Err(e) = glob::Pattern::new(path) {
+							report(format!(
+								"path pattern `{}` is invalid: {}",
+								path, e
+							));
+						}
+					}
+				}
+				(FieldKind::Globs, _) => report(format!(
+					"field `{}` must be an array of path globs",
+					name
+				)),
+			}
+		}
+		for name in fields
+			.keys()
+			.filter(|name| !FIELDS.iter().any(|field| &field.name == name))
+		{
+			report(format!("unknown field `{}`", name));
+		}
+
+		if let Some(Value::String(name)) = fields.get("project_name") {
+			if project_names.contains(name) {
+				report(format!("project '{}' is listed more than once", name));
+			}
+			project_names.push(name.clone());
+		}
+	}
+	diagnostics
+}
+
+/// Check that the projects listed in a Process file exist in the repository and that every user
+/// listed is a member of the organization.
+pub async fn check_process_against_repo(
+	github_bot: &GithubBot,
+	owner: &str,
+	repo_name: &str,
+	process: &[ProcessInfo],
+) -> Result<Vec<Diagnostic>> {
+	// a project may be either classic or v2, so only fail if neither can be listed
+	let project_names = match (
+		github_bot.projects(owner, repo_name).await,
+		github_bot.projects_v2(owner, repo_name).await,
+	) {
+		(Err(e), Err(_)) => return Err(e),
+		(classic, v2) => classic
+			.into_iter()
+			.flatten()
+			.map(|proj| proj.name)
+			.chain(v2.into_iter().flatten().map(|proj| proj.title))
+			.collect::<Vec<String>>(),
+	};
+
+	let mut diagnostics = vec![];
+	for (i, proc) in process.iter().enumerate() {
+		// projects owning paths need not have a board
+		if proc.paths.is_empty() && !project_names.contains(&proc.project_name)
+		{
+			diagnostics.push(Diagnostic {
+				entry: Some(i),
+				message: format!(
+					"project '{}' does not exist in repository '{}'",
+					proc.project_name, repo_name
+				),
+			});
+		}
+		let users = std::iter::once(&proc.owner)
+			.chain(proc.delegated_reviewer.iter())
+			.chain(proc.whitelist.iter());
+		for user in users {
+			if !github_bot.org_member(owner, user).await? {
+				diagnostics.push(Diagnostic {
+					entry: Some(i),
+					message: format!(
+						"'{}' is not a member of the organization '{}'",
+						user, owner
+					),
+				});
+			}
+		}
+	}
+	Ok(diagnostics)
 }
 
 /// Return a CombinedProcessInfo struct representing together each process entry that matches a
@@ -208,6 +875,249 @@ async fn combined_process_info(
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use std::convert::TryFrom;
+
+	fn process_info(
+		project_name: &str,
+		owner: &str,
+		paths: &[&str],
+	) -> ProcessInfo {
+		ProcessInfo {
+			project_name: project_name.to_string(),
+			owner: owner.to_string(),
+			delegated_reviewer: None,
+			whitelist: vec![],
+			matrix_room_id: format!("!room:matrix.parity.io"),
+			backlog: None,
+			paths: paths
+				.iter()
+				.map(|p| GlobPattern::try_from(p.to_string()).expect("glob"))
+				.collect(),
+		}
+	}
+
+	#[test]
+	fn test_process_matching_project_items() {
+		fn item(
+			title: &str,
+			status: Option<&str>,
+			closed: bool,
+		) -> github::ProjectV2Item {
+			github::ProjectV2Item {
+				project: github::ProjectV2 {
+					id: None,
+					number: 1,
+					title: title.to_string(),
+					closed: Some(closed),
+				},
+				status: Some(github::ProjectV2FieldValue {
+					name: status.map(|s| s.to_string()),
+				}),
+			}
+		}
+
+		let process = vec![
+			process_info("Networking", "tomaka", &[]),
+			process_info("Runtime", "gavofyork", &[]),
+			process_info("Client", "gnunicorn", &[]),
+		];
+		assert_eq!(
+			process_matching_project_items(
+				&process,
+				&[
+					item("Runtime", Some("In Progress"), false),
+					item("Networking", None, false),
+					item("Client", Some("Done"), true),
+					item("Unknown", Some("Todo"), false),
+				]
+			),
+			vec![process_info("Runtime", "gavofyork", &[])]
+		);
+	}
+
+	#[test]
+	fn test_check_process_file() {
+		assert!(check_process_file(
+			ProcessFormat::Json,
+			include_str!("../Process.json")
+		)
+		.is_empty());
+		assert_eq!(
+			check_process_file(
+				ProcessFormat::Json,
+				r#"[
+					{ "project_name": "Networking", "owner": "tomaka", "matrix_room_id": "!vUADSGcyXmxhKLeDsW:matrix.parity.io" },
+					{ "project_name": "Client", "matrix_room_id": "#client:matrix.parity.io", "whitelist": "gnunicorn" },
+					{ "project_name": "Networking", "owner": "", "delegate": "tomaka", "matrix_room_id": "!XdNWDTfVNFVixljKZU:matrix.parity.io" }
+				]"#
+			)
+			.into_iter()
+			.map(|d| d.to_string())
+			.collect::<Vec<String>>(),
+			vec![
+				"entry 2: missing field `owner`",
+				"entry 2: field `whitelist` must be an array of GitHub logins",
+				"entry 2: `#client:matrix.parity.io` is not a Matrix room ID (expected eg. `!VDLSjvBpNqqgyMDIBO:matrix.parity.io`)",
+				"entry 3: field `owner` is empty",
+				"entry 3: unknown field `delegate`",
+				"entry 3: project 'Networking' is listed more than once",
+			]
+		);
+		assert_eq!(
+			check_process_file(
+				ProcessFormat::Json,
+				r#"{ "project_name": "Networking" }"#
+			),
+			vec![Diagnostic {
+				entry: None,
+				message: "expected an array of projects at the top level"
+					.to_string()
+			}]
+		);
+	}
+
+	#[test]
+	fn test_process_matching_paths() {
+		let process = vec![
+			process_info("Networking", "tomaka", &["client/network/**"]),
+			process_info(
+				"Runtime",
+				"gavofyork",
+				&["frame/**", "bin/node/runtime/**"],
+			),
+			process_info("Client", "gnunicorn", &[]),
+		];
+		assert_eq!(
+			process_matching_paths(
+				&process,
+				&[
+					"bin/node/runtime/src/lib.rs".to_string(),
+					"README.md".to_string()
+				]
+			),
+			vec![process_info(
+				"Runtime",
+				"gavofyork",
+				&["frame/**", "bin/node/runtime/**"]
+			)]
+		);
+		assert!(process_matching_paths(&process, &["README.md".to_string()])
+			.is_empty());
+	}
+
+	#[test]
+	fn test_invalid_path_pattern() {
+		let contents = r#"[
+			{ "project_name": "Networking", "owner": "tomaka", "matrix_room_id": "!vUADSGcyXmxhKLeDsW:matrix.parity.io", "paths": ["client/***"] }
+		]"#;
+		assert!(ProcessFormat::Json.parse(contents).is_err());
+		assert_eq!(
+			check_process_file(ProcessFormat::Json, contents)
+				.into_iter()
+				.map(|d| d.message)
+				.collect::<Vec<String>>(),
+			vec![format!(
+				"path pattern `client/***` is invalid: {}",
+				glob::Pattern::new("client/***").expect_err("invalid glob")
+			)]
+		);
+	}
+
+	#[test]
+	fn test_process_formats() {
+		let expected = vec![
+			ProcessInfo {
+				project_name: "Networking".to_string(),
+				owner: "tomaka".to_string(),
+				delegated_reviewer: None,
+				whitelist: vec![],
+				matrix_room_id: "!vUADSGcyXmxhKLeDsW:matrix.parity.io"
+					.to_string(),
+				backlog: None,
+				paths: vec![],
+			},
+			ProcessInfo {
+				project_name: "Smart Contracts".to_string(),
+				owner: "pepyakin".to_string(),
+				delegated_reviewer: None,
+				whitelist: vec!["athei".to_string()],
+				matrix_room_id: "!yBKstWVBkwzUkPslsp:matrix.parity.io"
+					.to_string(),
+				backlog: None,
+				paths: vec![],
+			},
+		];
+		assert_eq!(
+			ProcessFormat::Toml
+				.parse(
+					r#"
+[Networking]
+owner = "tomaka"
+matrix_room_id = "!vUADSGcyXmxhKLeDsW:matrix.parity.io"
+
+[Smart Contracts]
+owner = "pepyakin"
+whitelist = ["athei"]
+matrix_room_id = "!yBKstWVBkwzUkPslsp:matrix.parity.io"
+"#
+				)
+				.expect("parse toml"),
+			expected
+		);
+		assert_eq!(
+			ProcessFormat::Yaml
+				.parse(
+					r#"
+- project_name: Networking
+  owner: tomaka
+  matrix_room_id: "!vUADSGcyXmxhKLeDsW:matrix.parity.io"
+- project_name: Smart Contracts
+  owner: pepyakin
+  whitelist: [athei]
+  matrix_room_id: "!yBKstWVBkwzUkPslsp:matrix.parity.io"
+"#
+				)
+				.expect("parse yaml"),
+			expected
+		);
+		assert_eq!(
+			ProcessFormat::Toml
+				.parse(
+					r#"
+[Smart Contracts]
+owner = "pepyakin"
+matrix_room_id = "!yBKstWVBkwzUkPslsp:matrix.parity.io"
+
+[Networking]
+owner = "tomaka"
+matrix_room_id = "!vUADSGcyXmxhKLeDsW:matrix.parity.io"
+"#
+				)
+				.expect("parse toml")
+				.into_iter()
+				.map(|proc| proc.project_name)
+				.collect::<Vec<String>>(),
+			vec!["Smart Contracts", "Networking"],
+			"projects keep the order of the file"
+		);
+		assert_eq!(
+			ProcessFormat::from_path(".github/process.yml"),
+			Some(ProcessFormat::Yaml)
+		);
+		assert_eq!(ProcessFormat::from_path("Process.txt"), None);
+	}
+
+	#[test]
+	fn test_process_schema() {
+		assert_eq!(
+			serde_json::from_str::<Value>(include_str!(
+				"../Process.schema.json"
+			))
+			.expect("parse schema"),
+			process_schema(),
+			"Process.schema.json is out of date; regenerate it with `parity-processbot validate --schema`"
+		);
+	}
 
 	#[test]
 	fn test_process_json() {
@@ -224,6 +1134,7 @@ mod tests {
 				whitelist: vec![],
 				matrix_room_id: format!("!VDLSjvBpNqqgyMDIBO:matrix.parity.io"),
 				backlog: None,
+				paths: vec![],
 			},]
 		);
 	}
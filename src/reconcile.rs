@@ -0,0 +1,161 @@
+use chrono::Utc;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::{
+	admin::{pending_merges, PendingMerge},
+	features::{self, Feature},
+	github_bot::GithubBot,
+	server::shutting_down,
+	webhook::{
+		checks_and_status, handle_deferred, handle_error, AppState,
+		MergeRequest,
+	},
+	Result,
+};
+
+/// Every `interval`, checks pending merge requests in case the webhook that would have
+/// completed them was missed, and pull requests deferred until the rate limit quota recovered.
+pub async fn reconcile_loop(state: Arc<Mutex<AppState>>, interval: Duration) {
+	loop {
+		tokio::time::delay_for(interval).await;
+		if shutting_down() {
+			break;
+		}
+		log::info!("Reconciling pending merge requests");
+		reconcile(&state).await;
+		handle_deferred(&*state.lock().await).await;
+	}
+}
+
+/// Checks each pending merge request: merges it if its checks are green, drops it if its pull
+/// request was closed, merged or pushed to, and drops it with a comment once it is older than
+/// `merge_request_expiry`. The state is locked for one merge request at a time, so that webhooks
+/// are handled in between.
+pub async fn reconcile(state: &Mutex<AppState>) {
+	let pending = match pending_merges(&state.lock().await.db) {
+		Ok(pending) => pending,
+		Err(e) => {
+			log::error!("Error reading pending merge requests: {}", e);
+			return;
+		}
+	};
+	for PendingMerge { sha, .. } in pending {
+		if shutting_down() {
+			return;
+		}
+		let state = &*state.lock().await;
+		// a webhook handled since the list was read may have merged or cancelled it
+		let merge_request = match state.db.get::<MergeRequest>(&sha) {
+			Ok(Some(merge_request)) => merge_request,
+			Ok(None) => continue,
+			Err(e) => {
+				log::error!("Error reading merge request {}: {}", sha, e);
+				continue;
+			}
+		};
+		let pending = PendingMerge { sha, merge_request };
+		if let Err(e) = reconcile_merge(&pending, state).await {
+			handle_error(e, state).await;
+		}
+	}
+}
+
+async fn reconcile_merge(
+	pending: &PendingMerge,
+	state: &AppState,
+) -> Result<()> {
+	let PendingMerge {
+		sha,
+		merge_request:
+			MergeRequest {
+				owner,
+				repo_name,
+				number,
+				html_url,
+				requested_at,
+				..
+			},
+	} = pending;
+	// checked before anything is asked of GitHub, so that a request whose pull request can no
+	// longer be fetched is still dropped eventually
+	let expiry =
+		chrono::Duration::seconds(state.bot_config.merge_request_expiry as i64);
+	if Utc::now() - *requested_at > expiry {
+		log::info!("Merge request for {} expired", html_url);
+		state.db.delete::<MergeRequest>(sha)?;
+		let github_bot = state.github_bot.for_account(owner).await?;
+		let comment = match held_for(&github_bot, pending, state).await {
+			Some(held_for) => format!(
+				"Merge request expired after waiting {} hours {}; post `bot merge` again to retry.",
+				expiry.num_hours(),
+				held_for
+			),
+			None => format!(
+				"Merge request expired after {} hours; post `bot merge` again to retry.",
+				expiry.num_hours()
+			),
+		};
+		let _ = github_bot
+			.create_issue_comment(owner, repo_name, *number, &comment)
+			.await
+			.map_err(|e| {
+				log::error!("Error posting comment: {}", e);
+			});
+		return Ok(());
+	}
+
+	if !features::enabled(&state.db, owner, repo_name, Feature::Merge)? {
+		log::info!("Merging is turned off; leaving {} pending", html_url);
+		return Ok(());
+	}
+
+	let github_bot = state.github_bot.for_account(owner).await?;
+	let pr = github_bot.pull_request(owner, repo_name, *number).await?;
+	if pr.merged == Some(true) || pr.state.as_deref() == Some("closed") {
+		log::info!(
+			"{} was closed while its merge was pending; dropping the request",
+			html_url
+		);
+		state.db.delete::<MergeRequest>(sha)?;
+		return Ok(());
+	}
+
+	checks_and_status(&github_bot, &state.bot_config, sha, &state.db).await
+}
+
+/// Describes what held a pending merge back, eg. `for checks`, or returns `None` if its pull
+/// request cannot be fetched.
+async fn held_for(
+	github_bot: &GithubBot,
+	pending: &PendingMerge,
+	state: &AppState,
+) -> Option<String> {
+	let MergeRequest {
+		owner,
+		repo_name,
+		number,
+		..
+	} = &pending.merge_request;
+	if let Ok(false) =
+		features::enabled(&state.db, owner, repo_name, Feature::Merge)
+	{
+		return Some("for merging to be turned on".to_owned());
+	}
+	let pr = match github_bot.pull_request(owner, repo_name, *number).await {
+		Ok(pr) => pr,
+		Err(e) => {
+			log::warn!(
+				"Error fetching {}: {}",
+				pending.merge_request.html_url,
+				e
+			);
+			return None;
+		}
+	};
+	match state.bot_config.wip_rules.for_repo(repo_name).reason(&pr) {
+		Some(reason) => Some(format!("because {}", reason)),
+		None => Some("for checks".to_owned()),
+	}
+}
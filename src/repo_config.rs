@@ -0,0 +1,281 @@
+/*
+A repository can override the global settings of the bot in `.github/processbot.toml` on its
+default branch, eg.
+
+```toml
+min_reviewers = 3
+core_devs_team = "runtime-devs"
+team_leads_team = "runtime-leads"
+companions = true
+compare = false
+burnin = true
+gitlab_job_name = "build-linux-release"
+```
+
+Every key is optional; a missing one keeps the global setting. The file is cached with the other
+files the bot reads from repositories, until a push changes it. An invalid file is ignored, and
+pull requests changing it get a check run listing its problems.
+*/
+
+use serde::Deserialize;
+
+use crate::{
+	config::BotConfig, constants::REPO_CONFIG_FILE, error::Error,
+	github_bot::GithubBot, process::decode_contents, Result,
+};
+
+/// Overrides of the global settings read from a repository.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RepoConfig {
+	/// approvals from members of `core_devs_team` needed to merge
+	pub min_reviewers: Option<usize>,
+	/// slug of the team whose approvals count towards `min_reviewers`
+	pub core_devs_team: Option<String>,
+	/// slug of the team whose members may merge on their own or approve alone
+	pub team_leads_team: Option<String>,
+	/// whether merged pull requests update the companion they link to
+	pub companions: Option<bool>,
+	/// whether `bot compare substrate` is accepted
+	pub compare: Option<bool>,
+	/// whether `bot burnin` is accepted
+	pub burnin: Option<bool>,
+	/// name of the Gitlab CI job `bot burnin` starts
+	pub gitlab_job_name: Option<String>,
+}
+
+/// Settings in effect for a repository.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RepoSettings {
+	pub min_reviewers: usize,
+	pub core_devs_team: String,
+	pub team_leads_team: String,
+	pub companions: bool,
+	pub compare: bool,
+	pub burnin: bool,
+	/// `None` for the job named by `GITLAB_JOB_NAME`
+	pub gitlab_job_name: Option<String>,
+}
+
+impl RepoConfig {
+	/// Parses and validates the contents of a repository config file, returning every problem
+	/// found if it is not usable.
+	pub fn parse(contents: &str) -> Result<Self, Vec<String>> {
+		let config = toml::from_str::<RepoConfig>(contents)
+			.map_err(|e| vec![format!("invalid TOML: {}", e)])?;
+		let problems = config.problems();
+		if problems.is_empty() {
+			Ok(config)
+		} else {
+			Err(problems)
+		}
+	}
+
+	fn problems(&self) -> Vec<String> {
+		let mut problems = vec![];
+		if self.min_reviewers == Some(0) {
+			problems.push("`min_reviewers` must be at least 1".to_string());
+		}
+		for (key, value) in [
+			("core_devs_team", &self.core_devs_team),
+			("team_leads_team", &self.team_leads_team),
+			("gitlab_job_name", &self.gitlab_job_name),
+		]
+		.iter()
+		{
+			if value
+				.as_ref()
+				.map_or(false, |value| value.trim().is_empty())
+			{
+				problems.push(format!("`{}` is empty", key));
+			}
+		}
+		problems
+	}
+
+	/// Returns the settings of `repo_name`, taking those it does not override from `bot_config`.
+	pub fn settings(
+		&self,
+		repo_name: &str,
+		bot_config: &BotConfig,
+	) -> RepoSettings {
+		let listed =
+			|repos: &[String]| repos.iter().any(|repo| repo == repo_name);
+		RepoSettings {
+			min_reviewers: self
+				.min_reviewers
+				.unwrap_or(bot_config.min_reviewers),
+			core_devs_team: self
+				.core_devs_team
+				.clone()
+				.unwrap_or_else(|| bot_config.core_devs_team.clone()),
+			team_leads_team: self
+				.team_leads_team
+				.clone()
+				.unwrap_or_else(|| bot_config.team_leads_team.clone()),
+			companions: self
+				.companions
+				.unwrap_or_else(|| listed(&bot_config.companion_repos)),
+			compare: self
+				.compare
+				.unwrap_or_else(|| listed(&bot_config.compare_repos)),
+			burnin: self.burnin.unwrap_or(true),
+			gitlab_job_name: self.gitlab_job_name.clone(),
+		}
+	}
+}
+
+/// Fetch the config file of a repository at `ref_field`, or `None` if there is none.
+pub async fn repo_config_file(
+	github_bot: &GithubBot,
+	owner: &str,
+	repo_name: &str,
+	ref_field: &str,
+) -> Result<Option<String>> {
+	match github_bot
+		.file_contents(owner, repo_name, REPO_CONFIG_FILE, ref_field)
+		.await?
+	{
+		Some(contents) => decode_contents(contents).map(Some),
+		None => Ok(None),
+	}
+}
+
+/// Returns the settings in effect for a repository according to the config file on its default
+/// branch. The global settings apply if the file is missing or invalid.
+pub async fn repo_settings(
+	github_bot: &GithubBot,
+	bot_config: &BotConfig,
+	owner: &str,
+	repo_name: &str,
+	default_branch: &str,
+) -> Result<RepoSettings> {
+	let config =
+		match repo_config_file(github_bot, owner, repo_name, default_branch)
+			.await?
+		{
+			Some(contents) => {
+				RepoConfig::parse(&contents).unwrap_or_else(|problems| {
+					log::warn!(
+						"Ignoring invalid {} in {}/{}: {}",
+						REPO_CONFIG_FILE,
+						owner,
+						repo_name,
+						problems.join("; ")
+					);
+					RepoConfig::default()
+				})
+			}
+			None => RepoConfig::default(),
+		};
+	Ok(config.settings(repo_name, bot_config))
+}
+
+/// Check that the teams a repository config names exist in the organization.
+pub async fn check_repo_config_against_org(
+	github_bot: &GithubBot,
+	owner: &str,
+	config: &RepoConfig,
+) -> Result<Vec<String>> {
+	let mut problems = vec![];
+	for (key, slug) in [
+		("core_devs_team", &config.core_devs_team),
+		("team_leads_team", &config.team_leads_team),
+	]
+	.iter()
+	{
+		if let Some(slug) = slug {
+			match github_bot.team(owner, slug).await {
+				Ok(_) => {}
+				Err(Error::Response { status, .. })
+					if status == reqwest::StatusCode::NOT_FOUND =>
+				{
+					problems.push(format!(
+						"`{}`: team '{}' does not exist in the organization '{}'",
+						key, slug, owner
+					));
+				}
+				Err(e) => return Err(e),
+			}
+		}
+	}
+	Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{labels::LabelRules, wip::WipRules};
+
+	fn bot_config() -> BotConfig {
+		BotConfig {
+			merge_request_expiry: 7 * 24 * 60 * 60,
+			min_reviewers: 2,
+			core_devs_team: "core-devs".to_string(),
+			team_leads_team: "substrateteamleads".to_string(),
+			companion_repos: vec!["substrate".to_string()],
+			compare_repos: vec!["polkadot".to_string()],
+			label_rules: LabelRules::default(),
+			wip_rules: WipRules::default(),
+		}
+	}
+
+	#[test]
+	fn test_repo_settings() {
+		let bot_config = bot_config();
+		assert_eq!(
+			RepoConfig::default().settings("substrate", &bot_config),
+			RepoSettings {
+				min_reviewers: 2,
+				core_devs_team: "core-devs".to_string(),
+				team_leads_team: "substrateteamleads".to_string(),
+				companions: true,
+				compare: false,
+				burnin: true,
+				gitlab_job_name: None,
+			}
+		);
+
+		let config = RepoConfig::parse(
+			r#"
+min_reviewers = 1
+core_devs_team = "runtime-devs"
+companions = false
+compare = true
+burnin = false
+gitlab_job_name = "build-linux-release"
+"#,
+		)
+		.expect("parse");
+		assert_eq!(
+			config.settings("substrate", &bot_config),
+			RepoSettings {
+				min_reviewers: 1,
+				core_devs_team: "runtime-devs".to_string(),
+				team_leads_team: "substrateteamleads".to_string(),
+				companions: false,
+				compare: true,
+				burnin: false,
+				gitlab_job_name: Some("build-linux-release".to_string()),
+			}
+		);
+	}
+
+	#[test]
+	fn test_repo_config_problems() {
+		assert_eq!(
+			RepoConfig::parse(
+				"min_reviewers = 0\ncore_devs_team = \"\"\nburnin = true\n"
+			),
+			Err(vec![
+				"`min_reviewers` must be at least 1".to_string(),
+				"`core_devs_team` is empty".to_string(),
+			])
+		);
+		let problems = RepoConfig::parse("min_reviewer = 1\n")
+			.expect_err("unknown keys are rejected");
+		assert!(problems[0].contains("unknown field `min_reviewer`"));
+		assert!(RepoConfig::parse("burnin = \"yes\"\n").is_err());
+		assert_eq!(RepoConfig::parse(""), Ok(RepoConfig::default()));
+	}
+}
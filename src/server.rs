@@ -11,10 +11,39 @@ use hyper::{
 	Body, Request, Server,
 };
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::task::Poll;
+use std::time::Duration;
 use tokio::sync::Mutex;
 
+/// Set on SIGTERM or SIGINT, so that webhooks still waiting for the lock are refused.
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+pub fn shutting_down() -> bool {
+	SHUTTING_DOWN.load(Ordering::SeqCst)
+}
+
+/// Resolves on SIGTERM, as sent by Kubernetes, or SIGINT.
+async fn shutdown_signal() {
+	match tokio::signal::unix::signal(
+		tokio::signal::unix::SignalKind::terminate(),
+	) {
+		Ok(mut terminate) => {
+			tokio::select! {
+				_ = terminate.recv() => log::info!("Received SIGTERM"),
+				_ = tokio::signal::ctrl_c() => log::info!("Received SIGINT"),
+			}
+		}
+		Err(e) => {
+			log::error!("Error listening for SIGTERM: {}", e);
+			let _ = tokio::signal::ctrl_c().await;
+			log::info!("Received SIGINT");
+		}
+	}
+	SHUTTING_DOWN.store(true, Ordering::SeqCst);
+}
+
 pub struct Incoming<'a>(pub async_std::net::Incoming<'a>);
 
 impl hyper::server::accept::Accept for Incoming<'_> {
@@ -95,11 +124,17 @@ impl std::error::Error for Error {
 	}
 }
 
-/// Initializes the metrics context, and starts an HTTP server
-/// to serve metrics.
+/// Starts an HTTP server to receive webhooks. On SIGTERM or SIGINT it stops accepting
+/// connections, waits up to `shutdown_timeout` for requests in flight and flushes the database.
+///
+/// Requests still in flight after the timeout are not waited for: their tasks are dropped with
+/// the runtime wherever they are, possibly halfway through a `git push`. The delivery log entry
+/// with the outcome `interrupted`, written before a delivery is handled, is the only checkpoint
+/// they leave; see `webhook_inner`.
 pub async fn init_server(
 	addr: SocketAddr,
 	state: Arc<Mutex<AppState>>,
+	shutdown_timeout: Duration,
 ) -> anyhow::Result<()> {
 	let listener = async_std::net::TcpListener::bind(&addr)
 		.await
@@ -107,20 +142,60 @@ pub async fn init_server(
 
 	log::info!("Listening on {}", addr);
 
+	let service_state = Arc::clone(&state);
+	let probes = state.lock().await.probes();
 	let service = make_service_fn(move |_| {
-		let state = Arc::clone(&state);
+		let state = Arc::clone(&service_state);
+		let probes = probes.clone();
 		async move {
 			Ok::<_, hyper::Error>(service_fn(move |req: Request<Body>| {
 				let state = Arc::clone(&state);
-				webhook(req, state)
+				webhook(req, state, probes.clone())
 			}))
 		}
 	});
 
-	Server::builder(Incoming(listener.incoming()))
+	let signal = shutdown_signal().shared();
+	let server = Server::builder(Incoming(listener.incoming()))
 		.http1_half_close(true)
 		.serve(service)
-		.boxed()
-		.await
-		.context(format!("Server error"))
+		.with_graceful_shutdown(signal.clone())
+		.boxed();
+	let deadline = async move {
+		signal.await;
+		log::info!(
+			"Shutting down; waiting up to {}s for requests in flight",
+			shutdown_timeout.as_secs()
+		);
+		tokio::time::delay_for(shutdown_timeout).await;
+	};
+	tokio::select! {
+		result = server => result.context(format!("Server error"))?,
+		_ = deadline => log::warn!(
+			"Requests still in flight after {}s; exiting regardless",
+			shutdown_timeout.as_secs()
+		),
+	}
+
+	match state.try_lock() {
+		Ok(state) => {
+			let deferred = state.deferred.lock().len();
+			if deferred > 0 {
+				log::info!(
+					"Dropping {} deferred pull requests; their next webhook checks them again",
+					deferred
+				);
+			}
+			state
+				.db
+				.flush()
+				.context(format!("Error flushing database"))?;
+			log::info!("Database flushed");
+		}
+		// the write-ahead log still has every write
+		Err(_) => log::warn!(
+			"A webhook is still being handled; the database is not flushed"
+		),
+	}
+	Ok(())
 }
@@ -0,0 +1,372 @@
+//! Trees in an append-only log file, read into memory when opened.
+//!
+//! Every change is appended as its big-endian `u32` length followed by its bincode
+//! serialization. Once the log is more than twice the size of the entries it holds, it is
+//! rewritten with only those. A change cut short at the end of the log, as left by a crash while
+//! appending, is discarded when the log is opened; anything else that cannot be read stops the
+//! log from opening, so that no records are dropped silently.
+//!
+//! The bot keeps a few thousand small records, which fit in memory. An embedded database such as
+//! sled would be another large dependency for that, and sled's on-disk format still changes
+//! between releases, so upgrading it would mean exporting and importing the database.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use super::{
+	db_error,
+	memory::{keys_in_tree, latest_in_tree, scan_tree, Tree},
+	Range, Store,
+};
+use crate::{error::*, Result};
+
+const LOG_FILE: &str = "processbot.log";
+/// Logs smaller than this are not compacted.
+const MIN_COMPACTION_LEN: u64 = 1 << 20;
+/// Length prefix, variant and the lengths of tree, key and value of an encoded `Change::Put`.
+const PUT_OVERHEAD: u64 = 4 + 4 + 8 + 8 + 8;
+
+#[derive(Serialize, Deserialize)]
+enum Change {
+	Put {
+		tree: String,
+		key: Vec<u8>,
+		value: Vec<u8>,
+	},
+	Delete {
+		tree: String,
+		key: Vec<u8>,
+	},
+	DropTree {
+		tree: String,
+	},
+}
+
+fn encode_change(change: &Change) -> Result<Vec<u8>> {
+	let body = bincode::serialize(change).context(Bincode)?;
+	let mut bytes = (body.len() as u32).to_be_bytes().to_vec();
+	bytes.extend(body);
+	Ok(bytes)
+}
+
+/// Reads the change at the start of `bytes` and its length, or nothing if it was cut short, ie.
+/// `bytes` end before it does.
+fn decode_change(bytes: &[u8]) -> Result<Option<(Change, usize)>> {
+	if bytes.len() < 4 {
+		return Ok(None);
+	}
+	let len =
+		u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+	let body = match bytes.get(4..4 + len) {
+		Some(body) => body,
+		None => return Ok(None),
+	};
+	let change = bincode::deserialize(body).context(Bincode)?;
+	Ok(Some((change, 4 + len)))
+}
+
+/// Length of the entry in a compacted log.
+fn entry_len(tree: &str, key: &[u8], value: &[u8]) -> u64 {
+	PUT_OVERHEAD + (tree.len() + key.len() + value.len()) as u64
+}
+
+struct Log {
+	path: PathBuf,
+	file: File,
+	trees: BTreeMap<String, Tree>,
+	len: u64,
+	/// Length of the log once compacted.
+	compacted_len: u64,
+}
+
+impl Log {
+	fn open(path: PathBuf) -> Result<Self> {
+		let mut file = OpenOptions::new()
+			.read(true)
+			.append(true)
+			.create(true)
+			.open(&path)
+			.map_err(db_error)?;
+		let mut bytes = Vec::new();
+		file.read_to_end(&mut bytes).map_err(db_error)?;
+		let mut log = Self {
+			path,
+			file,
+			trees: BTreeMap::new(),
+			len: 0,
+			compacted_len: 0,
+		};
+		loop {
+			let decoded =
+				decode_change(&bytes[log.len as usize..]).map_err(|e| {
+					Error::Message {
+						msg: format!(
+						"{} cannot be read at byte {}, leaving it untouched: {}",
+						log.path.display(),
+						log.len,
+						e
+					),
+					}
+				})?;
+			match decoded {
+				Some((change, len)) => {
+					log.apply(change);
+					log.len += len as u64;
+				}
+				None => break,
+			}
+		}
+		if log.len < bytes.len() as u64 {
+			// the bot stopped while appending
+			log::warn!(
+				"Discarding {} bytes at the end of {}",
+				bytes.len() as u64 - log.len,
+				log.path.display()
+			);
+			log.file.set_len(log.len).map_err(db_error)?;
+		}
+		log.compact_if_large()?;
+		Ok(log)
+	}
+
+	fn apply(&mut self, change: Change) {
+		match change {
+			Change::Put { tree, key, value } => {
+				self.compacted_len += entry_len(&tree, &key, &value);
+				let entries = self.trees.entry(tree.clone()).or_default();
+				if let Some(old) = entries.insert(key.clone(), value) {
+					self.compacted_len -= entry_len(&tree, &key, &old);
+				}
+			}
+			Change::Delete { tree, key } => {
+				if let Some(old) = self
+					.trees
+					.get_mut(&tree)
+					.and_then(|entries| entries.remove(&key))
+				{
+					self.compacted_len -= entry_len(&tree, &key, &old);
+				}
+			}
+			Change::DropTree { tree } => {
+				for (key, value) in self.trees.remove(&tree).unwrap_or_default()
+				{
+					self.compacted_len -= entry_len(&tree, &key, &value);
+				}
+			}
+		}
+	}
+
+	/// Appends the change to the log before applying it.
+	fn write(&mut self, change: Change) -> Result<()> {
+		let bytes = encode_change(&change)?;
+		self.file.write_all(&bytes).map_err(db_error)?;
+		self.len += bytes.len() as u64;
+		self.apply(change);
+		self.compact_if_large()
+	}
+
+	fn compact_if_large(&mut self) -> Result<()> {
+		if self.len > MIN_COMPACTION_LEN && self.len > 2 * self.compacted_len {
+			self.compact()?;
+		}
+		Ok(())
+	}
+
+	/// Replaces the log with one holding only the current entries. Until the rename the old log
+	/// is intact, and it holds the same entries.
+	fn compact(&mut self) -> Result<()> {
+		let compacted_path = self.path.with_extension("log.new");
+		let mut compacted =
+			BufWriter::new(File::create(&compacted_path).map_err(db_error)?);
+		let mut len = 0;
+		for (tree, entries) in self.trees.iter() {
+			for (key, value) in entries.iter() {
+				let bytes = encode_change(&Change::Put {
+					tree: tree.clone(),
+					key: key.clone(),
+					value: value.clone(),
+				})?;
+				compacted.write_all(&bytes).map_err(db_error)?;
+				len += bytes.len() as u64;
+			}
+		}
+		compacted.flush().map_err(db_error)?;
+		compacted.get_ref().sync_all().map_err(db_error)?;
+		fs::rename(&compacted_path, &self.path).map_err(db_error)?;
+		self.file = OpenOptions::new()
+			.append(true)
+			.open(&self.path)
+			.map_err(db_error)?;
+		log::info!(
+			"Compacted {} from {} to {} bytes",
+			self.path.display(),
+			self.len,
+			len
+		);
+		self.len = len;
+		Ok(())
+	}
+}
+
+pub struct FileStore {
+	log: Mutex<Log>,
+}
+
+impl FileStore {
+	/// Opens the log in the directory `path`, creating both if missing.
+	pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+		fs::create_dir_all(&path).map_err(db_error)?;
+		Ok(Self {
+			log: Mutex::new(Log::open(path.as_ref().join(LOG_FILE))?),
+		})
+	}
+}
+
+impl Store for FileStore {
+	fn get(&self, tree: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+		Ok(self
+			.log
+			.lock()
+			.trees
+			.get(tree)
+			.and_then(|tree| tree.get(key))
+			.cloned())
+	}
+
+	fn put(&self, tree: &str, key: &[u8], value: &[u8]) -> Result<()> {
+		self.log.lock().write(Change::Put {
+			tree: tree.to_owned(),
+			key: key.to_vec(),
+			value: value.to_vec(),
+		})
+	}
+
+	fn delete(&self, tree: &str, key: &[u8]) -> Result<()> {
+		let mut log = self.log.lock();
+		let exists = log
+			.trees
+			.get(tree)
+			.map_or(false, |entries| entries.contains_key(key));
+		if !exists {
+			return Ok(());
+		}
+		log.write(Change::Delete {
+			tree: tree.to_owned(),
+			key: key.to_vec(),
+		})
+	}
+
+	fn scan(
+		&self,
+		tree: &str,
+		range: Range,
+	) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+		Ok(scan_tree(self.log.lock().trees.get(tree), range))
+	}
+
+	fn scan_keys(&self, tree: &str, range: Range) -> Result<Vec<Vec<u8>>> {
+		Ok(keys_in_tree(self.log.lock().trees.get(tree), range))
+	}
+
+	fn latest(
+		&self,
+		tree: &str,
+		limit: usize,
+	) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+		Ok(latest_in_tree(self.log.lock().trees.get(tree), limit))
+	}
+
+	fn has_tree(&self, tree: &str) -> bool {
+		self.log.lock().trees.contains_key(tree)
+	}
+
+	fn drop_tree(&mut self, tree: &str) -> Result<()> {
+		let log = self.log.get_mut();
+		if log.trees.contains_key(tree) {
+			log.write(Change::DropTree {
+				tree: tree.to_owned(),
+			})?;
+		}
+		Ok(())
+	}
+
+	fn flush(&self) -> Result<()> {
+		self.log.lock().file.sync_data().map_err(db_error)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::storage::tests::{check_store, temporary_path};
+
+	#[test]
+	fn test_file_store() {
+		let path = temporary_path("file-store");
+		check_store(&mut FileStore::open(&path).expect("open"));
+
+		// the log is read again when reopened
+		let store = FileStore::open(&path).expect("reopen");
+		assert_eq!(
+			store.get("notes", b"a").expect("get"),
+			Some(b"first again".to_vec())
+		);
+		assert_eq!(store.get("notes", b"b").expect("get"), None);
+		assert!(!store.has_tree("other"));
+
+		// a change cut short is discarded
+		drop(store);
+		let log_path = path.join(LOG_FILE);
+		let len = fs::metadata(&log_path).expect("metadata").len();
+		let mut file = OpenOptions::new()
+			.append(true)
+			.open(&log_path)
+			.expect("open log");
+		file.write_all(&[0, 0, 1, 0, 42]).expect("write");
+		drop(file);
+		let store = FileStore::open(&path).expect("reopen");
+		assert_eq!(fs::metadata(&log_path).expect("metadata").len(), len);
+		store.put("notes", b"d", b"fourth").expect("put");
+		drop(store);
+		let store = FileStore::open(&path).expect("reopen");
+		assert_eq!(
+			store.get("notes", b"d").expect("get"),
+			Some(b"fourth".to_vec())
+		);
+		drop(store);
+
+		// a complete change that cannot be read is not discarded
+		let len = fs::metadata(&log_path).expect("metadata").len();
+		let mut file = OpenOptions::new()
+			.append(true)
+			.open(&log_path)
+			.expect("open log");
+		file.write_all(&[0, 0, 0, 4, 255, 255, 255, 255])
+			.expect("write");
+		drop(file);
+		assert!(FileStore::open(&path).is_err());
+		assert_eq!(fs::metadata(&log_path).expect("metadata").len(), len + 8);
+		fs::remove_dir_all(&path).expect("remove");
+	}
+
+	#[test]
+	fn test_compaction() {
+		let path = temporary_path("file-store-compaction");
+		let store = FileStore::open(&path).expect("open");
+		let value = vec![0; 1024];
+		for _ in 0..2048 {
+			store.put("notes", b"a", &value).expect("put");
+		}
+		let log_len =
+			fs::metadata(path.join(LOG_FILE)).expect("metadata").len();
+		assert!(log_len < MIN_COMPACTION_LEN + 2048);
+		assert_eq!(store.get("notes", b"a").expect("get"), Some(value));
+		drop(store);
+		fs::remove_dir_all(&path).expect("remove");
+	}
+}
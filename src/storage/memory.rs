@@ -0,0 +1,129 @@
+//! Trees kept in memory, for tests or a bot that can lose its records.
+
+use parking_lot::RwLock;
+use std::collections::BTreeMap;
+
+use super::{Range, Store};
+use crate::Result;
+
+pub(super) type Tree = BTreeMap<Vec<u8>, Vec<u8>>;
+
+pub(super) fn scan_tree(
+	tree: Option<&Tree>,
+	range: Range,
+) -> Vec<(Vec<u8>, Vec<u8>)> {
+	tree.map(|tree| {
+		tree.range(range.start().to_vec()..)
+			.take_while(|(key, _)| range.contains(key))
+			.map(|(key, value)| (key.clone(), value.clone()))
+			.collect()
+	})
+	.unwrap_or_default()
+}
+
+pub(super) fn keys_in_tree(tree: Option<&Tree>, range: Range) -> Vec<Vec<u8>> {
+	tree.map(|tree| {
+		tree.range(range.start().to_vec()..)
+			.map(|(key, _)| key)
+			.take_while(|key| range.contains(key))
+			.cloned()
+			.collect()
+	})
+	.unwrap_or_default()
+}
+
+pub(super) fn latest_in_tree(
+	tree: Option<&Tree>,
+	limit: usize,
+) -> Vec<(Vec<u8>, Vec<u8>)> {
+	tree.map(|tree| {
+		tree.iter()
+			.rev()
+			.take(limit)
+			.map(|(key, value)| (key.clone(), value.clone()))
+			.collect()
+	})
+	.unwrap_or_default()
+}
+
+#[derive(Default)]
+pub struct MemoryStore {
+	trees: RwLock<BTreeMap<String, Tree>>,
+}
+
+impl MemoryStore {
+	pub fn new() -> Self {
+		Self::default()
+	}
+}
+
+impl Store for MemoryStore {
+	fn get(&self, tree: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+		Ok(self
+			.trees
+			.read()
+			.get(tree)
+			.and_then(|tree| tree.get(key))
+			.cloned())
+	}
+
+	fn put(&self, tree: &str, key: &[u8], value: &[u8]) -> Result<()> {
+		self.trees
+			.write()
+			.entry(tree.to_owned())
+			.or_default()
+			.insert(key.to_vec(), value.to_vec());
+		Ok(())
+	}
+
+	fn delete(&self, tree: &str, key: &[u8]) -> Result<()> {
+		if let Some(tree) = self.trees.write().get_mut(tree) {
+			tree.remove(key);
+		}
+		Ok(())
+	}
+
+	fn scan(
+		&self,
+		tree: &str,
+		range: Range,
+	) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+		Ok(scan_tree(self.trees.read().get(tree), range))
+	}
+
+	fn scan_keys(&self, tree: &str, range: Range) -> Result<Vec<Vec<u8>>> {
+		Ok(keys_in_tree(self.trees.read().get(tree), range))
+	}
+
+	fn latest(
+		&self,
+		tree: &str,
+		limit: usize,
+	) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+		Ok(latest_in_tree(self.trees.read().get(tree), limit))
+	}
+
+	fn has_tree(&self, tree: &str) -> bool {
+		self.trees.read().contains_key(tree)
+	}
+
+	fn drop_tree(&mut self, tree: &str) -> Result<()> {
+		self.trees.get_mut().remove(tree);
+		Ok(())
+	}
+
+	fn flush(&self) -> Result<()> {
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::storage::tests::check_store;
+
+	#[test]
+	fn test_memory_store() {
+		check_store(&mut MemoryStore::new());
+	}
+}
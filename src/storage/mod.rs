@@ -0,0 +1,740 @@
+//! Typed records in trees of a key-value store.
+//!
+//! Each record is stored as the big-endian `u16` version of its layout followed by its bincode
+//! serialization. When the fields of a record type change, its `VERSION` is increased and
+//! `Record::migrate` reads the older layouts; records are upgraded when the database is opened.
+//!
+//! The store is RocksDB, with column families as trees, an append-only log file or memory.
+
+use chrono::Utc;
+use serde::{de::DeserializeOwned, Serialize};
+use snafu::ResultExt;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{
+	delivery::{
+		Delivery, DeliveryOutcome, HandledDelivery, HandledDeliveryTime,
+	},
+	error::*,
+	features::FeatureToggle,
+	matrix_bot::MatrixRoom,
+	webhook::MergeRequest,
+	Result,
+};
+
+mod file;
+mod memory;
+#[cfg(feature = "rocksdb")]
+mod rocks;
+
+pub use file::FileStore;
+pub use memory::MemoryStore;
+#[cfg(feature = "rocksdb")]
+pub use rocks::RocksDbStore;
+
+/// Layout of the trees. Databases written before a tree was used per record type have no
+/// schema version.
+const SCHEMA_VERSION: u32 = 1;
+const META_TREE: &str = "meta";
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+/// The tree of every record but the delivery log in schema version 0, which only RocksDB
+/// databases have.
+const LEGACY_DEFAULT_TREE: &str = "default";
+/// The delivery log of schema version 0, whose records had no version.
+const LEGACY_DELIVERIES_TREE: &str = "deliveries";
+
+/// A type stored in its own tree.
+pub trait Record: Serialize + DeserializeOwned {
+	/// Tree the records are stored in.
+	const CF: &'static str;
+	/// Version of the layout of the record, increased whenever its fields change.
+	const VERSION: u16;
+
+	/// Reads a record written with an older `version` of the layout.
+	fn migrate(version: u16, _bytes: &[u8]) -> Result<Self> {
+		RecordVersion {
+			cf: Self::CF,
+			version,
+		}
+		.fail()
+	}
+}
+
+/// Trees of every record type.
+const RECORD_TREES: &[&str] = &[
+	MergeRequest::CF,
+	Delivery::CF,
+	DeliveryOutcome::CF,
+	HandledDelivery::CF,
+	HandledDeliveryTime::CF,
+	MatrixRoom::CF,
+	FeatureToggle::CF,
+];
+
+/// Keys and values in named trees, each sorted by key.
+pub trait Store: Send + Sync {
+	fn get(&self, tree: &str, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+	fn put(&self, tree: &str, key: &[u8], value: &[u8]) -> Result<()>;
+
+	fn delete(&self, tree: &str, key: &[u8]) -> Result<()>;
+
+	/// Returns the entries in `range` in the order of their keys.
+	fn scan(&self, tree: &str, range: Range)
+		-> Result<Vec<(Vec<u8>, Vec<u8>)>>;
+
+	/// Returns the keys in `range` in order, without copying their values.
+	fn scan_keys(&self, tree: &str, range: Range) -> Result<Vec<Vec<u8>>>;
+
+	/// Returns up to `limit` entries with the greatest keys, greatest first.
+	fn latest(
+		&self,
+		tree: &str,
+		limit: usize,
+	) -> Result<Vec<(Vec<u8>, Vec<u8>)>>;
+
+	fn has_tree(&self, tree: &str) -> bool;
+
+	/// Removes the tree with its entries, if it exists.
+	fn drop_tree(&mut self, tree: &str) -> Result<()>;
+
+	/// Writes every change to disk.
+	fn flush(&self) -> Result<()>;
+}
+
+/// Wraps an error of a store.
+fn db_error(e: impl std::error::Error + Send + Sync + 'static) -> Error {
+	Error::Db {
+		source: Box::new(e),
+	}
+}
+
+/// The store a database is kept in, chosen with `STORAGE_BACKEND`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+	/// A RocksDB database in the directory `DB_PATH`. Requires the `rocksdb` feature.
+	RocksDb,
+	/// A log file in the directory `DB_PATH`.
+	File,
+	/// Nothing is kept once the bot exits.
+	Memory,
+}
+
+impl Default for Backend {
+	fn default() -> Self {
+		if cfg!(feature = "rocksdb") {
+			Backend::RocksDb
+		} else {
+			Backend::File
+		}
+	}
+}
+
+impl fmt::Display for Backend {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match self {
+			Backend::RocksDb => "rocksdb",
+			Backend::File => "file",
+			Backend::Memory => "memory",
+		})
+	}
+}
+
+impl FromStr for Backend {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"rocksdb" => Ok(Backend::RocksDb),
+			"file" => Ok(Backend::File),
+			"memory" => Ok(Backend::Memory),
+			_ => Err(format!("Unknown storage backend {}", s)),
+		}
+	}
+}
+
+fn encode<R: Record>(record: &R) -> Result<Vec<u8>> {
+	let mut bytes = R::VERSION.to_be_bytes().to_vec();
+	bytes.extend(bincode::serialize(record).context(Bincode)?);
+	Ok(bytes)
+}
+
+/// Returns the record and the version it was written with.
+fn decode<R: Record>(bytes: &[u8]) -> Result<(R, u16)> {
+	if bytes.len() < 2 {
+		return RecordVersion {
+			cf: R::CF,
+			version: 0u16,
+		}
+		.fail();
+	}
+	let version = u16::from_be_bytes([bytes[0], bytes[1]]);
+	let record = if version == R::VERSION {
+		bincode::deserialize(&bytes[2..]).context(Bincode)?
+	} else if version < R::VERSION {
+		R::migrate(version, &bytes[2..])?
+	} else {
+		return RecordVersion { cf: R::CF, version }.fail();
+	};
+	Ok((record, version))
+}
+
+fn decode_entry<R: Record>(
+	(key, value): (Vec<u8>, Vec<u8>),
+) -> Result<(String, R)> {
+	Ok((
+		String::from_utf8_lossy(&key).into_owned(),
+		decode(&value)?.0,
+	))
+}
+
+/// Which keys a scan visits.
+#[derive(Debug, Clone, Copy)]
+pub enum Range<'a> {
+	All,
+	/// Keys starting with the prefix.
+	Prefix(&'a str),
+	/// Keys sorting before the bound.
+	Before(&'a str),
+}
+
+impl Range<'_> {
+	/// The least key in the range.
+	fn start(&self) -> &[u8] {
+		match self {
+			Range::Prefix(prefix) => prefix.as_bytes(),
+			Range::All | Range::Before(_) => b"",
+		}
+	}
+
+	/// Whether `key` is in the range, given that it is not less than `start`.
+	fn contains(&self, key: &[u8]) -> bool {
+		match self {
+			Range::All => true,
+			Range::Prefix(prefix) => key.starts_with(prefix.as_bytes()),
+			Range::Before(bound) => key < bound.as_bytes(),
+		}
+	}
+}
+
+pub struct Storage {
+	store: Box<dyn Store>,
+}
+
+impl Storage {
+	/// Opens the database at `path` with `backend`, creating it if missing, and migrates it to
+	/// the current layout.
+	pub fn open(backend: Backend, path: impl AsRef<Path>) -> Result<Self> {
+		let store: Box<dyn Store> = match backend {
+			#[cfg(feature = "rocksdb")]
+			Backend::RocksDb => {
+				let mut trees = vec![LEGACY_DEFAULT_TREE, META_TREE];
+				trees.extend(RECORD_TREES);
+				Box::new(RocksDbStore::open(path, &trees)?)
+			}
+			#[cfg(not(feature = "rocksdb"))]
+			Backend::RocksDb => {
+				return Message {
+					msg: "Built without the rocksdb feature; set STORAGE_BACKEND=file"
+						.to_owned(),
+				}
+				.fail();
+			}
+			Backend::File => Box::new(FileStore::open(path)?),
+			Backend::Memory => {
+				log::warn!(
+					"Keeping the database in memory; it is lost on exit"
+				);
+				Box::new(MemoryStore::new())
+			}
+		};
+		Self::new(store)
+	}
+
+	/// Migrates the records of `store` to the current layout.
+	pub fn new(store: Box<dyn Store>) -> Result<Self> {
+		let mut storage = Self { store };
+		storage.migrate()?;
+		Ok(storage)
+	}
+
+	/// Opens an empty database in memory.
+	pub fn temporary() -> Result<Self> {
+		Self::new(Box::new(MemoryStore::new()))
+	}
+
+	pub fn get<R: Record>(&self, key: &str) -> Result<Option<R>> {
+		match self.store.get(R::CF, key.as_bytes())? {
+			Some(bytes) => Ok(Some(decode(&bytes)?.0)),
+			None => Ok(None),
+		}
+	}
+
+	pub fn put<R: Record>(&self, key: &str, record: &R) -> Result<()> {
+		self.store.put(R::CF, key.as_bytes(), &encode(record)?)
+	}
+
+	pub fn delete<R: Record>(&self, key: &str) -> Result<()> {
+		self.store.delete(R::CF, key.as_bytes())
+	}
+
+	/// Returns the records in `range` in the order of their keys.
+	pub fn scan<R: Record>(&self, range: Range) -> Result<Vec<(String, R)>> {
+		self.store
+			.scan(R::CF, range)?
+			.into_iter()
+			.map(decode_entry)
+			.collect()
+	}
+
+	/// Returns the keys in `range` in order, without copying or decoding their records.
+	pub fn keys<R: Record>(&self, range: Range) -> Result<Vec<String>> {
+		Ok(self
+			.store
+			.scan_keys(R::CF, range)?
+			.into_iter()
+			.map(|key| String::from_utf8_lossy(&key).into_owned())
+			.collect())
+	}
+
+	/// Returns up to `limit` records with the greatest keys, greatest first.
+	pub fn latest<R: Record>(&self, limit: usize) -> Result<Vec<(String, R)>> {
+		self.store
+			.latest(R::CF, limit)?
+			.into_iter()
+			.map(decode_entry)
+			.collect()
+	}
+
+	/// Checks that the database can be read.
+	pub fn check(&self) -> Result<()> {
+		self.schema_version().map(|_| ())
+	}
+
+	/// Writes every change to disk.
+	pub fn flush(&self) -> Result<()> {
+		self.store.flush()
+	}
+
+	fn schema_version(&self) -> Result<u32> {
+		Ok(self
+			.store
+			.get(META_TREE, SCHEMA_VERSION_KEY.as_bytes())?
+			.and_then(|value| String::from_utf8_lossy(&value).parse().ok())
+			.unwrap_or(0))
+	}
+
+	fn migrate(&mut self) -> Result<()> {
+		let version = self.schema_version()?;
+		if version > SCHEMA_VERSION {
+			return Message {
+				msg: format!(
+					"Database has schema version {}, newer than {}",
+					version, SCHEMA_VERSION
+				),
+			}
+			.fail();
+		}
+		if version < 1 {
+			log::info!("Moving records to trees per record type");
+			self.migrate_from_default_tree()?;
+			self.store.drop_tree(LEGACY_DELIVERIES_TREE)?;
+		}
+		self.upgrade::<MergeRequest>()?;
+		self.upgrade::<Delivery>()?;
+		self.upgrade::<DeliveryOutcome>()?;
+		self.upgrade::<HandledDelivery>()?;
+		self.upgrade::<HandledDeliveryTime>()?;
+		self.upgrade::<MatrixRoom>()?;
+		self.upgrade::<FeatureToggle>()?;
+		self.store.put(
+			META_TREE,
+			SCHEMA_VERSION_KEY.as_bytes(),
+			SCHEMA_VERSION.to_string().as_bytes(),
+		)
+	}
+
+	/// Rewrites records of `R` written with an older layout.
+	fn upgrade<R: Record>(&self) -> Result<()> {
+		let mut upgraded = 0;
+		for (key, value) in self.store.scan(R::CF, Range::All)? {
+			let (record, version) = decode::<R>(&value)?;
+			if version < R::VERSION {
+				self.store.put(R::CF, &key, &encode(&record)?)?;
+				upgraded += 1;
+			}
+		}
+		if upgraded > 0 {
+			log::info!("Upgraded {} records in {}", upgraded, R::CF);
+		}
+		Ok(())
+	}
+
+	/// Schema version 0 kept every record but the delivery log in the default column family,
+	/// without versions. Records are copied before being deleted, so an interrupted migration
+	/// is repeated when the database is next opened.
+	fn migrate_from_default_tree(&self) -> Result<()> {
+		let legacy = if self.store.has_tree(LEGACY_DEFAULT_TREE) {
+			self.store.scan(LEGACY_DEFAULT_TREE, Range::All)?
+		} else {
+			vec![]
+		};
+		let value_of = |key: &str| {
+			legacy
+				.iter()
+				.find(|(k, _)| k.as_slice() == key.as_bytes())
+				.map(|(_, v)| String::from_utf8_lossy(v).into_owned())
+		};
+		for (key, value) in legacy.iter() {
+			let key_str = String::from_utf8_lossy(key).into_owned();
+			if key.len() == 40 && key.iter().all(|b| b.is_ascii_hexdigit()) {
+				// merge requests were stored under the head SHA in the first layout
+				let mut merge_request = MergeRequest::migrate(1, value)?;
+				if let Some(requested_at) =
+					value_of(&format!("merge-requested-at:{}", key_str))
+						.and_then(|timestamp| timestamp.parse::<i64>().ok())
+				{
+					merge_request.requested_at = chrono::DateTime::from_utc(
+						chrono::NaiveDateTime::from_timestamp(requested_at, 0),
+						Utc,
+					);
+				}
+				self.put(&key_str, &merge_request)?;
+			} else if let Some(delivery_id) = key_str.strip_prefix("delivery:")
+			{
+				let handled_at = String::from_utf8_lossy(value)
+					.parse::<i64>()
+					.unwrap_or_else(|_| Utc::now().timestamp());
+				self.put(delivery_id, &HandledDelivery { handled_at })?;
+			} else if let Some(rest) = key_str.strip_prefix("delivery-time:") {
+				if let Some(delivery_id) = rest.splitn(2, ':').nth(1) {
+					self.put(
+						rest,
+						&HandledDeliveryTime {
+							delivery_id: delivery_id.to_owned(),
+						},
+					)?;
+				}
+			} else if key_str.starts_with("merge-requested-at:") {
+				// read with its merge request
+			} else if key_str.starts_with('@') {
+				self.put(
+					&key_str,
+					&MatrixRoom {
+						room_id: String::from_utf8_lossy(value).into_owned(),
+					},
+				)?;
+			} else {
+				log::warn!("Leaving unknown record {} in place", key_str);
+			}
+		}
+
+		if self.store.has_tree(LEGACY_DELIVERIES_TREE) {
+			// logged deliveries had the first layout of `Delivery`
+			for (key, value) in
+				self.store.scan(LEGACY_DELIVERIES_TREE, Range::All)?
+			{
+				let mut bytes = 1u16.to_be_bytes().to_vec();
+				bytes.extend_from_slice(&value);
+				self.store.put(Delivery::CF, &key, &bytes)?;
+			}
+		}
+
+		for (key, _) in legacy.iter() {
+			let key_str = String::from_utf8_lossy(key);
+			let migrated = (key.len() == 40
+				&& key.iter().all(|b| b.is_ascii_hexdigit()))
+				|| key_str.starts_with("delivery:")
+				|| key_str.starts_with("delivery-time:")
+				|| key_str.starts_with("merge-requested-at:")
+				|| key_str.starts_with('@');
+			if migrated {
+				self.store.delete(LEGACY_DEFAULT_TREE, key)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde::Deserialize;
+	use std::path::PathBuf;
+
+	/// Returns a path in the temporary directory that is not used yet.
+	pub(super) fn temporary_path(name: &str) -> PathBuf {
+		use std::sync::atomic::{AtomicUsize, Ordering};
+		static COUNT: AtomicUsize = AtomicUsize::new(0);
+		let path = std::env::temp_dir().join(format!(
+			"processbot-{}-{}-{}",
+			name,
+			std::process::id(),
+			COUNT.fetch_add(1, Ordering::SeqCst)
+		));
+		let _ = std::fs::remove_dir_all(&path);
+		path
+	}
+
+	/// Checks the behaviour every backend shares.
+	pub(super) fn check_store(store: &mut dyn Store) {
+		store.put("notes", b"b", b"second").expect("put");
+		store.put("notes", b"a", b"first").expect("put");
+		store.put("notes", b"c", b"third").expect("put");
+		store.put("notes", b"a", b"first again").expect("put");
+		store.put("other", b"a", b"other").expect("put");
+		assert_eq!(
+			store.get("notes", b"a").expect("get"),
+			Some(b"first again".to_vec())
+		);
+		assert_eq!(store.get("notes", b"d").expect("get"), None);
+		assert!(store.has_tree("notes"));
+
+		let keys = |entries: Vec<(Vec<u8>, Vec<u8>)>| {
+			entries
+				.into_iter()
+				.map(|(key, _)| String::from_utf8(key).expect("utf8"))
+				.collect::<Vec<String>>()
+		};
+		assert_eq!(
+			keys(store.scan("notes", Range::All).expect("scan")),
+			vec!["a", "b", "c"]
+		);
+		assert_eq!(
+			keys(store.scan("notes", Range::Prefix("b")).expect("scan")),
+			vec!["b"]
+		);
+		assert_eq!(
+			keys(store.scan("notes", Range::Before("c")).expect("scan")),
+			vec!["a", "b"]
+		);
+		assert_eq!(
+			keys(store.latest("notes", 2).expect("latest")),
+			vec!["c", "b"]
+		);
+		assert_eq!(
+			store
+				.scan_keys("notes", Range::Before("c"))
+				.expect("scan_keys"),
+			vec![b"a".to_vec(), b"b".to_vec()]
+		);
+		assert_eq!(
+			store
+				.scan_keys("notes", Range::Prefix("d"))
+				.expect("scan_keys"),
+			Vec::<Vec<u8>>::new()
+		);
+
+		store.delete("notes", b"b").expect("delete");
+		assert_eq!(store.get("notes", b"b").expect("get"), None);
+		store.delete("notes", b"missing").expect("delete");
+
+		store.drop_tree("other").expect("drop");
+		assert!(!store.has_tree("other"));
+		store.drop_tree("missing").expect("drop");
+		store.flush().expect("flush");
+	}
+
+	#[derive(Debug, PartialEq, Serialize, Deserialize)]
+	struct Note {
+		text: String,
+		pinned: bool,
+	}
+
+	impl Record for Note {
+		const CF: &'static str = "merge_requests";
+		const VERSION: u16 = 2;
+
+		fn migrate(version: u16, bytes: &[u8]) -> Result<Self> {
+			match version {
+				1 => Ok(Note {
+					text: bincode::deserialize(bytes).context(Bincode)?,
+					pinned: false,
+				}),
+				_ => RecordVersion {
+					cf: Self::CF,
+					version,
+				}
+				.fail(),
+			}
+		}
+	}
+
+	fn note(text: &str) -> Note {
+		Note {
+			text: text.to_owned(),
+			pinned: true,
+		}
+	}
+
+	#[test]
+	fn test_records() {
+		let storage = Storage::temporary().expect("storage");
+		storage.put("b", &note("second")).expect("put");
+		storage.put("a", &note("first")).expect("put");
+		storage.put("c", &note("third")).expect("put");
+		assert_eq!(storage.get::<Note>("a").expect("get"), Some(note("first")));
+		assert_eq!(storage.get::<Note>("d").expect("get"), None);
+		// record types do not share keys
+		assert_eq!(storage.get::<Delivery>("a").expect("get"), None);
+
+		assert_eq!(
+			storage.keys::<Note>(Range::All).expect("keys"),
+			vec!["a", "b", "c"]
+		);
+		assert_eq!(
+			storage.keys::<Note>(Range::Before("c")).expect("keys"),
+			vec!["a", "b"]
+		);
+		assert_eq!(
+			storage.scan::<Note>(Range::Prefix("b")).expect("scan"),
+			vec![("b".to_owned(), note("second"))]
+		);
+		assert_eq!(
+			storage
+				.latest::<Note>(2)
+				.expect("latest")
+				.into_iter()
+				.map(|(key, _)| key)
+				.collect::<Vec<String>>(),
+			vec!["c", "b"]
+		);
+
+		storage.delete::<Note>("b").expect("delete");
+		assert_eq!(storage.get::<Note>("b").expect("get"), None);
+	}
+
+	#[test]
+	fn test_versions() {
+		let storage = Storage::temporary().expect("storage");
+		let mut old = 1u16.to_be_bytes().to_vec();
+		old.extend(bincode::serialize("old").expect("serialize"));
+		storage.store.put(Note::CF, b"old", &old).expect("put");
+		assert_eq!(
+			storage.get::<Note>("old").expect("get"),
+			Some(Note {
+				text: "old".to_owned(),
+				pinned: false,
+			})
+		);
+
+		storage.upgrade::<Note>().expect("upgrade");
+		let upgraded = storage
+			.store
+			.get(Note::CF, b"old")
+			.expect("get")
+			.expect("old");
+		assert_eq!(&upgraded[..2], &2u16.to_be_bytes());
+
+		let mut newer = 3u16.to_be_bytes().to_vec();
+		newer.extend(bincode::serialize(&note("newer")).expect("serialize"));
+		storage.store.put(Note::CF, b"newer", &newer).expect("put");
+		assert!(storage.get::<Note>("newer").is_err());
+	}
+
+	#[test]
+	fn test_backend() {
+		assert_eq!("file".parse::<Backend>(), Ok(Backend::File));
+		assert_eq!("rocksdb".parse::<Backend>(), Ok(Backend::RocksDb));
+		assert!("sqlite".parse::<Backend>().is_err());
+
+		let path = temporary_path("backend");
+		let storage = Storage::open(Backend::File, &path).expect("open");
+		storage.put("a", &note("first")).expect("put");
+		drop(storage);
+		let storage = Storage::open(Backend::File, &path).expect("reopen");
+		assert_eq!(storage.get::<Note>("a").expect("get"), Some(note("first")));
+		std::fs::remove_dir_all(&path).expect("remove");
+	}
+
+	#[test]
+	fn test_migrate_from_default_tree() {
+		let sha = "5c9a1f2e26e7fd5b8a4d7c3f1b0d4e6a8c2b7f10";
+		let store = MemoryStore::new();
+		let put = |tree: &str, key: &str, value: &[u8]| {
+			store.put(tree, key.as_bytes(), value).expect("put")
+		};
+		// the first layout of `MergeRequest`
+		let legacy_merge_request = bincode::serialize(&(
+			"paritytech",
+			"substrate",
+			1234i64,
+			"https://github.com/paritytech/substrate/pull/1234",
+			"sjeohp",
+		))
+		.expect("serialize");
+		put(LEGACY_DEFAULT_TREE, sha, &legacy_merge_request);
+		put(
+			LEGACY_DEFAULT_TREE,
+			&format!("merge-requested-at:{}", sha),
+			b"1600000000",
+		);
+		put(LEGACY_DEFAULT_TREE, "delivery:72d3162e", b"1600000100");
+		put(
+			LEGACY_DEFAULT_TREE,
+			"delivery-time:00000000001600000100:72d3162e",
+			b"",
+		);
+		put(
+			LEGACY_DEFAULT_TREE,
+			"@sjeohp:matrix.parity.io",
+			b"!room:matrix.parity.io",
+		);
+		let mut headers = hyper::HeaderMap::new();
+		headers
+			.insert("x-github-delivery", "72d3162e".parse().expect("header"));
+		headers.insert("x-github-event", "ping".parse().expect("header"));
+		let delivery = Delivery::new(&headers, b"{}");
+		put(
+			LEGACY_DELIVERIES_TREE,
+			"00000000000000000001:72d3162e",
+			&bincode::serialize(&delivery).expect("serialize"),
+		);
+
+		let storage = Storage::new(Box::new(store)).expect("migrate");
+		let merge_request = storage
+			.get::<MergeRequest>(sha)
+			.expect("get")
+			.expect("merge request");
+		assert_eq!(merge_request.number, 1234);
+		assert_eq!(merge_request.requested_by, "sjeohp");
+		assert_eq!(merge_request.requested_at.timestamp(), 1600000000);
+		assert_eq!(
+			storage
+				.get::<HandledDelivery>("72d3162e")
+				.expect("get")
+				.map(|handled| handled.handled_at),
+			Some(1600000100)
+		);
+		assert_eq!(
+			storage
+				.keys::<HandledDeliveryTime>(Range::All)
+				.expect("keys"),
+			vec!["00000000001600000100:72d3162e"]
+		);
+		assert_eq!(
+			storage
+				.get::<MatrixRoom>("@sjeohp:matrix.parity.io")
+				.expect("get")
+				.map(|room| room.room_id),
+			Some("!room:matrix.parity.io".to_owned())
+		);
+		assert_eq!(
+			storage
+				.latest::<Delivery>(10)
+				.expect("latest")
+				.into_iter()
+				.map(|(_, delivery)| delivery.id)
+				.collect::<Vec<String>>(),
+			vec!["72d3162e"]
+		);
+		assert!(storage
+			.store
+			.scan(LEGACY_DEFAULT_TREE, Range::All)
+			.expect("scan")
+			.is_empty());
+		assert!(!storage.store.has_tree(LEGACY_DELIVERIES_TREE));
+	}
+}
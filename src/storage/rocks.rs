@@ -0,0 +1,140 @@
+//! Trees as column families of a RocksDB database.
+
+use rocksdb::{ColumnFamily, Direction, IteratorMode, Options, DB};
+use snafu::OptionExt;
+use std::path::Path;
+
+use super::{db_error, Range, Store};
+use crate::{error::*, Result};
+
+pub struct RocksDbStore {
+	db: DB,
+	/// Column families that are open, to be flushed.
+	trees: Vec<String>,
+}
+
+impl RocksDbStore {
+	/// Opens the database at `path`, creating it and the column families `trees` if missing.
+	pub fn open(path: impl AsRef<Path>, trees: &[&str]) -> Result<Self> {
+		let mut options = Options::default();
+		options.create_if_missing(true);
+		options.create_missing_column_families(true);
+		// every existing column family has to be opened
+		let mut cfs = DB::list_cf(&options, &path).unwrap_or_default();
+		for tree in trees {
+			if !cfs.iter().any(|existing| existing == *tree) {
+				cfs.push(tree.to_string());
+			}
+		}
+		Ok(Self {
+			db: DB::open_cf(&options, &path, &cfs).map_err(db_error)?,
+			trees: cfs,
+		})
+	}
+
+	fn cf(&self, tree: &str) -> Result<&ColumnFamily> {
+		self.db.cf_handle(tree).context(Message {
+			msg: format!("Missing column family {}", tree),
+		})
+	}
+}
+
+fn entry((key, value): (Box<[u8]>, Box<[u8]>)) -> (Vec<u8>, Vec<u8>) {
+	(key.into_vec(), value.into_vec())
+}
+
+impl Store for RocksDbStore {
+	fn get(&self, tree: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+		self.db.get_cf(self.cf(tree)?, key).map_err(db_error)
+	}
+
+	fn put(&self, tree: &str, key: &[u8], value: &[u8]) -> Result<()> {
+		self.db.put_cf(self.cf(tree)?, key, value).map_err(db_error)
+	}
+
+	fn delete(&self, tree: &str, key: &[u8]) -> Result<()> {
+		self.db.delete_cf(self.cf(tree)?, key).map_err(db_error)
+	}
+
+	fn scan(
+		&self,
+		tree: &str,
+		range: Range,
+	) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+		Ok(self
+			.db
+			.iterator_cf(
+				self.cf(tree)?,
+				IteratorMode::From(range.start(), Direction::Forward),
+			)
+			.take_while(|(key, _)| range.contains(key))
+			.map(entry)
+			.collect())
+	}
+
+	fn scan_keys(&self, tree: &str, range: Range) -> Result<Vec<Vec<u8>>> {
+		// unlike `iterator_cf`, a raw iterator does not copy each value
+		let mut iter = self.db.raw_iterator_cf(self.cf(tree)?);
+		iter.seek(range.start());
+		let mut keys = vec![];
+		while let Some(key) = iter.key().filter(|key| range.contains(key)) {
+			keys.push(key.to_vec());
+			iter.next();
+		}
+		iter.status().map_err(db_error)?;
+		Ok(keys)
+	}
+
+	fn latest(
+		&self,
+		tree: &str,
+		limit: usize,
+	) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+		Ok(self
+			.db
+			.iterator_cf(self.cf(tree)?, IteratorMode::End)
+			.take(limit)
+			.map(entry)
+			.collect())
+	}
+
+	fn has_tree(&self, tree: &str) -> bool {
+		self.db.cf_handle(tree).is_some()
+	}
+
+	fn drop_tree(&mut self, tree: &str) -> Result<()> {
+		if self.has_tree(tree) {
+			self.db.drop_cf(tree).map_err(db_error)?;
+			self.trees.retain(|open| open != tree);
+		}
+		Ok(())
+	}
+
+	fn flush(&self) -> Result<()> {
+		self.db.flush().map_err(db_error)?;
+		for tree in self.trees.iter() {
+			self.db.flush_cf(self.cf(tree)?).map_err(db_error)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::storage::tests::{check_store, temporary_path};
+
+	#[test]
+	fn test_rocksdb_store() {
+		let path = temporary_path("rocksdb-store");
+		let trees = ["default", "notes", "other"];
+		check_store(&mut RocksDbStore::open(&path, &trees).expect("open"));
+		let store = RocksDbStore::open(&path, &trees).expect("reopen");
+		assert_eq!(
+			store.get("notes", b"a").expect("get"),
+			Some(b"first again".to_vec())
+		);
+		drop(store);
+		DB::destroy(&Options::default(), &path).expect("destroy");
+	}
+}
@@ -1,35 +1,97 @@
+use chrono::{DateTime, Utc};
 use futures::StreamExt;
 use futures_util::future::TryFutureExt;
 use hyper::{http::StatusCode, Body, Request, Response};
 use itertools::Itertools;
 use ring::hmac;
-use rocksdb::DB;
 use serde::{Deserialize, Serialize};
 use snafu::{OptionExt, ResultExt};
+use std::collections::VecDeque;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+use crate::admin::{admin, admin_denied, json_response, not_found};
+use crate::server::shutting_down;
 use crate::{
-	auth::GithubUserAuthenticator, companion::*, config::BotConfig,
-	constants::*, error::*, github::*, github_bot::GithubBot, gitlab_bot::*,
-	matrix_bot::MatrixBot, performance, process, rebase::*, Result,
+	auth::GithubUserAuthenticator,
+	backport::*,
+	companion::*,
+	config::BotConfig,
+	constants::*,
+	delivery,
+	error::*,
+	features::{self, Feature},
+	github::*,
+	github_bot::{self, GithubBot},
+	gitlab_bot::*,
+	matrix_bot::MatrixBot,
+	metrics, performance, process,
+	rebase::*,
+	repo_config::{self, RepoConfig, RepoSettings},
+	storage::{Record, Storage},
+	Result,
 };
 
+/// Pull requests deferred until the rate limit quota of their installation recovers, with the
+/// installation their webhook was delivered for.
+pub type Deferred =
+	parking_lot::Mutex<VecDeque<(Option<i64>, PullRequest, Repository)>>;
+
 /// This data gets passed along with each webhook to the webhook handler.
 pub struct AppState {
-	pub db: DB,
+	pub db: Arc<Storage>,
 	pub github_bot: GithubBot,
 	pub matrix_bot: MatrixBot,
-	pub gitlab_bot: GitlabBot,
+	/// `None` unless the Gitlab settings are set, in which case `bot burnin` is refused.
+	pub gitlab_bot: Option<GitlabBot>,
 
 	pub bot_config: BotConfig,
-	pub webhook_secret: String,
+	/// Secrets a webhook may be signed with; several are active while rotating.
+	pub webhook_secrets: Vec<String>,
+	/// Token of the admin endpoints, which are disabled without one.
+	pub admin_token: Option<String>,
+
+	/// Pull requests whose labels and Process file are checked once the rate limit quota of
+	/// their installation recovers.
+	pub deferred: Arc<Deferred>,
+}
+
+impl AppState {
+	/// Returns what the `ready` and `status` endpoints read.
+	pub fn probes(&self) -> Probes {
+		Probes {
+			db: Arc::clone(&self.db),
+			github_bot: self.github_bot.clone(),
+			matrix_bot: self.matrix_bot.clone(),
+			deferred: Arc::clone(&self.deferred),
+		}
+	}
+}
+
+/// The parts of `AppState` read by the `ready` and `status` endpoints, shared rather than read
+/// through the lock so that they answer while a webhook is being handled.
+#[derive(Clone)]
+pub struct Probes {
+	db: Arc<Storage>,
+	github_bot: GithubBot,
+	matrix_bot: MatrixBot,
+	deferred: Arc<Deferred>,
 }
 
 /// This stores information about a pull request while we wait for checks to complete.
-#[derive(Debug, Serialize, Deserialize)]
-#[repr(C)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MergeRequest {
+	pub owner: String,
+	pub repo_name: String,
+	pub number: i64,
+	pub html_url: String,
+	pub requested_by: String,
+	pub requested_at: DateTime<Utc>,
+}
+
+/// The first layout of `MergeRequest`.
+#[derive(Deserialize)]
+struct MergeRequestV1 {
 	owner: String,
 	repo_name: String,
 	number: i64,
@@ -37,61 +99,222 @@ pub struct MergeRequest {
 	requested_by: String,
 }
 
-/// Check the SHA1 signature on a webhook payload.
+/// Merge requests are stored under the head SHA of their pull request.
+impl Record for MergeRequest {
+	const CF: &'static str = "merge_requests";
+	const VERSION: u16 = 2;
+
+	fn migrate(version: u16, bytes: &[u8]) -> Result<Self> {
+		match version {
+			1 => {
+				let MergeRequestV1 {
+					owner,
+					repo_name,
+					number,
+					html_url,
+					requested_by,
+				} = bincode::deserialize(bytes).context(Bincode)?;
+				// the expiry of merge requests made before it was recorded counts from now
+				Ok(Self {
+					owner,
+					repo_name,
+					number,
+					html_url,
+					requested_by,
+					requested_at: Utc::now(),
+				})
+			}
+			_ => RecordVersion {
+				cf: Self::CF,
+				version,
+			}
+			.fail(),
+		}
+	}
+}
+
+/// Check the signature on a webhook payload against each of `secrets`, preferring the SHA-256
+/// signature of `x-hub-signature-256` over the SHA-1 one of `x-hub-signature`.
 fn verify(
-	secret: &[u8],
+	secrets: &[String],
 	msg: &[u8],
-	signature: &[u8],
-) -> Result<(), ring::error::Unspecified> {
-	let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret);
-	hmac::verify(&key, msg, signature)
+	headers: &hyper::HeaderMap,
+) -> Result<()> {
+	let (algorithm, header, prefix) =
+		if headers.contains_key("x-hub-signature-256") {
+			(hmac::HMAC_SHA256, "x-hub-signature-256", "sha256=")
+		} else {
+			(
+				hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+				"x-hub-signature",
+				"sha1=",
+			)
+		};
+	let sig = headers
+		.get(header)
+		.context(Message {
+			msg: format!("Missing {}", header),
+		})?
+		.to_str()
+		.ok()
+		.context(Message {
+			msg: format!("Error parsing {}", header),
+		})?
+		.replace(prefix, "");
+	let sig_bytes = base16::decode(sig.as_bytes()).ok().context(Message {
+		msg: format!("Error decoding {}", header),
+	})?;
+
+	secrets
+		.iter()
+		.map(|secret| hmac::Key::new(algorithm, secret.trim().as_bytes()))
+		.find(|key| hmac::verify(key, msg, &sig_bytes).is_ok())
+		.map(|_| ())
+		.context(Message {
+			msg: format!("Validation signature does not match"),
+		})
+}
+
+/// The `X-GitHub-Delivery` ID of a webhook, if any.
+fn delivery_id(req: &Request<Body>) -> Option<String> {
+	req.headers()
+		.get("x-github-delivery")
+		.and_then(|v| v.to_str().ok())
+		.map(|v| v.to_owned())
 }
 
 /// Receive a webhook and state object, acquire lock on state object.
 pub async fn webhook(
 	req: Request<Body>,
 	state: Arc<Mutex<AppState>>,
+	probes: Probes,
 ) -> Result<Response<Body>> {
-	if req.uri().path() == "/webhook" {
-		// lock here to prevent double merge requests being sent (which often happens when checks
-		// complete because we receive redundant status hooks).
-		let state = &*state.lock().await;
-		let sig = req
-			.headers()
-			.get("x-hub-signature")
-			.context(Message {
-				msg: format!("Missing x-hub-signature"),
-			})?
-			.to_str()
-			.ok()
-			.context(Message {
-				msg: format!("Error parsing x-hub-signature"),
-			})?
-			.to_string();
-		log::info!("Lock acquired for {:?}", sig);
-		if let Err(e) = webhook_inner(req, state).await {
-			handle_error(e, state).await;
+	let path = req
+		.uri()
+		.path()
+		.trim_end_matches('/')
+		.split('/')
+		.skip(1)
+		.map(|segment| segment.to_owned())
+		.collect::<Vec<String>>();
+	let path = path.iter().map(String::as_str).collect::<Vec<&str>>();
+	match path.as_slice() {
+		["webhook"] => {
+			// lock here to prevent double merge requests being sent (which often happens when
+			// checks complete because we receive redundant status hooks).
+			let state = &*state.lock().await;
+			let delivery = delivery_id(&req).unwrap_or_default();
+			if shutting_down() {
+				log::warn!(
+					"Refusing delivery {:?} while shutting down",
+					delivery
+				);
+				return Response::builder()
+					.status(StatusCode::SERVICE_UNAVAILABLE)
+					.body(Body::from("Shutting down."))
+					.ok()
+					.context(Message {
+						msg: format!("Error building response"),
+					});
+			}
+			log::info!("Lock acquired for delivery {:?}", delivery);
+			if let Err(e) = webhook_inner(req, state).await {
+				handle_error(e, state).await;
+			}
+			log::info!("Will release lock for delivery {:?}", delivery);
+			Response::builder()
+				.status(StatusCode::OK)
+				.body(Body::from(""))
+				.ok()
+				.context(Message {
+					msg: format!("Error building response"),
+				})
 		}
-		log::info!("Will release lock for {:?}", sig);
-		Response::builder()
+		// the process is up and serving requests
+		["health"] => Response::builder()
 			.status(StatusCode::OK)
-			.body(Body::from(""))
+			.body(Body::from("OK"))
 			.ok()
 			.context(Message {
 				msg: format!("Error building response"),
-			})
-	} else {
-		Response::builder()
-			.status(StatusCode::NOT_FOUND)
-			.body(Body::from("Not found."))
+			}),
+		["ready"] if shutting_down() => json_response(
+			StatusCode::SERVICE_UNAVAILABLE,
+			serde_json::json!({ "ready": false, "shutting_down": true }),
+		),
+		["ready"] => readiness(&probes).await,
+		["metrics"] => Response::builder()
+			.status(StatusCode::OK)
+			.header("content-type", "text/plain; version=0.0.4")
+			.body(Body::from(metrics::render()))
 			.ok()
 			.context(Message {
 				msg: format!("Error building response"),
-			})
+			}),
+		["status"] => json_response(
+			StatusCode::OK,
+			serde_json::json!({
+				"rate_limits": probes.github_bot.client.rate_limits(),
+				"deferred": probes.deferred.lock().len(),
+				"caches": probes
+					.github_bot
+					.cache_stats()
+					.iter()
+					.map(|stats| serde_json::json!({
+						"name": stats.name,
+						"entries": stats.entries,
+						"hits": stats.hits,
+						"misses": stats.misses,
+						"hit_rate": stats.hit_rate(),
+					}))
+					.collect::<Vec<serde_json::Value>>(),
+			}),
+		),
+		["admin"] | ["deliveries", ..] | ["merges", ..] | ["features", ..] => {
+			let state = &*state.lock().await;
+			if let Some(response) = admin_denied(&req, state) {
+				return response;
+			}
+			admin(&req, &path, state).await
+		}
+		_ => not_found(),
 	}
 }
 
-/// Parse webhook body and verify.
+/// Checks the database, that a GitHub access token can be had and that the Matrix homeserver is
+/// reachable.
+async fn readiness(probes: &Probes) -> Result<Response<Body>> {
+	let checks = vec![
+		("db", probes.db.check()),
+		("github", probes.github_bot.client.check().await),
+		("matrix", probes.matrix_bot.check().await),
+	];
+	let ready = checks.iter().all(|(_, result)| result.is_ok());
+	let checks = checks
+		.into_iter()
+		.map(|(name, result)| {
+			let status = match result {
+				Ok(()) => "ok".to_owned(),
+				Err(e) => {
+					log::warn!("Readiness check {} failed: {}", name, e);
+					e.to_string()
+				}
+			};
+			(name.to_owned(), serde_json::json!(status))
+		})
+		.collect::<serde_json::Map<String, serde_json::Value>>();
+	let body = serde_json::json!({ "ready": ready, "checks": checks });
+	json_response(
+		if ready {
+			StatusCode::OK
+		} else {
+			StatusCode::SERVICE_UNAVAILABLE
+		},
+		body,
+	)
+}
+
+/// Parse webhook body and verify, recording verified deliveries in the delivery log.
 pub async fn webhook_inner(
 	mut req: Request<Body>,
 	state: &AppState,
@@ -103,44 +326,117 @@ pub async fn webhook_inner(
 		})?);
 	}
 
-	let sig = req
-		.headers()
-		.get("x-hub-signature")
-		.context(Message {
-			msg: format!("Missing x-hub-signature"),
-		})?
-		.to_str()
-		.ok()
-		.context(Message {
-			msg: format!("Error parsing x-hub-signature"),
-		})?
-		.replace("sha1=", "");
-	let sig_bytes = base16::decode(sig.as_bytes()).ok().context(Message {
-		msg: format!("Error decoding x-hub-signature"),
-	})?;
+	if let Err(e) = verify(&state.webhook_secrets, &msg_bytes, req.headers()) {
+		// counted but not logged, so that unsigned requests cannot push genuine deliveries out
+		// of the log; the event header of an unsigned request is not to be trusted
+		log::warn!("Refusing webhook with an invalid signature: {}", e);
+		metrics::WEBHOOKS
+			.inc(&["unknown", delivery::Outcome::InvalidSignature.as_str()]);
+		return Err(e);
+	}
 
-	verify(
-		state.webhook_secret.trim().as_bytes(),
-		&msg_bytes,
-		&sig_bytes,
-	)
-	.ok()
-	.context(Message {
-		msg: format!("Validation signature does not match"),
-	})?;
+	let mut delivery = delivery::Delivery::new(req.headers(), &msg_bytes);
+	// Logged before handling, so that a delivery cut short by a shutdown can be found and
+	// replayed. `init_server` returns once its timeout passes even if this task is in the middle
+	// of eg. a `git push`, and then this entry is the only checkpoint left of the delivery.
+	delivery.outcome = delivery::Outcome::Interrupted;
+	if let Err(e) = delivery::log_delivery(&state.db, &delivery) {
+		log::error!("Error logging delivery {}: {}", delivery.id, e);
+	}
+	let (outcome, result) =
+		match handle_delivery(&delivery, &msg_bytes, state).await {
+			Ok(outcome) => (outcome, Ok(())),
+			Err(e) => (delivery::Outcome::Failed, Err(e)),
+		};
+	metrics::WEBHOOKS.inc(&[delivery.event.as_str(), outcome.as_str()]);
+	let outcome = delivery::DeliveryOutcome {
+		outcome,
+		error: result.as_ref().err().map(|e| e.to_string()),
+	};
+	if let Err(e) = delivery::log_outcome(&state.db, &delivery, &outcome) {
+		log::error!("Error logging delivery {}: {}", delivery.id, e);
+	}
+	result
+}
 
-	let payload = serde_json::from_slice::<Payload>(&msg_bytes).ok().context(
-		Message {
-			msg: format!("Error parsing request body"),
-		},
-	)?;
+/// Handle a verified delivery unless it was already handled.
+async fn handle_delivery(
+	delivery: &delivery::Delivery,
+	msg_bytes: &[u8],
+	state: &AppState,
+) -> Result<delivery::Outcome> {
+	// GitHub may deliver a webhook more than once
+	let delivery_id = Some(delivery.id.as_str()).filter(|id| !id.is_empty());
+	if let Some(delivery_id) = delivery_id {
+		if delivery::delivery_seen(&state.db, delivery_id)? {
+			log::info!("Ignoring duplicate delivery {}", delivery_id);
+			return Ok(delivery::Outcome::Duplicate);
+		}
+	}
+
+	if delivery.event.is_empty() {
+		return Message {
+			msg: format!("Missing x-github-event"),
+		}
+		.fail();
+	}
+	let result = handle_event(&delivery.event, msg_bytes, state).await;
+	// a delivery that failed is handled again if redelivered
+	if let (Some(delivery_id), Ok(_)) = (delivery_id, &result) {
+		if let Err(e) = delivery::record_delivery(&state.db, delivery_id) {
+			log::error!("Error recording delivery {}: {}", delivery_id, e);
+		}
+	}
+	handle_deferred(state).await;
+	result
+}
 
-	handle_payload(payload, state).await
+/// Parse the body of an `event` webhook and handle it.
+pub(crate) async fn handle_event(
+	event: &str,
+	msg_bytes: &[u8],
+	state: &AppState,
+) -> Result<delivery::Outcome> {
+	let payload = match Payload::from_event(event, msg_bytes) {
+		Ok(Some(payload)) => payload,
+		Ok(None) => {
+			log::info!("Ignoring unhandled {} event", event);
+			return Ok(delivery::Outcome::Ignored);
+		}
+		Err(e) => {
+			return Message {
+				msg: format!("Error parsing {} event: {}", event, e),
+			}
+			.fail()
+		}
+	};
+
+	// the installation is read separately as no variant of `Payload` needs it otherwise
+	let installation = serde_json::from_slice::<WebhookInstallation>(msg_bytes)
+		.ok()
+		.and_then(|webhook| webhook.installation)
+		.map(|installation| installation.id);
+	let github_bot = state.github_bot.for_installation(installation);
+
+	let outcome = handle_payload(payload, &github_bot, state).await?;
+	if outcome == delivery::Outcome::Ignored {
+		// the action is read separately as unknown actions are not kept when parsing
+		let action = serde_json::from_slice::<serde_json::Value>(msg_bytes)
+			.ok()
+			.and_then(|body| body.get("action")?.as_str().map(str::to_owned));
+		log::debug!("Ignoring {} event with action {:?}", event, action);
+	}
+	Ok(outcome)
 }
 
-/// Match different kinds of payload.
-async fn handle_payload(payload: Payload, state: &AppState) -> Result<()> {
-	match payload {
+/// Match different kinds of payload, returning `Outcome::Ignored` for those the bot does not act
+/// on, eg. a pull request being assigned.
+async fn handle_payload(
+	payload: Payload,
+	github_bot: &GithubBot,
+	state: &AppState,
+) -> Result<delivery::Outcome> {
+	let result = match payload {
 		Payload::IssueComment {
 			action: IssueCommentAction::Created,
 			comment:
@@ -158,32 +454,521 @@ async fn handle_payload(payload: Payload, state: &AppState) -> Result<()> {
 					..
 				},
 		} => {
-			handle_comment(body, login, number, html_url, repo_url, state).await
+			handle_comment(
+				body, login, number, html_url, repo_url, github_bot, state,
+			)
+			.await
 		}
 		Payload::CommitStatus {
 			sha, state: status, ..
-		} => handle_status(sha, status, state).await,
+		} => handle_status(sha, status, github_bot, state).await,
 		Payload::CheckRun {
 			check_run: CheckRun {
 				status, head_sha, ..
 			},
 			..
-		} => handle_check(status, head_sha, state).await,
-		_event => Ok(()),
+		} => handle_check(status, head_sha, github_bot, state).await,
+		Payload::PullRequest {
+			action: PullRequestAction::Closed,
+			pull_request,
+			repository,
+			..
+		} => {
+			handle_pull_request_closed(
+				pull_request,
+				repository,
+				github_bot,
+				state,
+			)
+			.await
+		}
+		Payload::PullRequest {
+			action: PullRequestAction::Opened,
+			pull_request,
+			repository,
+			..
+		}
+		| Payload::PullRequest {
+			action: PullRequestAction::Synchronized,
+			pull_request,
+			repository,
+			..
+		} => {
+			if github_bot.client.quota_low() {
+				log::info!(
+					"Deferring checks of {} until the rate limit resets",
+					pull_request.html_url
+				);
+				let mut deferred = state.deferred.lock();
+				deferred.retain(|(_, pr, _)| pr.id != pull_request.id);
+				deferred.push_back((
+					github_bot.client.installation(),
+					pull_request,
+					repository,
+				));
+				return Ok(delivery::Outcome::Handled);
+			}
+			handle_pull_request_changes(
+				pull_request,
+				repository,
+				github_bot,
+				state,
+			)
+			.await
+		}
+		Payload::PullRequest {
+			action: PullRequestAction::Labeled,
+			pull_request,
+			repository,
+			label,
+			..
+		} => {
+			handle_pull_request_labels(
+				pull_request,
+				repository,
+				label,
+				github_bot,
+				state,
+			)
+			.await
+		}
+		Payload::PullRequest {
+			action: PullRequestAction::Unlabeled,
+			pull_request,
+			repository,
+			..
+		} => {
+			handle_pull_request_labels(
+				pull_request.clone(),
+				repository,
+				None,
+				github_bot,
+				state,
+			)
+			.await?;
+			handle_pull_request_ready(pull_request, github_bot, state).await
+		}
+		Payload::PullRequest {
+			action: PullRequestAction::ReadyForReview,
+			pull_request,
+			..
+		} => handle_pull_request_ready(pull_request, github_bot, state).await,
+		Payload::PullRequest {
+			action: PullRequestAction::Edited,
+			pull_request,
+			..
+		} => handle_pull_request_ready(pull_request, github_bot, state).await,
+		Payload::Push {
+			commits,
+			repository,
+			..
+		} => {
+			let touches_files =
+				commits.iter().flat_map(|c| c.paths()).any(|path| {
+					path == REPO_CONFIG_FILE
+						|| process::PROCESS_FILES.iter().any(|(p, _)| path == p)
+				});
+			if touches_files {
+				log::info!(
+					"Process or config file changed in {}/{}; invalidating cache",
+					repository.owner.login,
+					repository.name
+				);
+				github_bot.invalidate_contents(
+					&repository.owner.login,
+					&repository.name,
+				);
+			}
+			Ok(())
+		}
+		Payload::Membership {
+			member,
+			team,
+			organization,
+		} => {
+			log::info!(
+				"Membership of {} in {}/{} changed; invalidating cache",
+				member.login,
+				organization.login,
+				team.slug
+			);
+			github_bot.invalidate_team(
+				&organization.login,
+				&team.slug,
+				team.id,
+			);
+			Ok(())
+		}
+		Payload::OrganizationMembership {
+			membership: Some(membership),
+			organization,
+		} => {
+			log::info!(
+				"Membership of {} in {} changed; invalidating cache",
+				membership.user.login,
+				organization.login
+			);
+			github_bot.invalidate_org_member(
+				&organization.login,
+				&membership.user.login,
+			);
+			Ok(())
+		}
+		Payload::Team { team, organization } => {
+			github_bot.invalidate_team(
+				&organization.login,
+				&team.slug,
+				team.id,
+			);
+			Ok(())
+		}
+		_ => return Ok(delivery::Outcome::Ignored),
+	};
+	result.map(|()| delivery::Outcome::Handled)
+}
+
+/// If a pull request is merged, backport it to every branch named in a `backport-to-<branch>`
+/// label.
+async fn handle_pull_request_closed(
+	pr: PullRequest,
+	repo: Repository,
+	github_bot: &GithubBot,
+	state: &AppState,
+) -> Result<()> {
+	if pr.merged != Some(true)
+		|| !features::enabled(
+			&state.db,
+			&repo.owner.login,
+			&repo.name,
+			Feature::Backport,
+		)? {
+		return Ok(());
+	}
+	for target in pr
+		.labels
+		.iter()
+		.filter_map(|label| backport_label_target(&label.name))
+	{
+		log::info!(
+			"Backport of {} to {} requested by label",
+			pr.html_url,
+			target
+		);
+		if let Err(e) = handle_backport_request(
+			github_bot,
+			&repo.owner.login,
+			&repo.name,
+			&pr,
+			target,
+		)
+		.await
+		{
+			handle_error(e, state).await;
+		}
+	}
+	Ok(())
+}
+
+/// Check the labels, Process file and config file of a pull request that was opened or pushed
+/// to.
+async fn handle_pull_request_changes(
+	pr: PullRequest,
+	repo: Repository,
+	github_bot: &GithubBot,
+	state: &AppState,
+) -> Result<()> {
+	let paths = github_bot
+		.pull_request_files(&repo.owner.login, &repo.name, pr.number)
+		.await?
+		.into_iter()
+		.map(|file| file.filename)
+		.collect::<Vec<String>>();
+	handle_pull_request_paths(&pr, &repo, &paths, github_bot, state).await?;
+	handle_pull_request_process_file(&pr, &repo, &paths, github_bot, state)
+		.await?;
+	handle_pull_request_repo_config(&pr, &repo, &paths, github_bot, state).await
+}
+
+/// Handle the pull requests deferred while the rate limit quota of their installation was low,
+/// as long as it lasts. Called after each webhook and by the reconcile loop, so that deferred
+/// pull requests are checked even if no more webhooks arrive.
+pub(crate) async fn handle_deferred(state: &AppState) {
+	let pending = std::mem::take(&mut *state.deferred.lock());
+	for (installation, pr, repo) in pending {
+		let github_bot = state.github_bot.for_installation(installation);
+		if github_bot.client.quota_low() {
+			state.deferred.lock().push_back((installation, pr, repo));
+		} else if let Err(e) =
+			handle_pull_request_changes(pr, repo, &github_bot, state).await
+		{
+			handle_error(e, state).await;
+		}
+	}
+}
+
+/// Apply the labels whose path rules match the files changed by a pull request.
+async fn handle_pull_request_paths(
+	pr: &PullRequest,
+	repo: &Repository,
+	paths: &[String],
+	github_bot: &GithubBot,
+	state: &AppState,
+) -> Result<()> {
+	let rules = &state.bot_config.label_rules;
+
+	if rules.paths.is_empty()
+		|| !features::enabled(
+			&state.db,
+			&repo.owner.login,
+			&repo.name,
+			Feature::PathLabels,
+		)? {
+		return Ok(());
+	}
+
+	let missing = rules
+		.labels_for_paths(paths)
+		.into_iter()
+		.filter(|label| !pr.labels.iter().any(|l| &l.name == label))
+		.collect::<Vec<String>>();
+	if !missing.is_empty() {
+		log::info!("Applying labels {:?} to {}", missing, pr.html_url);
+		github_bot
+			.add_labels(&repo.owner.login, &repo.name, pr.number, &missing)
+			.await?;
+	}
+	Ok(())
+}
+
+/// Validate the Process file of a pull request that changes it, and report the result as a check
+/// run on the head commit.
+async fn handle_pull_request_process_file(
+	pr: &PullRequest,
+	repo: &Repository,
+	paths: &[String],
+	github_bot: &GithubBot,
+	state: &AppState,
+) -> Result<()> {
+	let owner = &repo.owner.login;
+
+	let changed = paths.iter().any(|changed| {
+		process::PROCESS_FILES
+			.iter()
+			.any(|(path, _)| changed == path)
+	});
+	if !changed
+		|| !features::enabled(
+			&state.db,
+			owner,
+			&repo.name,
+			Feature::ProcessFileCheck,
+		)? {
+		return Ok(());
+	}
+
+	// a removed Process file is reported when merge approval is checked
+	let file = match process::process_file(
+		github_bot,
+		owner,
+		&repo.name,
+		&pr.head.sha,
+	)
+	.await?
+	{
+		Some(file) => file,
+		None => return Ok(()),
+	};
+	let mut problems = process::check_process_file(file.format, &file.contents);
+	let summary = if problems.is_empty() {
+		problems = process::check_process_against_repo(
+			github_bot,
+			owner,
+			&repo.name,
+			&file.process()?,
+		)
+		.await?;
+		if !problems.is_empty() {
+			MISMATCHED_PROCESS_FILE
+		} else if !file.ignored.is_empty() {
+			CONFLICTING_PROCESS_FILES
+		} else {
+			VALID_PROCESS_FILE
+		}
+	} else {
+		MALFORMED_PROCESS_FILE
+	};
+
+	log::info!(
+		"{} for {} has {} problem(s)",
+		file.path,
+		pr.html_url,
+		problems.len()
+	);
+	github_bot
+		.create_check_run(
+			owner,
+			&repo.name,
+			&pr.head.sha,
+			&github_bot::CheckRun {
+				name: PROCESS_FILE_CHECK,
+				success: problems.is_empty(),
+				summary: summary
+					.replace("{process_file}", file.path)
+					.replace("{repo_url}", &repo.html_url),
+				text: file
+					.conflicts()
+					.iter()
+					.chain(problems.iter())
+					.map(|d| format!("- {}", d))
+					.join("\n"),
+			},
+		)
+		.await
+}
+
+/// Validate the config file of a pull request that changes it, and report the result as a check
+/// run on the head commit.
+async fn handle_pull_request_repo_config(
+	pr: &PullRequest,
+	repo: &Repository,
+	paths: &[String],
+	github_bot: &GithubBot,
+	state: &AppState,
+) -> Result<()> {
+	let owner = &repo.owner.login;
+
+	if !paths.iter().any(|path| path == REPO_CONFIG_FILE)
+		|| !features::enabled(
+			&state.db,
+			owner,
+			&repo.name,
+			Feature::RepoConfigCheck,
+		)? {
+		return Ok(());
+	}
+
+	// a removed config file leaves the global settings in effect
+	let contents = match repo_config::repo_config_file(
+		github_bot,
+		owner,
+		&repo.name,
+		&pr.head.sha,
+	)
+	.await?
+	{
+		Some(contents) => contents,
+		None => return Ok(()),
+	};
+	let problems = match RepoConfig::parse(&contents) {
+		Ok(config) => {
+			repo_config::check_repo_config_against_org(
+				github_bot, owner, &config,
+			)
+			.await?
+		}
+		Err(problems) => problems,
+	};
+
+	log::info!(
+		"{} for {} has {} problem(s)",
+		REPO_CONFIG_FILE,
+		pr.html_url,
+		problems.len()
+	);
+	let summary = if problems.is_empty() {
+		VALID_REPO_CONFIG
+	} else {
+		INVALID_REPO_CONFIG
+	};
+	github_bot
+		.create_check_run(
+			owner,
+			&repo.name,
+			&pr.head.sha,
+			&github_bot::CheckRun {
+				name: REPO_CONFIG_CHECK,
+				success: problems.is_empty(),
+				summary: summary
+					.replace("{repo_config_file}", REPO_CONFIG_FILE)
+					.replace("{repo_url}", &repo.html_url),
+				text: problems.iter().map(|p| format!("- {}", p)).join("\n"),
+			},
+		)
+		.await
+}
+
+/// Remove labels that are mutually exclusive with a newly applied label, and cancel a pending
+/// merge if the labels no longer allow it.
+async fn handle_pull_request_labels(
+	pr: PullRequest,
+	repo: Repository,
+	added: Option<Label>,
+	github_bot: &GithubBot,
+	state: &AppState,
+) -> Result<()> {
+	let db = &state.db;
+	let rules = &state.bot_config.label_rules;
+	let owner = &repo.owner.login;
+
+	let mut labels = pr.labels.clone();
+	if let Some(added) = added {
+		let conflicting = rules.conflicting_labels(&added.name, &labels);
+		for label in conflicting.iter() {
+			log::info!(
+				"Removing {} from {} as it conflicts with {}",
+				label,
+				pr.html_url,
+				added.name
+			);
+			github_bot
+				.remove_label(owner, &repo.name, pr.number, label)
+				.await?;
+		}
+		labels.retain(|label| !conflicting.contains(&label.name));
+	}
+
+	if let Some(reason) = rules.merge_blocked(&labels) {
+		if db.get::<MergeRequest>(pr.head.sha.trim())?.is_some() {
+			log::info!("Cancelling merge of {}: {}", pr.html_url, reason);
+			db.delete::<MergeRequest>(pr.head.sha.trim())?;
+			let _ = github_bot
+				.create_issue_comment(
+					owner,
+					&repo.name,
+					pr.number,
+					&format!("Merge cancelled: {}.", reason),
+				)
+				.await
+				.map_err(|e| {
+					log::error!("Error posting comment: {}", e);
+				});
+		}
 	}
+	Ok(())
+}
+
+/// If a pull request may have become ready for review, attempt a merge that was waiting on it.
+async fn handle_pull_request_ready(
+	pr: PullRequest,
+	github_bot: &GithubBot,
+	state: &AppState,
+) -> Result<()> {
+	checks_and_status(github_bot, &state.bot_config, &pr.head.sha, &state.db)
+		.await
 }
 
 /// If a check completes, query if all statuses and checks are complete.
 async fn handle_check(
 	status: String,
 	commit_sha: String,
+	github_bot: &GithubBot,
 	state: &AppState,
 ) -> Result<()> {
 	let db = &state.db;
-	let github_bot = &state.github_bot;
+	let bot_config = &state.bot_config;
 
 	if status == "completed".to_string() {
-		checks_and_status(github_bot, &commit_sha, db).await?;
+		checks_and_status(github_bot, bot_config, &commit_sha, db).await?;
 	}
 
 	Ok(())
@@ -193,38 +978,51 @@ async fn handle_check(
 async fn handle_status(
 	commit_sha: String,
 	status: StatusState,
+	github_bot: &GithubBot,
 	state: &AppState,
 ) -> Result<()> {
 	let db = &state.db;
-	let github_bot = &state.github_bot;
+	let bot_config = &state.bot_config;
 
 	if status != StatusState::Pending {
-		checks_and_status(github_bot, &commit_sha, db).await?;
+		checks_and_status(github_bot, bot_config, &commit_sha, db).await?;
 	}
 	Ok(())
 }
 
-/// Check that no commit has been pushed since the merge request was received.  Query checks and
-/// statuses and if they are green, attempt merge.
-async fn checks_and_status(
+/// Check that no commit has been pushed since the merge request was received and that the pull
+/// request is ready for review.  Query checks and statuses and if they are green, attempt merge.
+pub(crate) async fn checks_and_status(
 	github_bot: &GithubBot,
+	bot_config: &BotConfig,
 	commit_sha: &str,
-	db: &DB,
+	db: &Storage,
 ) -> Result<()> {
-	if let Some(b) = db.get(commit_sha.trim().as_bytes()).context(Db)? {
-		let m = bincode::deserialize(&b).context(Bincode)?;
-		log::info!("Deserialized merge request: {:?}", m);
+	if let Some(m) = db.get::<MergeRequest>(commit_sha.trim())? {
+		log::info!("Read merge request: {:?}", m);
 		let MergeRequest {
 			owner,
 			repo_name,
 			number,
 			html_url,
-			requested_by: _,
+			..
 		} = m;
+		// kept until merging is turned on again or the request expires
+		if !features::enabled(db, &owner, &repo_name, Feature::Merge)? {
+			log::info!("Merging is turned off; leaving {} pending", html_url);
+			return Ok(());
+		}
 		let pr = github_bot.pull_request(&owner, &repo_name, number).await?;
 
 		// Head sha should not have changed since request was made.
 		if commit_sha == pr.head.sha {
+			if let Some(reason) =
+				bot_config.wip_rules.for_repo(&repo_name).reason(&pr)
+			{
+				log::info!("{} is not ready to merge: {}", html_url, reason);
+				return Ok(());
+			}
+
 			log::info!(
 				"Commit sha {} matches head of {}",
 				commit_sha,
@@ -259,19 +1057,29 @@ async fn checks_and_status(
 						merge(github_bot, &owner, &repo_name, &pr).await?;
 
 						// clean db
-						db.delete(pr.head.sha.trim().as_bytes())
-							.context(Db)
-							.map_err(|e| {
+						db.delete::<MergeRequest>(pr.head.sha.trim()).map_err(
+							|e| {
 								e.map_issue(Some((
 									owner.to_string(),
 									repo_name.to_string(),
 									pr.number,
 								)))
-							})?;
+							},
+						)?;
 
 						// update companion if necessary
-						update_companion(github_bot, &repo_name, &pr, db)
-							.await?;
+						let settings = repo_config::repo_settings(
+							github_bot,
+							bot_config,
+							&owner,
+							&repo_name,
+							pr.default_branch(),
+						)
+						.await?;
+						update_companion(
+							github_bot, &owner, &repo_name, &pr, &settings, db,
+						)
+						.await?;
 					}
 					CombinedStatus {
 						state: StatusState::Failure,
@@ -345,6 +1153,26 @@ async fn checks_and_status(
 	Ok(())
 }
 
+/// Returns the command a comment consists of, if any.
+fn command_name(body: &str) -> Option<&'static str> {
+	let body = body.to_lowercase();
+	let body = body.trim();
+	if backport_parse(body).is_some() {
+		return Some(BACKPORT_REQUEST);
+	}
+	[
+		AUTO_MERGE_REQUEST,
+		AUTO_MERGE_FORCE,
+		AUTO_MERGE_CANCEL,
+		COMPARE_RELEASE_REQUEST,
+		REBASE,
+		BURNIN_REQUEST,
+	]
+	.iter()
+	.find(|command| body == command.to_lowercase().trim())
+	.copied()
+}
+
 /// Parse bot commands in pull request comments.  Possible commands include:
 /// `bot merge`
 /// `bot merge force`
@@ -352,6 +1180,7 @@ async fn checks_and_status(
 /// `bot compare substrate`
 /// `bot rebase`
 /// `bot burnin`
+/// `bot backport <branch>`
 ///
 /// See also README.md.
 async fn handle_comment(
@@ -360,10 +1189,10 @@ async fn handle_comment(
 	number: i64,
 	html_url: String,
 	repo_url: String,
+	github_bot: &GithubBot,
 	state: &AppState,
 ) -> Result<()> {
 	let db = &state.db;
-	let github_bot = &state.github_bot;
 	let bot_config = &state.bot_config;
 
 	let owner = GithubBot::owner_from_html_url(&html_url).context(Message {
@@ -377,6 +1206,31 @@ async fn handle_comment(
 			},
 		)?;
 
+	// commands that are turned off are refused before fetching anything
+	if let Some(command) = command_name(&body) {
+		if let Some(feature) = Feature::of_command(command) {
+			if !features::enabled(db, owner, &repo_name, feature)? {
+				log::info!(
+					"Refusing {} on {} as {} is disabled",
+					command,
+					html_url,
+					feature
+				);
+				Err(Error::Message {
+					msg: format!(
+						"`{}` is disabled in this repository.",
+						command
+					),
+				}
+				.map_issue(Some((
+					owner.to_string(),
+					repo_name.to_string(),
+					number,
+				))))?;
+			}
+		}
+	}
+
 	// Fetch the pr to get all fields (eg. mergeable).
 	let pr = github_bot
 		.pull_request(owner, &repo_name, number)
@@ -392,6 +1246,22 @@ async fn handle_comment(
 	let auth =
 		GithubUserAuthenticator::new(&requested_by, owner, &repo_name, number);
 
+	let settings = repo_config::repo_settings(
+		github_bot,
+		bot_config,
+		owner,
+		&repo_name,
+		pr.default_branch(),
+	)
+	.await
+	.map_err(|e| {
+		e.map_issue(Some((owner.to_string(), repo_name.to_string(), number)))
+	})?;
+
+	if let Some(command) = command_name(&body) {
+		metrics::COMMANDS.inc(&[command]);
+	}
+
 	if body.to_lowercase().trim() == AUTO_MERGE_REQUEST.to_lowercase().trim() {
 		//
 		// MERGE
@@ -413,10 +1283,45 @@ async fn handle_comment(
 			&repo_name,
 			&pr,
 			&bot_config,
+			&settings,
 			&requested_by,
 		)
 		.await?;
 
+		//
+		// work in progress
+		//
+		if let Some(reason) =
+			bot_config.wip_rules.for_repo(&repo_name).reason(&pr)
+		{
+			log::info!("{} is not ready to merge: {}", html_url, reason);
+			create_merge_request(
+				owner,
+				&repo_name,
+				pr.number,
+				&pr.html_url,
+				&requested_by,
+				&pr.head.sha,
+				db,
+			)
+			.await?;
+			let _ = github_bot
+				.create_issue_comment(
+					owner,
+					&repo_name,
+					pr.number,
+					&format!(
+						"Waiting to merge until the PR is ready for review; {}.",
+						reason
+					),
+				)
+				.await
+				.map_err(|e| {
+					log::error!("Error posting comment: {}", e);
+				});
+			return Ok(());
+		}
+
 		//
 		// status and merge
 		//
@@ -431,7 +1336,8 @@ async fn handle_comment(
 			.await?;
 
 			merge(github_bot, owner, &repo_name, &pr).await?;
-			update_companion(github_bot, &repo_name, &pr, db).await?;
+			update_companion(github_bot, owner, &repo_name, &pr, &settings, db)
+				.await?;
 		} else {
 			wait_to_merge(
 				github_bot,
@@ -468,10 +1374,25 @@ async fn handle_comment(
 			&repo_name,
 			&pr,
 			&bot_config,
+			&settings,
 			&requested_by,
 		)
 		.await?;
 
+		//
+		// work in progress
+		//
+		if let Some(reason) =
+			bot_config.wip_rules.for_repo(&repo_name).reason(&pr)
+		{
+			log::info!("{} is not ready to merge: {}", html_url, reason);
+			Err(Error::WorkInProgress { reason }.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				number,
+			))))?;
+		}
+
 		//
 		// attempt merge without wait for checks
 		//
@@ -484,7 +1405,8 @@ async fn handle_comment(
 		)
 		.await?;
 		merge(github_bot, owner, &repo_name, &pr).await?;
-		update_companion(github_bot, &repo_name, &pr, db).await?;
+		update_companion(github_bot, owner, &repo_name, &pr, &settings, db)
+			.await?;
 	} else if body.to_lowercase().trim()
 		== AUTO_MERGE_CANCEL.to_lowercase().trim()
 	{
@@ -497,15 +1419,13 @@ async fn handle_comment(
 			requested_by
 		);
 		log::info!("Deleting merge request for {}", &html_url);
-		db.delete(pr.head.sha.trim().as_bytes())
-			.context(Db)
-			.map_err(|e| {
-				e.map_issue(Some((
-					owner.to_string(),
-					repo_name.to_string(),
-					number,
-				)))
-			})?;
+		db.delete::<MergeRequest>(pr.head.sha.trim()).map_err(|e| {
+			e.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				number,
+			)))
+		})?;
 		let _ = github_bot
 			.create_issue_comment(
 				owner,
@@ -517,7 +1437,7 @@ async fn handle_comment(
 			.map_err(|e| {
 				log::error!("Error posting comment: {}", e);
 			});
-	} else if repo_name == "polkadot"
+	} else if settings.compare
 		&& body.to_lowercase().trim()
 			== COMPARE_RELEASE_REQUEST.to_lowercase().trim()
 	{
@@ -635,20 +1555,157 @@ async fn handle_comment(
 				number,
 			))))?;
 		}
-	} else if body.to_lowercase().trim() == BURNIN_REQUEST.to_lowercase().trim()
+	} else if settings.burnin
+		&& body.to_lowercase().trim() == BURNIN_REQUEST.to_lowercase().trim()
 	{
 		auth.check_org_membership(github_bot).await?;
 
-		handle_burnin_request(
-			github_bot,
-			&state.gitlab_bot,
-			&state.matrix_bot,
+		if let Some(gitlab_bot) = &state.gitlab_bot {
+			handle_burnin_request(
+				github_bot,
+				gitlab_bot,
+				&state.matrix_bot,
+				owner,
+				&requested_by,
+				&repo_name,
+				&pr,
+				&settings,
+			)
+			.await;
+		} else {
+			Err(Error::Message {
+				msg: "Burn-in deployments are not configured; set the `GITLAB_*` settings to enable `bot burnin`.".to_owned(),
+			}
+			.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				number,
+			))))?;
+		}
+	} else if let Some(target) = backport_parse(&body) {
+		log::info!(
+			"Backport of {} to {} requested by {}",
+			html_url,
+			target,
+			requested_by
+		);
+
+		auth.check_org_membership(github_bot).await?;
+
+		handle_backport_request(github_bot, owner, &repo_name, &pr, target)
+			.await?;
+	}
+
+	Ok(())
+}
+
+/// Cherry-pick a merged pull request onto `target` and open a pull request for it, or post the
+/// conflicting paths if the cherry-pick fails.
+async fn handle_backport_request(
+	github_bot: &GithubBot,
+	owner: &str,
+	repo_name: &str,
+	pr: &PullRequest,
+	target: &str,
+) -> Result<()> {
+	let commit_sha = match (pr.merged, &pr.merge_commit_sha) {
+		(Some(true), Some(sha)) => sha,
+		_ => {
+			return Err(Error::Message {
+				msg: format!(
+					"Only merged pull requests can be backported; label this `{}{}` to backport it once merged.",
+					BACKPORT_LABEL_PREFIX, target
+				),
+			}
+			.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				pr.number,
+			))));
+		}
+	};
+
+	let _ = github_bot
+		.create_issue_comment(
 			owner,
-			&requested_by,
-			&repo_name,
-			&pr,
+			repo_name,
+			pr.number,
+			&format!("Backporting to `{}`.", target),
 		)
-		.await;
+		.await
+		.map_err(|e| {
+			log::error!("Error posting comment: {}", e);
+		});
+
+	match backport(
+		github_bot,
+		owner,
+		repo_name,
+		pr.number,
+		commit_sha,
+		&pr.head.sha,
+		target,
+	)
+	.await
+	.map_err(|e| {
+		e.map_issue(Some((owner.to_string(), repo_name.to_string(), pr.number)))
+	})? {
+		Backport::Pushed { branch } => {
+			let title = format!(
+				"[{}] {}",
+				target,
+				pr.title.as_deref().unwrap_or("Backport")
+			);
+			let body = format!("Backport of {} to `{}`.", pr.html_url, target);
+			let backport_pr = github_bot
+				.create_pull_request(
+					owner,
+					repo_name,
+					title.as_str(),
+					body.as_str(),
+					branch.as_str(),
+					target,
+				)
+				.await
+				.map_err(|e| {
+					e.map_issue(Some((
+						owner.to_string(),
+						repo_name.to_string(),
+						pr.number,
+					)))
+				})?;
+			log::info!("Opened backport {}", backport_pr.html_url);
+			let _ = github_bot
+				.create_issue_comment(
+					owner,
+					repo_name,
+					pr.number,
+					&format!("Opened backport {}.", backport_pr.html_url),
+				)
+				.await
+				.map_err(|e| {
+					log::error!("Error posting comment: {}", e);
+				});
+		}
+		Backport::Conflicts { paths } => {
+			log::info!(
+				"Backport of {} to {} has conflicts",
+				pr.html_url,
+				target
+			);
+			return Err(Error::Message {
+				msg: format!(
+					"Backport to `{}` failed due to conflicts in:\n\n{}",
+					target,
+					paths.iter().map(|path| format!("- `{}`", path)).join("\n")
+				),
+			}
+			.map_issue(Some((
+				owner.to_string(),
+				repo_name.to_string(),
+				pr.number,
+			))));
+		}
 	}
 
 	Ok(())
@@ -662,6 +1719,7 @@ async fn handle_burnin_request(
 	requested_by: &str,
 	repo_name: &str,
 	pr: &PullRequest,
+	settings: &RepoSettings,
 ) {
 	let make_job_link =
 		|url| format!("<a href=\"{}\">CI job for burn-in deployment</a>", url);
@@ -669,7 +1727,9 @@ async fn handle_burnin_request(
 	let unexpected_error_msg = "Starting CI job for burn-in deployment failed with an unexpected error; see logs.".to_string();
 	let mut matrix_msg: Option<String> = None;
 
-	let msg = match gitlab_bot.build_artifact(&pr.head.sha) {
+	let msg = match gitlab_bot
+		.build_artifact(&pr.head.sha, settings.gitlab_job_name.as_deref())
+	{
 		Ok(job) => {
 			let ci_job_link = make_job_link(job.url);
 
@@ -752,8 +1812,18 @@ async fn merge_allowed(
 	repo_name: &str,
 	pr: &PullRequest,
 	bot_config: &BotConfig,
+	settings: &RepoSettings,
 	requested_by: &str,
 ) -> Result<()> {
+	if let Some(reason) = bot_config.label_rules.merge_blocked(&pr.labels) {
+		log::info!("{} is blocked by labels: {}", pr.html_url, reason);
+		Err(Error::Labels { reason }.map_issue(Some((
+			owner.to_string(),
+			repo_name.to_string(),
+			pr.number,
+		))))?;
+	}
+
 	let mergeable = pr.mergeable.unwrap_or(false);
 	if !mergeable {
 		log::info!("{} is unmergeable", pr.html_url);
@@ -769,11 +1839,11 @@ async fn merge_allowed(
 		log::info!("{} is mergeable.", pr.html_url);
 
 		let team_leads = github_bot
-			.team(owner, "substrateteamleads")
+			.team(owner, &settings.team_leads_team)
 			.and_then(|team| github_bot.team_members(team.id))
 			.await
 			.unwrap_or_else(|e| {
-				log::error!("Error getting core devs: {}", e);
+				log::error!("Error getting team leads: {}", e);
 				vec![]
 			});
 
@@ -790,10 +1860,10 @@ async fn merge_allowed(
 				if pr.labels.iter().find(label_insubstantial).is_some() {
 					1
 				} else {
-					bot_config.min_reviewers
+					settings.min_reviewers
 				};
 			let core_devs = github_bot
-				.team(owner, "core-devs")
+				.team(owner, &settings.core_devs_team)
 				.and_then(|team| github_bot.team_members(team.id))
 				.await
 				.unwrap_or_else(|e| {
@@ -1007,7 +2077,7 @@ async fn create_merge_request(
 	html_url: &str,
 	requested_by: &str,
 	commit_sha: &str,
-	db: &DB,
+	db: &Storage,
 ) -> Result<()> {
 	let m = MergeRequest {
 		owner: owner.to_string(),
@@ -1015,21 +2085,16 @@ async fn create_merge_request(
 		number: number,
 		html_url: html_url.to_string(),
 		requested_by: requested_by.to_string(),
+		requested_at: Utc::now(),
 	};
-	log::info!("Serializing merge request: {:?}", m);
-	let bytes = bincode::serialize(&m).context(Bincode).map_err(|e| {
+	log::info!(
+		"Writing merge request to db (head sha: {}): {:?}",
+		commit_sha,
+		m
+	);
+	db.put(commit_sha.trim(), &m).map_err(|e| {
 		e.map_issue(Some((owner.to_string(), repo_name.to_string(), number)))
 	})?;
-	log::info!("Writing merge request to db (head sha: {})", commit_sha);
-	db.put(commit_sha.trim().as_bytes(), bytes)
-		.context(Db)
-		.map_err(|e| {
-			e.map_issue(Some((
-				owner.to_string(),
-				repo_name.to_string(),
-				number,
-			)))
-		})?;
 	Ok(())
 }
 
@@ -1043,7 +2108,7 @@ async fn wait_to_merge(
 	html_url: &str,
 	requested_by: &str,
 	commit_sha: &str,
-	db: &DB,
+	db: &Storage,
 ) -> Result<()> {
 	log::info!("{} checks incomplete.", html_url);
 	create_merge_request(
@@ -1096,6 +2161,7 @@ async fn merge(
 	repo_name: &str,
 	pr: &PullRequest,
 ) -> Result<()> {
+	metrics::MERGES_ATTEMPTED.inc(&[]);
 	github_bot
 		.merge_pull_request(owner, repo_name, pr.number, &pr.head.sha)
 		.await
@@ -1111,6 +2177,7 @@ async fn merge(
 			)))
 		})?;
 	log::info!("{} merged successfully.", pr.html_url);
+	metrics::MERGES_SUCCEEDED.inc(&[]);
 	Ok(())
 }
 
@@ -1195,19 +2262,23 @@ async fn performance_regression(
 	Ok(())
 }
 
-/// Check for a Polkadot companion and update it if found.
+/// Check for a companion and update it if found, in repositories that have companions.
 async fn update_companion(
 	github_bot: &GithubBot,
+	owner: &str,
 	repo_name: &str,
 	pr: &PullRequest,
-	db: &DB,
+	settings: &RepoSettings,
+	db: &Storage,
 ) -> Result<()> {
-	if repo_name == "substrate" {
+	if settings.companions
+		&& features::enabled(db, owner, repo_name, Feature::Companion)?
+	{
 		log::info!("Checking for companion.");
 		if let Some(body) = &pr.body {
 			// check for link in pr body
 			if let Some((comp_html_url, comp_owner, comp_repo, comp_number)) =
-				companion_parse(&body)
+				companion_parse(&body, &github_bot.base_html_url)
 			{
 				log::info!("Found companion {}", comp_html_url);
 				let comp_pr = github_bot
@@ -1330,10 +2401,15 @@ fn status_failure_allowed(ci: &str, context: &str) -> bool {
 	}
 }
 
-const TROUBLESHOOT_MSG: &str = "Merge can be attempted if:\n- The PR has approval from two core-devs (or one if the PR is labelled insubstantial).\n- The PR has approval from a member of `substrateteamleads`.\n- The PR is attached to a project column and has approval from the project owner.\n\nSee https://github.com/paritytech/parity-processbot#faq";
+const TROUBLESHOOT_MSG: &str = "Merge can be attempted if:\n- The PR has approval from the minimum number of core devs (or one if the PR is labelled insubstantial).\n- The PR has approval from a team lead.\n- The PR is attached to a project column and has approval from the project owner.\n\nSee https://github.com/paritytech/parity-processbot#faq";
 
-async fn handle_error(e: Error, state: &AppState) {
+pub(crate) async fn handle_error(e: Error, state: &AppState) {
 	log::error!("{}", e);
+	if let Error::WithIssue { source, .. } = &e {
+		if let Some(reason) = source.merge_failure_reason() {
+			metrics::MERGES_FAILED.inc(&[reason]);
+		}
+	}
 	match e {
 		Error::WithIssue {
 			source,
@@ -1346,8 +2422,10 @@ async fn handle_error(e: Error, state: &AppState) {
 				}
 				Error::Merge { source, commit_sha } => {
 					// clean db
-					let _ =
-						state.db.delete(commit_sha.as_bytes()).map_err(|e| {
+					let _ = state
+						.db
+						.delete::<MergeRequest>(&commit_sha)
+						.map_err(|e| {
 							log::error!(
 								"Error deleting merge request from db: {}",
 								e
@@ -1373,28 +2451,36 @@ async fn handle_error(e: Error, state: &AppState) {
 						body: serde_json::Value::Object(m),
 						..
 					} => format!(
-						"Error getting Process.json: `{}`",
+						"Error getting the Process file: `{}`",
 						m["message"]
 					),
 					Error::Http { source, .. } => format!(
-						"Network error getting Process.json:\n\n{}",
+						"Network error getting the Process file:\n\n{}",
 						source
 					),
 					e => format!(
-						"Unexpected error getting Process.json:\n\n{}",
+						"Unexpected error getting the Process file:\n\n{}",
 						e
 					),
 				},
 				Error::ProcessInfo {} => {
 					format!("Missing process info; check that the PR belongs to a project column.\n\n{}", TROUBLESHOOT_MSG)
 				}
+				Error::WorkInProgress { reason } => {
+					format!("Merge refused: {}.", reason)
+				}
+				Error::Labels { reason } => {
+					format!("Merge blocked: {}.", reason)
+				}
 				Error::Approval {} => {
 					format!("Missing approval from the project owner or a minimum of core developers.\n\n{}", TROUBLESHOOT_MSG)
 				}
 				Error::HeadChanged { commit_sha } => {
 					// clean db
-					let _ =
-						state.db.delete(commit_sha.as_bytes()).map_err(|e| {
+					let _ = state
+						.db
+						.delete::<MergeRequest>(&commit_sha)
+						.map_err(|e| {
 							log::error!(
 								"Error deleting merge request from db: {}",
 								e
@@ -1404,8 +2490,10 @@ async fn handle_error(e: Error, state: &AppState) {
 				}
 				Error::ChecksFailed { commit_sha } => {
 					// clean db
-					let _ =
-						state.db.delete(commit_sha.as_bytes()).map_err(|e| {
+					let _ = state
+						.db
+						.delete::<MergeRequest>(&commit_sha)
+						.map_err(|e| {
 							log::error!(
 								"Error deleting merge request from db: {}",
 								e
@@ -1423,8 +2511,16 @@ async fn handle_error(e: Error, state: &AppState) {
 				} => format!("Error: `{}`", m["message"]),
 				_ => "Unexpected error; see logs.".to_string(),
 			};
-			let _ = state
-				.github_bot
+			// errors are handled apart from the webhook or merge they came from, so the comment is
+			// made on behalf of the installation on the owner of the repository
+			let github_bot = match state.github_bot.for_account(&owner).await {
+				Ok(github_bot) => github_bot,
+				Err(e) => {
+					log::error!("Error posting comment: {}", e);
+					return;
+				}
+			};
+			let _ = github_bot
 				.create_issue_comment(&owner, &repo, number, &msg)
 				.await
 				.map_err(|e| {
@@ -1434,3 +2530,82 @@ async fn handle_error(e: Error, state: &AppState) {
 		_ => {}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_verify() {
+		let msg = br#"{"zen": "Keep it logically awesome."}"#;
+		let sign = |algorithm, secret: &str| {
+			base16::encode_lower(
+				hmac::sign(&hmac::Key::new(algorithm, secret.as_bytes()), msg)
+					.as_ref(),
+			)
+		};
+		let headers = |pairs: &[(&'static str, String)]| {
+			let mut headers = hyper::HeaderMap::new();
+			for (name, value) in pairs {
+				headers.insert(*name, value.parse().expect("header value"));
+			}
+			headers
+		};
+		let secrets = vec!["new".to_owned(), "old".to_owned()];
+
+		// either active secret is accepted
+		for secret in &["new", "old"] {
+			let sha256 = headers(&[(
+				"x-hub-signature-256",
+				format!("sha256={}", sign(hmac::HMAC_SHA256, secret)),
+			)]);
+			assert!(verify(&secrets, msg, &sha256).is_ok());
+		}
+		assert!(verify(
+			&secrets,
+			msg,
+			&headers(&[(
+				"x-hub-signature-256",
+				format!("sha256={}", sign(hmac::HMAC_SHA256, "other")),
+			)])
+		)
+		.is_err());
+
+		// SHA-1 is only used without a SHA-256 signature
+		let sha1 = format!(
+			"sha1={}",
+			sign(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, "new")
+		);
+		assert!(verify(
+			&secrets,
+			msg,
+			&headers(&[("x-hub-signature", sha1.clone())])
+		)
+		.is_ok());
+		assert!(verify(
+			&secrets,
+			msg,
+			&headers(&[
+				("x-hub-signature", sha1),
+				(
+					"x-hub-signature-256",
+					format!("sha256={}", sign(hmac::HMAC_SHA256, "other")),
+				),
+			])
+		)
+		.is_err());
+		assert!(verify(&secrets, msg, &headers(&[])).is_err());
+	}
+
+	#[test]
+	fn test_command_name() {
+		assert_eq!(command_name(" Bot merge\n"), Some(AUTO_MERGE_REQUEST));
+		assert_eq!(command_name("bot merge force"), Some(AUTO_MERGE_FORCE));
+		assert_eq!(
+			command_name("bot backport polkadot-v0.9.1"),
+			Some(BACKPORT_REQUEST)
+		);
+		assert_eq!(command_name("bot merge please"), None);
+		assert_eq!(command_name("LGTM"), None);
+	}
+}
@@ -0,0 +1,141 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use crate::{github::PullRequest, patterns::CaseInsensitivePattern};
+
+/// Rules deciding whether a pull request is still a work in progress and so must not be merged.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct WipRules {
+	/// Labels blocking merge; matched case-insensitively against any part of a label name.
+	#[serde(default = "default_labels")]
+	pub labels: Vec<String>,
+	/// Case-insensitive regexes matched against the pull request title.
+	#[serde(default = "default_title_patterns")]
+	pub title_patterns: Vec<CaseInsensitivePattern>,
+	/// Rules replacing these for a given repository name.
+	#[serde(default)]
+	pub repos: HashMap<String, WipRules>,
+}
+
+fn default_labels() -> Vec<String> {
+	vec!["do not merge".to_string()]
+}
+
+fn default_title_patterns() -> Vec<CaseInsensitivePattern> {
+	vec![
+		CaseInsensitivePattern::try_from(r"^\s*\[?wip\b".to_string())
+			.expect("default title pattern"),
+	]
+}
+
+impl Default for WipRules {
+	fn default() -> Self {
+		Self {
+			labels: default_labels(),
+			title_patterns: default_title_patterns(),
+			repos: HashMap::new(),
+		}
+	}
+}
+
+impl WipRules {
+	/// Returns the rules that apply to a repository.
+	pub fn for_repo(&self, repo_name: &str) -> &WipRules {
+		self.repos.get(repo_name).unwrap_or(self)
+	}
+
+	/// Returns the reason a pull request is considered a work in progress, if any.
+	pub fn reason(&self, pr: &PullRequest) -> Option<String> {
+		if pr.draft.unwrap_or(false) {
+			return Some("the PR is a draft".to_string());
+		}
+		if let Some(label) = pr.labels.iter().find(|label| {
+			self.labels.iter().any(|blocking| {
+				label.name.to_lowercase().contains(&blocking.to_lowercase())
+			})
+		}) {
+			return Some(format!("the PR is labelled `{}`", label.name));
+		}
+		let title = pr.title.as_deref().unwrap_or("");
+		if self
+			.title_patterns
+			.iter()
+			.any(|pattern| pattern.is_match(title))
+		{
+			return Some(
+				"the PR title marks it as a work in progress".to_string(),
+			);
+		}
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::github::Label;
+
+	fn pr(title: &str, labels: &[&str], draft: bool) -> PullRequest {
+		PullRequest {
+			title: Some(title.to_string()),
+			labels: labels
+				.iter()
+				.map(|name| Label {
+					name: name.to_string(),
+					..Label::default()
+				})
+				.collect(),
+			draft: Some(draft),
+			..PullRequest::default()
+		}
+	}
+
+	#[test]
+	fn test_wip_reason() {
+		let rules = WipRules::default();
+		assert_eq!(rules.reason(&pr("Add feature", &[], false)), None);
+		assert_eq!(
+			rules.reason(&pr("Add feature", &[], true)),
+			Some("the PR is a draft".to_string())
+		);
+		assert_eq!(
+			rules.reason(&pr("Add feature", &["A5-Do Not Merge"], false)),
+			Some("the PR is labelled `A5-Do Not Merge`".to_string())
+		);
+		assert!(rules.reason(&pr("[WIP] Add feature", &[], false)).is_some());
+		assert!(rules.reason(&pr("wip: add feature", &[], false)).is_some());
+		assert_eq!(rules.reason(&pr("Wipe storage", &[], false)), None);
+	}
+
+	#[test]
+	fn test_wip_rules_for_repo() {
+		let rules = serde_json::from_str::<WipRules>(
+			r#"{
+				"labels": ["A3-in_progress"],
+				"repos": { "polkadot": { "title_patterns": ["^DNM"] } }
+			}"#,
+		)
+		.expect("parse rules");
+		let substrate = rules.for_repo("substrate");
+		assert!(substrate
+			.reason(&pr("Fix", &["A3-in_progress"], false))
+			.is_some());
+		assert!(substrate.reason(&pr("[WIP] Fix", &[], false)).is_some());
+		let polkadot = rules.for_repo("polkadot");
+		assert!(polkadot.reason(&pr("DNM Fix", &[], false)).is_some());
+		assert!(polkadot.reason(&pr("[WIP] Fix", &[], false)).is_none());
+		assert!(polkadot
+			.reason(&pr("Fix", &["do not merge"], false))
+			.is_some());
+	}
+
+	#[test]
+	fn test_invalid_title_pattern() {
+		let e = serde_json::from_str::<WipRules>(
+			r#"{ "repos": { "polkadot": { "title_patterns": ["^(DNM"] } } }"#,
+		)
+		.expect_err("invalid regex");
+		assert!(e.to_string().starts_with("pattern '^(DNM' is invalid"));
+	}
+}